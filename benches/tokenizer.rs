@@ -0,0 +1,46 @@
+use std::io::BufReader;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use xml5::Tokenizer;
+
+/// Document of about 5 MB with elements, attributes, text, references,
+/// comments and CDATA, like a large data export.
+fn large_document() -> String {
+    let mut doc = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<catalog>\n");
+    for i in 0..20_000 {
+        doc.push_str(&format!(
+            "  <book id=\"bk{i}\" lang='en'>\n    <title>Book number {i}</title>\n    \
+             <author>Author &amp; Co.</author>\n    <price currency=\"EUR\">{i}.95</price>\n    \
+             <!-- entry {i} -->\n    <summary><![CDATA[Summary with <markup> of {i}]]></summary>\n    \
+             <available/>\n  </book>\n"
+        ));
+    }
+    doc.push_str("</catalog>\n");
+    doc
+}
+
+fn tokenize(c: &mut Criterion) {
+    let doc = large_document();
+    let mut group = c.benchmark_group("tokenize");
+    group.throughput(Throughput::Bytes(doc.len() as u64));
+
+    group.bench_function("from_str_reader", |b| {
+        b.iter(|| Tokenizer::new().from_str_reader(&doc).count())
+    });
+    group.bench_function("span_tokens", |b| {
+        b.iter(|| Tokenizer::new().span_tokens(doc.as_bytes()).count())
+    });
+    group.bench_function("from_buf_reader", |b| {
+        let mut buffer = Vec::new();
+        b.iter(|| {
+            let source = BufReader::new(doc.as_bytes());
+            Tokenizer::new()
+                .from_buf_reader(source, &mut buffer)
+                .count()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, tokenize);
+criterion_main!(benches);
@@ -0,0 +1,96 @@
+//! Access to the attributes of start and empty tags.
+//!
+//! Values are kept as the tag holds them. The tokenizer has expanded the
+//! references in them already, so a `&` left in a value stands for itself,
+//! and [`unescaped_value`](Attribute::unescaped_value) doesn't expand
+//! anything a second time.
+
+use std::borrow::Cow;
+use std::ops::Deref;
+
+use crate::encoding::Decoder;
+use crate::errors::{Xml5Error, Xml5Result};
+use crate::events::Attrs;
+
+/// Iterator over the attributes of a tag, in the order they were written.
+///
+/// Attributes with a name used before are returned like any other, unless
+/// checks are turned on with [`with_checks`](Self::with_checks).
+#[derive(Clone, Debug)]
+pub struct Attributes<'t, 'a> {
+    attrs: &'t Attrs<'a>,
+    pos: usize,
+    check_duplicates: bool,
+}
+
+impl<'t, 'a> Attributes<'t, 'a> {
+    pub(crate) fn new(attrs: &'t Attrs<'a>) -> Self {
+        Attributes {
+            attrs,
+            pos: 0,
+            check_duplicates: false,
+        }
+    }
+
+    /// Whether to report an attribute with a name used before as
+    /// [`Xml5Error::DuplicatedAttribute`].
+    pub fn with_checks(mut self, check_duplicates: bool) -> Self {
+        self.check_duplicates = check_duplicates;
+        self
+    }
+
+    /// First attribute named `name`.
+    pub fn get(&self, name: &[u8]) -> Option<Attribute<'t>> {
+        self.attrs
+            .iter()
+            .find(|(key, _)| key.deref() == name)
+            .map(|(key, value)| Attribute { key, value })
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.attrs.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.attrs.is_empty()
+    }
+}
+
+impl<'t, 'a> Iterator for Attributes<'t, 'a> {
+    type Item = Xml5Result<Attribute<'t>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (key, value) = self.attrs.get(self.pos)?;
+        let seen = &self.attrs[..self.pos];
+        self.pos += 1;
+        if self.check_duplicates && seen.iter().any(|(seen, _)| seen == key) {
+            return Some(Err(Xml5Error::DuplicatedAttribute(key.to_vec())));
+        }
+        Some(Ok(Attribute { key, value }))
+    }
+}
+
+/// Name and value of a single attribute.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct Attribute<'t> {
+    pub key: &'t [u8],
+    pub value: &'t [u8],
+}
+
+impl<'t> Attribute<'t> {
+    /// Value as UTF-8 text, with references expanded as the tokenizer read
+    /// them.
+    pub fn unescaped_value(&self) -> Xml5Result<Cow<'t, str>> {
+        self.decode_and_unescape_value(Decoder::utf8())
+    }
+
+    /// Value decoded with `decoder`, with references expanded as the
+    /// tokenizer read them. The value is only decoded if it's still in the
+    /// encoding of the input, i.e. unless the tokenizer that `decoder` comes
+    /// from transcoded it to UTF-8.
+    pub fn decode_and_unescape_value(&self, decoder: Decoder) -> Xml5Result<Cow<'t, str>> {
+        decoder.decode_token(self.value)
+    }
+}
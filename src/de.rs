@@ -0,0 +1,648 @@
+//! Serde deserializer, available with the `serialize` feature.
+//!
+//! The input is first built into a [`Document`] with the [`TreeBuilder`], so
+//! malformed input is recovered from exactly like the tree builder does, and
+//! the document is then mapped onto Rust types:
+//!
+//! - attributes are struct fields prefixed with `@`, e.g. `@id`
+//! - child elements are fields named after the element, and repeated
+//!   elements can be collected into a sequence, even when not adjacent
+//! - text of an element is the `$text` field, or the element's value when it
+//!   is deserialized as a primitive
+//! - enums are either unit variants named by the text, or a variant named
+//!   after the first child element
+//!
+//! The name of the root element is not checked. Text that isn't valid UTF-8
+//! fails to deserialize, unless [`Deserializer::lossy`] is turned on.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::io::BufRead;
+use std::str::FromStr;
+
+use serde::de::value::SeqDeserializer;
+use serde::de::{
+    self, DeserializeOwned, DeserializeSeed, EnumAccess, IntoDeserializer, MapAccess, SeqAccess,
+    VariantAccess, Visitor,
+};
+use serde::forward_to_deserialize_any;
+
+use crate::dom::{Document, NodeData, NodeId};
+use crate::errors::Xml5Error;
+use crate::{Token, Tokenizer, TreeBuilder};
+
+/// Field holding the text of an element.
+pub const TEXT_KEY: &str = "$text";
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeError {
+    /// Error raised by the type being deserialized
+    Custom(String),
+    /// Document has no root element
+    NoRootElement,
+    /// Text that couldn't be parsed into the expected type
+    InvalidValue(String),
+    /// Input that couldn't be read or decoded
+    Xml(Xml5Error),
+}
+
+impl fmt::Display for DeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeError::Custom(msg) => f.write_str(msg),
+            DeError::NoRootElement => f.write_str("document has no root element"),
+            DeError::InvalidValue(value) => write!(f, "invalid value `{}`", value),
+            DeError::Xml(err) => write!(f, "{:?}", err),
+        }
+    }
+}
+
+impl std::error::Error for DeError {}
+
+impl de::Error for DeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        DeError::Custom(msg.to_string())
+    }
+}
+
+/// Deserializes a value from an XML string.
+pub fn from_str<T: DeserializeOwned>(input: &str) -> Result<T, DeError> {
+    let document = TreeBuilder::new().build(Tokenizer::new().from_str_reader(input));
+    from_document(&document)
+}
+
+/// Deserializes a value from XML read out of `reader`.
+///
+/// The input is read like [`Tokenizer::from_byte_reader`] does, so with the
+/// `encoding` feature it can be in any supported encoding, and malformed
+/// sequences are replaced with U+FFFD as it's decoded. Without the feature,
+/// it has to be UTF-8. Input that can't be read or decoded fails with
+/// [`DeError::Xml`].
+pub fn from_reader<R: BufRead, T: DeserializeOwned>(reader: R) -> Result<T, DeError> {
+    let mut error = None;
+    let tokens = Tokenizer::new().from_byte_reader(reader).inspect(|token| {
+        if let Token::Error(err @ (Xml5Error::Io(_) | Xml5Error::NonDecodable(_)), _) = token {
+            error.get_or_insert_with(|| err.clone());
+        }
+    });
+    let document = TreeBuilder::new().build(tokens);
+    match error {
+        Some(err) => Err(DeError::Xml(err)),
+        None => from_document(&document),
+    }
+}
+
+/// Deserializes a value from the root element of an already built document.
+pub fn from_document<T: DeserializeOwned>(document: &Document) -> Result<T, DeError> {
+    T::deserialize(Deserializer::from_document(document)?)
+}
+
+/// Deserializer of a single element.
+pub struct Deserializer<'a> {
+    document: &'a Document,
+    element: NodeId,
+    /// whether bytes that aren't UTF-8 are replaced instead of failing
+    lossy: bool,
+}
+
+impl<'a> Deserializer<'a> {
+    pub fn from_document(document: &'a Document) -> Result<Self, DeError> {
+        let element = document.root_element().ok_or(DeError::NoRootElement)?;
+        Ok(Deserializer {
+            document,
+            element,
+            lossy: false,
+        })
+    }
+
+    /// Whether names and text that aren't valid UTF-8, e.g. of a document
+    /// read with [`Tokenizer::from_buf_reader`], have the invalid bytes
+    /// replaced with U+FFFD. Otherwise they fail with [`DeError::Xml`].
+    pub fn lossy(mut self, enabled: bool) -> Self {
+        self.lossy = enabled;
+        self
+    }
+
+    fn child(&self, element: NodeId) -> Self {
+        Deserializer {
+            document: self.document,
+            element,
+            lossy: self.lossy,
+        }
+    }
+
+    fn text(&self) -> Result<String, DeError> {
+        to_string(&self.document.text_content(self.element), self.lossy)
+    }
+
+    fn child_elements(&self) -> impl Iterator<Item = NodeId> + 'a {
+        let document = self.document;
+        document[self.element]
+            .children
+            .iter()
+            .copied()
+            .filter(move |&child| document[child].get_name().is_some())
+    }
+
+    fn has_structure(&self) -> bool {
+        let has_attrs = matches!(
+            &self.document[self.element].data,
+            NodeData::Element { attrs, .. } if !attrs.is_empty()
+        );
+        has_attrs || self.child_elements().next().is_some()
+    }
+}
+
+fn to_string(bytes: &[u8], lossy: bool) -> Result<String, DeError> {
+    if lossy {
+        Ok(String::from_utf8_lossy(bytes).into_owned())
+    } else {
+        String::from_utf8(bytes.to_vec()).map_err(|err| DeError::Xml(err.utf8_error().into()))
+    }
+}
+
+fn parse<T: FromStr>(text: &str) -> Result<T, DeError> {
+    text.trim()
+        .parse()
+        .map_err(|_| DeError::InvalidValue(text.to_string()))
+}
+
+fn parse_bool(text: &str) -> Result<bool, DeError> {
+    match text.trim() {
+        "true" | "1" => Ok(true),
+        "false" | "0" => Ok(false),
+        _ => Err(DeError::InvalidValue(text.to_string())),
+    }
+}
+
+/// Implements deserialization of primitives, parsed from `self.text()`.
+macro_rules! deserialize_primitives {
+    ($($method:ident => $visit:ident,)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+                visitor.$visit(parse(&self.text()?)?)
+            }
+        )*
+
+        fn deserialize_bool<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+            visitor.visit_bool(parse_bool(&self.text()?)?)
+        }
+
+        fn deserialize_char<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+            let text = self.text()?;
+            let mut chars = text.chars();
+            match (chars.next(), chars.next()) {
+                (Some(chr), None) => visitor.visit_char(chr),
+                _ => Err(DeError::InvalidValue(text)),
+            }
+        }
+
+        fn deserialize_str<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+            visitor.visit_string(self.text()?)
+        }
+
+        fn deserialize_string<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+            visitor.visit_string(self.text()?)
+        }
+
+        fn deserialize_bytes<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+            visitor.visit_byte_buf(self.text()?.into_bytes())
+        }
+
+        fn deserialize_byte_buf<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+            visitor.visit_byte_buf(self.text()?.into_bytes())
+        }
+
+        fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+            visitor.visit_some(self)
+        }
+
+        fn deserialize_unit<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+            visitor.visit_unit()
+        }
+
+        fn deserialize_unit_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            visitor: V,
+        ) -> Result<V::Value, DeError> {
+            visitor.visit_unit()
+        }
+
+        fn deserialize_newtype_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            visitor: V,
+        ) -> Result<V::Value, DeError> {
+            visitor.visit_newtype_struct(self)
+        }
+
+        fn deserialize_tuple<V: Visitor<'de>>(
+            self,
+            _len: usize,
+            visitor: V,
+        ) -> Result<V::Value, DeError> {
+            self.deserialize_seq(visitor)
+        }
+
+        fn deserialize_tuple_struct<V: Visitor<'de>>(
+            self,
+            _name: &'static str,
+            _len: usize,
+            visitor: V,
+        ) -> Result<V::Value, DeError> {
+            self.deserialize_seq(visitor)
+        }
+
+        fn deserialize_identifier<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+            visitor.visit_string(self.text()?)
+        }
+
+        fn deserialize_ignored_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+            visitor.visit_unit()
+        }
+    };
+}
+
+impl<'de, 'a> de::Deserializer<'de> for Deserializer<'a> {
+    type Error = DeError;
+
+    deserialize_primitives! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+    }
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        if self.has_structure() {
+            self.deserialize_map(visitor)
+        } else {
+            visitor.visit_string(self.text()?)
+        }
+    }
+
+    /// Child elements as items of the sequence.
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        let children: Vec<_> = self.child_elements().collect();
+        visitor.visit_seq(ElementSeq {
+            document: self.document,
+            elements: children.into_iter(),
+            lossy: self.lossy,
+        })
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        visitor.visit_map(ElementMap::new(self, &[])?)
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        visitor.visit_map(ElementMap::new(self, fields)?)
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        visitor.visit_enum(self)
+    }
+}
+
+impl<'de, 'a> EnumAccess<'de> for Deserializer<'a> {
+    type Error = DeError;
+    type Variant = ElementVariant<'a>;
+
+    fn variant_seed<S>(self, seed: S) -> Result<(S::Value, Self::Variant), DeError>
+    where
+        S: DeserializeSeed<'de>,
+    {
+        match self.child_elements().next() {
+            Some(child) => {
+                let name = self.document[child].get_name().unwrap_or_default();
+                let name = to_string(name, self.lossy)?;
+                let variant = seed.deserialize(name.into_deserializer())?;
+                Ok((variant, ElementVariant(Some(self.child(child)))))
+            }
+            None => {
+                let name = self.text()?.trim().to_string();
+                let variant = seed.deserialize(name.into_deserializer())?;
+                Ok((variant, ElementVariant(None)))
+            }
+        }
+    }
+}
+
+/// Content of an enum variant, `None` for unit variants given as text.
+pub struct ElementVariant<'a>(Option<Deserializer<'a>>);
+
+impl<'a> ElementVariant<'a> {
+    fn content(self) -> Result<Deserializer<'a>, DeError> {
+        self.0
+            .ok_or_else(|| DeError::Custom("expected element for enum variant".to_string()))
+    }
+}
+
+impl<'de, 'a> VariantAccess<'de> for ElementVariant<'a> {
+    type Error = DeError;
+
+    fn unit_variant(self) -> Result<(), DeError> {
+        Ok(())
+    }
+
+    fn newtype_variant_seed<S>(self, seed: S) -> Result<S::Value, DeError>
+    where
+        S: DeserializeSeed<'de>,
+    {
+        seed.deserialize(self.content()?)
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, _len: usize, visitor: V) -> Result<V::Value, DeError> {
+        de::Deserializer::deserialize_seq(self.content()?, visitor)
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        visitor.visit_map(ElementMap::new(self.content()?, fields)?)
+    }
+}
+
+struct ElementSeq<'a> {
+    document: &'a Document,
+    elements: std::vec::IntoIter<NodeId>,
+    lossy: bool,
+}
+
+impl<'de, 'a> SeqAccess<'de> for ElementSeq<'a> {
+    type Error = DeError;
+
+    fn next_element_seed<S>(&mut self, seed: S) -> Result<Option<S::Value>, DeError>
+    where
+        S: DeserializeSeed<'de>,
+    {
+        match self.elements.next() {
+            Some(element) => seed
+                .deserialize(Deserializer {
+                    document: self.document,
+                    element,
+                    lossy: self.lossy,
+                })
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.elements.len())
+    }
+}
+
+enum MapValue {
+    Text(String),
+    /// All child elements with the same name
+    Elements(Vec<NodeId>),
+}
+
+/// Attributes, child elements and text of an element as map entries.
+struct ElementMap<'a> {
+    document: &'a Document,
+    entries: std::vec::IntoIter<(String, MapValue)>,
+    value: Option<MapValue>,
+    lossy: bool,
+}
+
+impl<'a> ElementMap<'a> {
+    fn new(element: Deserializer<'a>, fields: &[&str]) -> Result<Self, DeError> {
+        let document = element.document;
+        let lossy = element.lossy;
+        let mut entries: Vec<(String, MapValue)> = vec![];
+        if let NodeData::Element { attrs, .. } = &document[element.element].data {
+            for (name, value) in attrs {
+                let key = format!("@{}", to_string(name, lossy)?);
+                entries.push((key, MapValue::Text(to_string(value, lossy)?)));
+            }
+        }
+
+        let mut text = vec![];
+        for &child in &document[element.element].children {
+            match &document[child].data {
+                NodeData::Element { name, .. } => {
+                    let name = to_string(name, lossy)?;
+                    let same_name = entries.iter_mut().find_map(|(key, value)| match value {
+                        MapValue::Elements(ids) if *key == name => Some(ids),
+                        _ => None,
+                    });
+                    match same_name {
+                        Some(ids) => ids.push(child),
+                        None => entries.push((name, MapValue::Elements(vec![child]))),
+                    }
+                }
+                NodeData::Text(data) | NodeData::CData(data) => text.extend_from_slice(data),
+                _ => (),
+            }
+        }
+        let has_text = !text.iter().all(u8::is_ascii_whitespace);
+        if has_text || fields.contains(&TEXT_KEY) {
+            entries.push((
+                TEXT_KEY.to_string(),
+                MapValue::Text(to_string(&text, lossy)?),
+            ));
+        }
+
+        Ok(ElementMap {
+            document,
+            entries: entries.into_iter(),
+            value: None,
+            lossy,
+        })
+    }
+}
+
+impl<'de, 'a> MapAccess<'de> for ElementMap<'a> {
+    type Error = DeError;
+
+    fn next_key_seed<K>(&mut self, seed: K) -> Result<Option<K::Value>, DeError>
+    where
+        K: DeserializeSeed<'de>,
+    {
+        match self.entries.next() {
+            Some((key, value)) => {
+                self.value = Some(value);
+                seed.deserialize(key.into_deserializer()).map(Some)
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn next_value_seed<V>(&mut self, seed: V) -> Result<V::Value, DeError>
+    where
+        V: DeserializeSeed<'de>,
+    {
+        match self.value.take() {
+            Some(MapValue::Text(text)) => seed.deserialize(TextDeserializer(Cow::Owned(text))),
+            Some(MapValue::Elements(elements)) => seed.deserialize(ElementsDeserializer {
+                document: self.document,
+                elements,
+                lossy: self.lossy,
+            }),
+            None => Err(DeError::Custom("value requested before key".to_string())),
+        }
+    }
+}
+
+/// Elements sharing a name. They are a sequence if one is expected,
+/// otherwise only the first one is used.
+struct ElementsDeserializer<'a> {
+    document: &'a Document,
+    elements: Vec<NodeId>,
+    lossy: bool,
+}
+
+impl<'a> ElementsDeserializer<'a> {
+    fn first(self) -> Deserializer<'a> {
+        Deserializer {
+            document: self.document,
+            element: self.elements[0],
+            lossy: self.lossy,
+        }
+    }
+}
+
+/// Forwards deserialization to the first of the elements.
+macro_rules! forward_to_first {
+    ($($method:ident($($arg:ident: $ty:ty),*),)*) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, DeError> {
+                de::Deserializer::$method(self.first(), $($arg,)* visitor)
+            }
+        )*
+    };
+}
+
+impl<'de, 'a> de::Deserializer<'de> for ElementsDeserializer<'a> {
+    type Error = DeError;
+
+    forward_to_first! {
+        deserialize_any(),
+        deserialize_bool(),
+        deserialize_i8(),
+        deserialize_i16(),
+        deserialize_i32(),
+        deserialize_i64(),
+        deserialize_u8(),
+        deserialize_u16(),
+        deserialize_u32(),
+        deserialize_u64(),
+        deserialize_f32(),
+        deserialize_f64(),
+        deserialize_char(),
+        deserialize_str(),
+        deserialize_string(),
+        deserialize_bytes(),
+        deserialize_byte_buf(),
+        deserialize_unit(),
+        deserialize_unit_struct(name: &'static str),
+        deserialize_newtype_struct(name: &'static str),
+        deserialize_tuple_struct(name: &'static str, len: usize),
+        deserialize_map(),
+        deserialize_struct(name: &'static str, fields: &'static [&'static str]),
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]),
+        deserialize_identifier(),
+        deserialize_ignored_any(),
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        visitor.visit_seq(ElementSeq {
+            document: self.document,
+            elements: self.elements.into_iter(),
+            lossy: self.lossy,
+        })
+    }
+
+    fn deserialize_tuple<V: Visitor<'de>>(
+        self,
+        _len: usize,
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        self.deserialize_seq(visitor)
+    }
+}
+
+/// Deserializer of attribute values and text. As a sequence, the text is
+/// split on whitespace.
+struct TextDeserializer<'a>(Cow<'a, str>);
+
+impl<'de, 'a> IntoDeserializer<'de, DeError> for TextDeserializer<'a> {
+    type Deserializer = Self;
+
+    fn into_deserializer(self) -> Self {
+        self
+    }
+}
+
+impl<'a> TextDeserializer<'a> {
+    fn text(&self) -> Result<String, DeError> {
+        Ok(self.0.to_string())
+    }
+}
+
+impl<'de, 'a> de::Deserializer<'de> for TextDeserializer<'a> {
+    type Error = DeError;
+
+    deserialize_primitives! {
+        deserialize_i8 => visit_i8,
+        deserialize_i16 => visit_i16,
+        deserialize_i32 => visit_i32,
+        deserialize_i64 => visit_i64,
+        deserialize_u8 => visit_u8,
+        deserialize_u16 => visit_u16,
+        deserialize_u32 => visit_u32,
+        deserialize_u64 => visit_u64,
+        deserialize_f32 => visit_f32,
+        deserialize_f64 => visit_f64,
+    }
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        visitor.visit_string(self.text()?)
+    }
+
+    fn deserialize_seq<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeError> {
+        let items = self
+            .0
+            .split_whitespace()
+            .map(|item| TextDeserializer(Cow::Borrowed(item)));
+        visitor.visit_seq(SeqDeserializer::new(items))
+    }
+
+    fn deserialize_enum<V: Visitor<'de>>(
+        self,
+        _name: &'static str,
+        _variants: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, DeError> {
+        visitor.visit_enum(self.0.trim().to_string().into_deserializer())
+    }
+
+    forward_to_deserialize_any! {
+        map struct
+    }
+}
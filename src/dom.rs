@@ -0,0 +1,231 @@
+//! Owned document tree, as built by [`TreeBuilder`](crate::TreeBuilder).
+//!
+//! Nodes are kept in an arena owned by the [`Document`] and refer to each
+//! other by [`NodeId`], so the tree can be walked both up and down.
+
+use std::ops::Index;
+
+use crate::errors::Xml5Error;
+use crate::events::Attrs;
+use crate::tree_builder::TreeSink;
+
+/// Handle of a node within its [`Document`].
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Hash)]
+pub struct NodeId(usize);
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum NodeData {
+    /// The document itself, parent of the root element.
+    Document,
+    Doctype {
+        name: Vec<u8>,
+        public_id: Option<Vec<u8>>,
+        system_id: Option<Vec<u8>>,
+    },
+    Element {
+        name: Vec<u8>,
+        attrs: Vec<(Vec<u8>, Vec<u8>)>,
+    },
+    Text(Vec<u8>),
+    CData(Vec<u8>),
+    Comment(Vec<u8>),
+    Pi {
+        target: Vec<u8>,
+        data: Vec<u8>,
+    },
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Node {
+    pub parent: Option<NodeId>,
+    pub children: Vec<NodeId>,
+    pub data: NodeData,
+}
+
+impl Node {
+    /// Name of the element, `None` for other nodes.
+    #[inline]
+    pub fn get_name(&self) -> Option<&[u8]> {
+        match &self.data {
+            NodeData::Element { name, .. } => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Value of the element's attribute called `name`.
+    pub fn get_attr(&self, name: &[u8]) -> Option<&[u8]> {
+        match &self.data {
+            NodeData::Element { attrs, .. } => attrs
+                .iter()
+                .find(|(attr, _)| attr == name)
+                .map(|(_, value)| &value[..]),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Document {
+    nodes: Vec<Node>,
+    errors: Vec<Xml5Error>,
+}
+
+impl Default for Document {
+    fn default() -> Self {
+        Document {
+            nodes: vec![Node {
+                parent: None,
+                children: vec![],
+                data: NodeData::Document,
+            }],
+            errors: vec![],
+        }
+    }
+}
+
+impl Document {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Id of the document node, which holds the doctype, root element and
+    /// any comments or processing instructions around it.
+    #[inline]
+    pub fn document(&self) -> NodeId {
+        NodeId(0)
+    }
+
+    /// First element child of the document.
+    pub fn root_element(&self) -> Option<NodeId> {
+        self[self.document()]
+            .children
+            .iter()
+            .copied()
+            .find(|&id| self[id].get_name().is_some())
+    }
+
+    /// Errors reported while the document was tokenized and built.
+    #[inline]
+    pub fn errors(&self) -> &[Xml5Error] {
+        &self.errors
+    }
+
+    /// Concatenated text and CDATA within the node.
+    pub fn text_content(&self, id: NodeId) -> Vec<u8> {
+        let mut text = vec![];
+        self.collect_text(id, &mut text);
+        text
+    }
+
+    fn collect_text(&self, id: NodeId, text: &mut Vec<u8>) {
+        match &self[id].data {
+            NodeData::Text(data) | NodeData::CData(data) => text.extend_from_slice(data),
+            _ => {
+                for &child in &self[id].children {
+                    self.collect_text(child, text);
+                }
+            }
+        }
+    }
+
+    fn create_node(&mut self, data: NodeData) -> NodeId {
+        self.nodes.push(Node {
+            parent: None,
+            children: vec![],
+            data,
+        });
+        NodeId(self.nodes.len() - 1)
+    }
+}
+
+impl TreeSink for Document {
+    type Handle = NodeId;
+    type Output = Document;
+
+    fn finish(self) -> Document {
+        self
+    }
+
+    fn parse_error(&mut self, err: Xml5Error) {
+        self.errors.push(err);
+    }
+
+    fn get_document(&self) -> NodeId {
+        self.document()
+    }
+
+    fn get_parent(&self, node: &NodeId) -> Option<NodeId> {
+        self[*node].parent
+    }
+
+    fn create_element(&mut self, name: &[u8]) -> NodeId {
+        self.create_node(NodeData::Element {
+            name: name.to_vec(),
+            attrs: vec![],
+        })
+    }
+
+    fn add_attrs(&mut self, element: &NodeId, new_attrs: Attrs<'_>) {
+        if let NodeData::Element { attrs, .. } = &mut self.nodes[element.0].data {
+            attrs.extend(
+                new_attrs
+                    .into_iter()
+                    .map(|(name, value)| (name.into_owned(), value.into_owned())),
+            );
+        }
+    }
+
+    fn create_comment(&mut self, text: &[u8]) -> NodeId {
+        self.create_node(NodeData::Comment(text.to_vec()))
+    }
+
+    fn create_cdata(&mut self, text: &[u8]) -> NodeId {
+        self.create_node(NodeData::CData(text.to_vec()))
+    }
+
+    fn create_pi(&mut self, target: &[u8], data: &[u8]) -> NodeId {
+        self.create_node(NodeData::Pi {
+            target: target.to_vec(),
+            data: data.to_vec(),
+        })
+    }
+
+    fn create_doctype(
+        &mut self,
+        name: &[u8],
+        public_id: Option<&[u8]>,
+        system_id: Option<&[u8]>,
+    ) -> NodeId {
+        self.create_node(NodeData::Doctype {
+            name: name.to_vec(),
+            public_id: public_id.map(<[u8]>::to_vec),
+            system_id: system_id.map(<[u8]>::to_vec),
+        })
+    }
+
+    fn append_child(&mut self, parent: &NodeId, child: &NodeId) {
+        self.nodes[child.0].parent = Some(*parent);
+        self.nodes[parent.0].children.push(*child);
+    }
+
+    /// Merges `text` into the last child of `parent`, if that is a text node.
+    fn append_text(&mut self, parent: &NodeId, text: &[u8]) {
+        if let Some(&last) = self.nodes[parent.0].children.last() {
+            if let NodeData::Text(data) = &mut self.nodes[last.0].data {
+                data.extend_from_slice(text);
+                return;
+            }
+        }
+        let child = self.create_node(NodeData::Text(text.to_vec()));
+        self.append_child(parent, &child);
+    }
+}
+
+impl Index<NodeId> for Document {
+    type Output = Node;
+
+    #[inline]
+    fn index(&self, id: NodeId) -> &Node {
+        &self.nodes[id.0]
+    }
+}
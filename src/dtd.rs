@@ -0,0 +1,620 @@
+//! Parser for declarations in the internal subset of a doctype, i.e. the
+//! part between `[` and `]` of `<!DOCTYPE root [ ... ]>`.
+//!
+//! The parser is lenient in the XML5 spirit: declarations it can't make
+//! sense of are skipped up to the next `>`, rather than failing the whole
+//! subset.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::errors::{Xml5Error, Xml5Result};
+use crate::tokenizer::char_ref::{expand_refs, predefined_entity};
+
+/// How deep entities are allowed to reference other entities.
+const MAX_ENTITY_DEPTH: usize = 16;
+/// Maximum length of an expanded entity, guards against "billion laughs".
+const MAX_ENTITY_LEN: usize = 1 << 20;
+/// Maximum number of bytes entities may expand to in a single document.
+const MAX_EXPANSION_BUDGET: usize = 1 << 23;
+
+/// Declarations found in the internal subset.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Dtd {
+    pub entities: Vec<EntityDecl>,
+    pub elements: Vec<ElementDecl>,
+    pub attlists: Vec<AttlistDecl>,
+    pub notations: Vec<NotationDecl>,
+}
+
+/// `<!ENTITY name "value">` or `<!ENTITY % name SYSTEM "uri">`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EntityDecl {
+    pub name: Vec<u8>,
+    /// `true` for parameter entities, declared with `%`
+    pub parameter: bool,
+    pub value: EntityValue,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EntityValue {
+    /// Replacement text, with character references already expanded
+    Internal(Vec<u8>),
+    /// Entity stored outside of the document, which is never loaded
+    External {
+        public_id: Option<Vec<u8>>,
+        system_id: Vec<u8>,
+        /// Notation of an unparsed entity, from `NDATA name`
+        notation: Option<Vec<u8>>,
+    },
+}
+
+/// `<!ELEMENT name content>`, with `content_spec` kept as is, e.g. `(a|b)*`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ElementDecl {
+    pub name: Vec<u8>,
+    pub content_spec: Vec<u8>,
+}
+
+/// `<!ATTLIST element attr CDATA #IMPLIED ...>`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AttlistDecl {
+    pub element: Vec<u8>,
+    pub attributes: Vec<AttDef>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AttDef {
+    pub name: Vec<u8>,
+    /// Type as written, e.g. `CDATA`, `ID` or `(yes|no)`
+    pub att_type: Vec<u8>,
+    pub default: AttDefault,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AttDefault {
+    Required,
+    Implied,
+    Fixed(Vec<u8>),
+    Value(Vec<u8>),
+}
+
+/// `<!NOTATION name PUBLIC "public" "system">`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NotationDecl {
+    pub name: Vec<u8>,
+    pub public_id: Option<Vec<u8>>,
+    pub system_id: Option<Vec<u8>>,
+}
+
+impl Dtd {
+    /// Parses the raw bytes of an internal subset, as returned by
+    /// `DocTypeText::get_internal_subset`.
+    pub fn parse(subset: &[u8]) -> Dtd {
+        let mut dtd = Dtd::default();
+        let mut parser = DtdParser {
+            input: subset,
+            pos: 0,
+        };
+        loop {
+            parser.skip_whitespace();
+            if parser.is_eof() {
+                break;
+            } else if parser.try_read(b"<!--") {
+                parser.skip_past(b"-->");
+            } else if parser.try_read(b"<?") {
+                parser.skip_past(b"?>");
+            } else if parser.try_read(b"<![") {
+                parser.skip_past(b"]]>");
+            } else if parser.try_read(b"<!ENTITY") {
+                if let Some(entity) = parser.entity_decl() {
+                    dtd.entities.push(entity);
+                }
+                parser.skip_decl();
+            } else if parser.try_read(b"<!ELEMENT") {
+                if let Some(element) = parser.element_decl() {
+                    dtd.elements.push(element);
+                }
+                parser.skip_decl();
+            } else if parser.try_read(b"<!ATTLIST") {
+                if let Some(attlist) = parser.attlist_decl() {
+                    dtd.attlists.push(attlist);
+                }
+                parser.skip_decl();
+            } else if parser.try_read(b"<!NOTATION") {
+                if let Some(notation) = parser.notation_decl() {
+                    dtd.notations.push(notation);
+                }
+                parser.skip_decl();
+            } else if parser.try_read(b"%") {
+                // parameter entity reference
+                parser.skip_past(b";");
+            } else {
+                parser.skip_decl();
+            }
+        }
+        dtd
+    }
+
+    /// Finds the general entity called `name`. If an entity is declared more
+    /// than once, the first declaration is binding.
+    pub fn get_entity(&self, name: &[u8]) -> Option<&EntityDecl> {
+        self.entities
+            .iter()
+            .find(|entity| !entity.parameter && entity.name == name)
+    }
+
+    /// Returns the replacement text of the internal general entity `name`,
+    /// with the references inside of it expanded.
+    ///
+    /// Returns `None` if there is no such entity, or it is an external one.
+    pub fn expand_entity(&self, name: &[u8]) -> Option<Xml5Result<Vec<u8>>> {
+        Expansions::default().expand(self, name, 0)
+    }
+}
+
+/// Expansions of the general entities of a single document.
+///
+/// Each entity is expanded once and then reused. Entities that fail to
+/// expand are tried again where they're referenced next, as they may only
+/// have been too deep where they were referenced first. Every byte that
+/// entities expand to, both in their replacement texts and in references to
+/// them, is counted against a budget for the whole document, including the
+/// bytes of failed expansions.
+#[derive(Default)]
+pub(crate) struct Expansions {
+    /// replacement texts by entity name
+    values: HashMap<Vec<u8>, Vec<u8>>,
+    /// entities being expanded, which can't reference themselves
+    expanding: HashSet<Vec<u8>>,
+    /// bytes expanded so far
+    spent: usize,
+}
+
+impl Expansions {
+    /// Expands a reference to the entity `name` in the document.
+    pub(crate) fn expand_ref(&mut self, dtd: &Dtd, name: &[u8]) -> Option<Xml5Result<Vec<u8>>> {
+        Some(
+            self.expand(dtd, name, 0)?
+                .and_then(|value| self.spend(value.len()).map(|()| value)),
+        )
+    }
+
+    fn expand(&mut self, dtd: &Dtd, name: &[u8], depth: usize) -> Option<Xml5Result<Vec<u8>>> {
+        if let Some(value) = self.values.get(name) {
+            return Some(Ok(value.clone()));
+        }
+        let text = match &dtd.get_entity(name)?.value {
+            EntityValue::Internal(text) => text,
+            EntityValue::External { .. } => return None,
+        };
+        if depth >= MAX_ENTITY_DEPTH
+            || self.spent > MAX_EXPANSION_BUDGET
+            || self.expanding.contains(name)
+        {
+            return Some(Err(Xml5Error::EntityExpansionLimit));
+        }
+
+        self.expanding.insert(name.to_vec());
+        let mut value = Vec::with_capacity(text.len());
+        let resolve = |name: &[u8]| match predefined_entity(name) {
+            Some(value) => Some(Ok(value.as_bytes().to_vec())),
+            None => self.expand(dtd, name, depth + 1),
+        };
+        let result = expand_refs(text, resolve, &mut value, MAX_ENTITY_LEN);
+        self.expanding.remove(name);
+        let result = match result {
+            Ok(()) => self.spend(value.len()).map(|()| value),
+            Err(err) => self.spend(value.len()).and(Err(err)),
+        };
+        if let Ok(value) = &result {
+            self.values.insert(name.to_vec(), value.clone());
+        }
+        Some(result)
+    }
+
+    fn spend(&mut self, len: usize) -> Xml5Result<()> {
+        self.spent += len;
+        if self.spent > MAX_EXPANSION_BUDGET {
+            Err(Xml5Error::EntityExpansionLimit)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+/// Public and system identifiers of an external ID.
+type ExternalId = (Option<Vec<u8>>, Option<Vec<u8>>);
+
+struct DtdParser<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> DtdParser<'a> {
+    #[inline]
+    fn is_eof(&self) -> bool {
+        self.pos >= self.input.len()
+    }
+
+    #[inline]
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn try_read(&mut self, needle: &[u8]) -> bool {
+        if self.input[self.pos..].starts_with(needle) {
+            self.pos += needle.len();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Skips whitespace, returning `true` if there was any.
+    fn skip_whitespace(&mut self) -> bool {
+        let start = self.pos;
+        while let Some(b' ' | b'\t' | b'\n' | b'\r') = self.peek() {
+            self.pos += 1;
+        }
+        self.pos != start
+    }
+
+    fn skip_past(&mut self, needle: &[u8]) {
+        match self.input[self.pos..]
+            .windows(needle.len())
+            .position(|window| window == needle)
+        {
+            Some(offset) => self.pos += offset + needle.len(),
+            None => self.pos = self.input.len(),
+        }
+    }
+
+    /// Skips the rest of a declaration, up to and including the `>`, minding
+    /// that quoted strings may contain `>` too.
+    fn skip_decl(&mut self) {
+        while let Some(chr) = self.peek() {
+            match chr {
+                b'>' => {
+                    self.pos += 1;
+                    return;
+                }
+                b'"' | b'\'' => {
+                    self.quoted();
+                }
+                _ => self.pos += 1,
+            }
+        }
+    }
+
+    fn name(&mut self) -> Option<Vec<u8>> {
+        let start = self.pos;
+        while let Some(chr) = self.peek() {
+            match chr {
+                b' ' | b'\t' | b'\n' | b'\r' | b'>' | b'"' | b'\'' | b'(' | b'%' => break,
+                _ => self.pos += 1,
+            }
+        }
+        if start == self.pos {
+            None
+        } else {
+            Some(self.input[start..self.pos].to_vec())
+        }
+    }
+
+    fn quoted(&mut self) -> Option<&'a [u8]> {
+        let quote = match self.peek() {
+            Some(quote @ b'"') | Some(quote @ b'\'') => quote,
+            _ => return None,
+        };
+        let start = self.pos + 1;
+        match self.input[start..].iter().position(|&chr| chr == quote) {
+            Some(len) => {
+                self.pos = start + len + 1;
+                Some(&self.input[start..start + len])
+            }
+            None => {
+                self.pos = self.input.len();
+                Some(&self.input[start..])
+            }
+        }
+    }
+
+    /// Reads `PUBLIC "public" "system"` or `SYSTEM "system"`, where the system
+    /// literal is optional only in notations.
+    fn external_id(&mut self) -> Option<ExternalId> {
+        if self.try_read(b"PUBLIC") {
+            self.skip_whitespace();
+            let public_id = self.quoted()?.to_vec();
+            self.skip_whitespace();
+            let system_id = self.quoted().map(<[u8]>::to_vec);
+            Some((Some(public_id), system_id))
+        } else if self.try_read(b"SYSTEM") {
+            self.skip_whitespace();
+            let system_id = self.quoted()?.to_vec();
+            Some((None, Some(system_id)))
+        } else {
+            None
+        }
+    }
+
+    fn entity_decl(&mut self) -> Option<EntityDecl> {
+        if !self.skip_whitespace() {
+            return None;
+        }
+        let parameter = self.try_read(b"%");
+        if parameter && !self.skip_whitespace() {
+            return None;
+        }
+        let name = self.name()?;
+        self.skip_whitespace();
+
+        let value = match self.quoted() {
+            Some(literal) => {
+                // Character references are expanded when the entity is
+                // declared, while entity references are kept until it's used
+                let mut text = Vec::with_capacity(literal.len());
+                let keep_named = |name: &[u8]| {
+                    let mut raw = Vec::with_capacity(name.len() + 2);
+                    raw.push(b'&');
+                    raw.extend_from_slice(name);
+                    raw.push(b';');
+                    Some(Ok(raw))
+                };
+                if expand_refs(literal, keep_named, &mut text, usize::MAX).is_err() {
+                    text = literal.to_vec();
+                }
+                EntityValue::Internal(text)
+            }
+            None => {
+                let (public_id, system_id) = self.external_id()?;
+                self.skip_whitespace();
+                let notation = if self.try_read(b"NDATA") {
+                    self.skip_whitespace();
+                    self.name()
+                } else {
+                    None
+                };
+                EntityValue::External {
+                    public_id,
+                    system_id: system_id?,
+                    notation,
+                }
+            }
+        };
+        Some(EntityDecl {
+            name,
+            parameter,
+            value,
+        })
+    }
+
+    fn element_decl(&mut self) -> Option<ElementDecl> {
+        self.skip_whitespace();
+        let name = self.name()?;
+        self.skip_whitespace();
+        let start = self.pos;
+        while let Some(chr) = self.peek() {
+            if chr == b'>' {
+                break;
+            }
+            self.pos += 1;
+        }
+        let content_spec = self.input[start..self.pos].trim_ascii_end().to_vec();
+        Some(ElementDecl { name, content_spec })
+    }
+
+    fn attlist_decl(&mut self) -> Option<AttlistDecl> {
+        self.skip_whitespace();
+        let element = self.name()?;
+        let mut attributes = vec![];
+        loop {
+            self.skip_whitespace();
+            if self.is_eof() || self.peek() == Some(b'>') {
+                break;
+            }
+            let name = self.name()?;
+            self.skip_whitespace();
+            let att_type = self.att_type()?;
+            self.skip_whitespace();
+            let default = if self.try_read(b"#REQUIRED") {
+                AttDefault::Required
+            } else if self.try_read(b"#IMPLIED") {
+                AttDefault::Implied
+            } else if self.try_read(b"#FIXED") {
+                self.skip_whitespace();
+                AttDefault::Fixed(self.quoted()?.to_vec())
+            } else {
+                AttDefault::Value(self.quoted()?.to_vec())
+            };
+            attributes.push(AttDef {
+                name,
+                att_type,
+                default,
+            });
+        }
+        Some(AttlistDecl {
+            element,
+            attributes,
+        })
+    }
+
+    fn att_type(&mut self) -> Option<Vec<u8>> {
+        let start = self.pos;
+        if self.try_read(b"NOTATION") {
+            self.skip_whitespace();
+        } else if self.peek() != Some(b'(') {
+            return self.name();
+        }
+        if self.peek() != Some(b'(') {
+            return None;
+        }
+        self.skip_past(b")");
+        Some(self.input[start..self.pos].to_vec())
+    }
+
+    fn notation_decl(&mut self) -> Option<NotationDecl> {
+        self.skip_whitespace();
+        let name = self.name()?;
+        self.skip_whitespace();
+        let (public_id, system_id) = self.external_id()?;
+        Some(NotationDecl {
+            name,
+            public_id,
+            system_id,
+        })
+    }
+}
+
+#[test]
+fn test_parse_declarations() {
+    let dtd = Dtd::parse(
+        br#"
+        <!-- comment with <!ENTITY fake "x"> inside -->
+        <!ENTITY copy "&#169; &author;">
+        <!ENTITY author 'J. "Doe"'>
+        <!ENTITY % common SYSTEM "common.ent">
+        %common;
+        <!ENTITY logo SYSTEM "logo.png" NDATA png>
+        <!ELEMENT note (to, from)* >
+        <!ATTLIST note id ID #REQUIRED
+                       kind (a|b) "a"
+                       lang CDATA #FIXED 'en'>
+        <!NOTATION png PUBLIC "image/png">
+        <!BOGUS ">" >
+        "#,
+    );
+    assert_eq!(
+        vec![
+            EntityDecl {
+                name: b"copy".to_vec(),
+                parameter: false,
+                value: EntityValue::Internal("© &author;".as_bytes().to_vec()),
+            },
+            EntityDecl {
+                name: b"author".to_vec(),
+                parameter: false,
+                value: EntityValue::Internal(b"J. \"Doe\"".to_vec()),
+            },
+            EntityDecl {
+                name: b"common".to_vec(),
+                parameter: true,
+                value: EntityValue::External {
+                    public_id: None,
+                    system_id: b"common.ent".to_vec(),
+                    notation: None,
+                },
+            },
+            EntityDecl {
+                name: b"logo".to_vec(),
+                parameter: false,
+                value: EntityValue::External {
+                    public_id: None,
+                    system_id: b"logo.png".to_vec(),
+                    notation: Some(b"png".to_vec()),
+                },
+            },
+        ],
+        dtd.entities
+    );
+    assert_eq!(
+        vec![ElementDecl {
+            name: b"note".to_vec(),
+            content_spec: b"(to, from)*".to_vec(),
+        }],
+        dtd.elements
+    );
+    assert_eq!(
+        vec![AttlistDecl {
+            element: b"note".to_vec(),
+            attributes: vec![
+                AttDef {
+                    name: b"id".to_vec(),
+                    att_type: b"ID".to_vec(),
+                    default: AttDefault::Required,
+                },
+                AttDef {
+                    name: b"kind".to_vec(),
+                    att_type: b"(a|b)".to_vec(),
+                    default: AttDefault::Value(b"a".to_vec()),
+                },
+                AttDef {
+                    name: b"lang".to_vec(),
+                    att_type: b"CDATA".to_vec(),
+                    default: AttDefault::Fixed(b"en".to_vec()),
+                },
+            ],
+        }],
+        dtd.attlists
+    );
+    assert_eq!(
+        vec![NotationDecl {
+            name: b"png".to_vec(),
+            public_id: Some(b"image/png".to_vec()),
+            system_id: None,
+        }],
+        dtd.notations
+    );
+}
+
+#[test]
+fn test_expand_entity() {
+    let dtd = Dtd::parse(
+        br#"<!ENTITY a "&b;&b;"> <!ENTITY b "&lt;b&gt;"> <!ENTITY loop "&loop;">
+            <!ENTITY ext SYSTEM "ext.xml">"#,
+    );
+    assert_eq!(Some(Ok(b"<b><b>".to_vec())), dtd.expand_entity(b"a"));
+    assert_eq!(
+        Some(Err(Xml5Error::EntityExpansionLimit)),
+        dtd.expand_entity(b"loop")
+    );
+    assert_eq!(None, dtd.expand_entity(b"ext"));
+    assert_eq!(None, dtd.expand_entity(b"missing"));
+}
+
+#[test]
+fn test_expansion_limit() {
+    // `lol1` expands to about 1 MB, and `lol2` to 200 times that
+    let mut subset = format!("<!ENTITY lol0 \"{}\">", "x".repeat(1000));
+    subset.push_str(&format!("<!ENTITY lol1 \"{}\">", "&lol0;".repeat(1000)));
+    subset.push_str(&format!("<!ENTITY lol2 \"{}\">", "&lol1;".repeat(200)));
+    let dtd = Dtd::parse(subset.as_bytes());
+
+    assert_eq!(
+        1_000_000,
+        dtd.expand_entity(b"lol1").unwrap().unwrap().len()
+    );
+    assert_eq!(
+        Some(Err(Xml5Error::EntityExpansionLimit)),
+        dtd.expand_entity(b"lol2")
+    );
+
+    // every reference counts, on top of expanding `lol1` once
+    let mut expansions = Expansions::default();
+    let expanded = (0..50)
+        .map(|_| expansions.expand_ref(&dtd, b"lol1").unwrap())
+        .take_while(Result::is_ok)
+        .count();
+    assert_eq!(7, expanded);
+}
+
+#[test]
+fn test_expansion_depth() {
+    // `a` is too deep when reached from `d0`, but not on its own
+    let mut subset = String::from("<!ENTITY a \"x\">");
+    for i in 0..MAX_ENTITY_DEPTH {
+        let next = match i + 1 {
+            MAX_ENTITY_DEPTH => "a".to_string(),
+            next => format!("d{}", next),
+        };
+        subset.push_str(&format!("<!ENTITY d{} \"&{};\">", i, next));
+    }
+    let dtd = Dtd::parse(subset.as_bytes());
+
+    let mut expansions = Expansions::default();
+    assert_eq!(
+        Some(Err(Xml5Error::EntityExpansionLimit)),
+        expansions.expand_ref(&dtd, b"d0")
+    );
+    assert_eq!(Some(Ok(b"x".to_vec())), expansions.expand_ref(&dtd, b"a"));
+    assert_eq!(Some(Ok(b"x".to_vec())), expansions.expand_ref(&dtd, b"d1"));
+}
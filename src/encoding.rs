@@ -30,8 +30,6 @@ use std::borrow::Cow;
 use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
 
 use crate::errors::Xml5Error;
-#[cfg(feature = "encoding")]
-use crate::Error;
 
 pub type Result<T> = std::result::Result<T, Xml5Error>;
 
@@ -52,6 +50,28 @@ pub type Result<T> = std::result::Result<T, Xml5Error>;
 pub struct Decoder {
     #[cfg(feature = "encoding")]
     pub(crate) encoding: &'static Encoding,
+    pub(crate) source: EncodingSource,
+    /// whether the tokens were transcoded to UTF-8 from `encoding`
+    #[cfg(feature = "encoding")]
+    pub(crate) transcoded: bool,
+}
+
+/// Where the encoding of a [`Decoder`] was found.
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub enum EncodingSource {
+    /// Nothing specified an encoding, so UTF-8 is assumed
+    #[default]
+    Default,
+    /// Byte order mark, or the byte order of the first bytes
+    Bom,
+    /// `encoding` of the XML declaration
+    Declaration,
+}
+
+impl Default for Decoder {
+    fn default() -> Self {
+        Decoder::utf8()
+    }
 }
 
 impl Decoder {
@@ -59,12 +79,53 @@ impl Decoder {
         Decoder {
             #[cfg(feature = "encoding")]
             encoding: UTF_8,
+            source: EncodingSource::Default,
+            #[cfg(feature = "encoding")]
+            transcoded: false,
+        }
+    }
+
+    /// Where the encoding was found.
+    #[inline]
+    pub fn source(&self) -> EncodingSource {
+        self.source
+    }
+
+    #[inline]
+    pub(crate) fn with_source(mut self, source: EncodingSource) -> Self {
+        self.source = source;
+        self
+    }
+
+    /// Decoder refined by the `encoding` of the XML declaration, unless one
+    /// was declared already. As when transcoding, only encodings that are
+    /// ASCII compatible are taken, and without feature `encoding` the
+    /// declaration is ignored.
+    pub(crate) fn declared(self, label: &[u8]) -> Self {
+        #[cfg(feature = "encoding")]
+        if self.source != EncodingSource::Declaration {
+            if let Some(encoding) = Encoding::for_label(label) {
+                if encoding.is_ascii_compatible() {
+                    return Decoder {
+                        encoding,
+                        source: EncodingSource::Declaration,
+                        transcoded: self.transcoded,
+                    };
+                }
+            }
         }
+        let _ = label;
+        self
     }
 
-    #[cfg(all(test, feature = "encoding", feature = "serialize"))]
-    pub(crate) fn utf16() -> Self {
-        Decoder { encoding: UTF_16LE }
+    /// Decodes bytes of a token, which are UTF-8 already if the tokenizer
+    /// transcoded its input, and in the source encoding otherwise.
+    pub(crate) fn decode_token<'b>(&self, bytes: &'b [u8]) -> Result<Cow<'b, str>> {
+        #[cfg(feature = "encoding")]
+        if self.transcoded {
+            return Ok(Cow::Borrowed(std::str::from_utf8(bytes)?));
+        }
+        self.decode(bytes)
     }
 }
 
@@ -160,9 +221,9 @@ pub fn decode_with_bom_removal<'b>(bytes: &'b [u8]) -> Result<Cow<'b, str>> {
 fn split_at_bom<'b>(bytes: &'b [u8], encoding: &'static Encoding) -> (&'b [u8], &'b [u8]) {
     if encoding == UTF_8 && bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
         bytes.split_at(3)
-    } else if encoding == UTF_16LE && bytes.starts_with(&[0xFF, 0xFE]) {
-        bytes.split_at(2)
-    } else if encoding == UTF_16BE && bytes.starts_with(&[0xFE, 0xFF]) {
+    } else if (encoding == UTF_16LE && bytes.starts_with(&[0xFF, 0xFE]))
+        || (encoding == UTF_16BE && bytes.starts_with(&[0xFE, 0xFF]))
+    {
         bytes.split_at(2)
     } else {
         (&[], bytes)
@@ -10,6 +10,7 @@ pub enum Xml5Error {
     AbruptClosingEmptyComment,
     AbruptClosingXmlDeclaration,
     AbruptEndDoctypeIdentifier,
+    CharRefOutOfRange(u32),
     ColonBeforeAttrName,
     EofInCdata,
     EofInComment,
@@ -26,9 +27,28 @@ pub enum Xml5Error {
     MissingDoctypeName,
     MissingDoctypeIdentifier,
     InvalidXmlDeclaration,
+    /// Pseudo-attribute of the XML declaration other than `version`,
+    /// `encoding` and `standalone`
+    UnknownXmlDeclarationAttribute,
+    UnknownNamedCharRef,
+    UnterminatedCharRef,
+    EntityExpansionLimit,
     UnexpectedSymbolOrEof(Option<u8>),
     UnexpectedSymbol(char),
     UnexpectedEof,
+    /// End tag that doesn't match any open element
+    UnmatchedEndTag,
+    /// End tag that implicitly closes other elements
+    MisnestedEndTag,
+    UnexpectedDoctype,
+    /// Text or element after the root element was closed
+    ContentOutsideRoot,
+    /// Prefix of a name that isn't bound to any namespace
+    UnboundPrefix(Vec<u8>),
+    /// Attribute with the same name as an earlier one of the tag
+    DuplicatedAttribute(Vec<u8>),
+    /// Processing instruction with `?>` in its data can't be written
+    InvalidPiData,
     Utf8(Utf8Error),
     Io(String),
     /// Input decoding error. If `encoding` feature is disabled, contains `None`,
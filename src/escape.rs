@@ -0,0 +1,124 @@
+//! Escaping of text for XML, and expanding of references in it.
+//!
+//! The predefined entities and references are handled the same way as the
+//! tokenizer does it, so unescaping gives what the tokenizer would read.
+//! Input without anything to change is returned borrowed.
+
+use std::borrow::Cow;
+use std::str::from_utf8;
+
+use crate::errors::{Xml5Error, Xml5Result};
+use crate::tokenizer::char_ref::{expand_refs, predefined_entity, PREDEFINED_ENTITIES};
+
+/// Escapes `<`, `>`, `&`, `'` and `"`, so the text can be used anywhere,
+/// including attribute values in either quotes.
+pub fn escape(raw: &str) -> Cow<'_, str> {
+    escape_str(raw, |chr| matches!(chr, b'<' | b'>' | b'&' | b'\'' | b'"'))
+}
+
+/// Escapes `<`, `>` and `&` only, which is enough for text content.
+pub fn partial_escape(raw: &str) -> Cow<'_, str> {
+    escape_str(raw, |chr| matches!(chr, b'<' | b'>' | b'&'))
+}
+
+/// Expands character references and the predefined entities. Fails on
+/// unknown entities and malformed references.
+pub fn unescape(raw: &str) -> Xml5Result<Cow<'_, str>> {
+    unescape_with(raw, |_| None)
+}
+
+/// Expands character references and entities, where `resolve` gives the
+/// value of entities other than the predefined ones. Values are inserted as
+/// they are, without expanding references in them.
+pub fn unescape_with<'i, 'e, F>(raw: &'i str, resolve: F) -> Xml5Result<Cow<'i, str>>
+where
+    F: Fn(&str) -> Option<&'e str>,
+{
+    if !raw.contains('&') {
+        return Ok(Cow::Borrowed(raw));
+    }
+    let resolve = |name: &[u8]| {
+        let value = match predefined_entity(name) {
+            Some(value) => value,
+            None => resolve(from_utf8(name).ok()?)?,
+        };
+        Some(Ok(value.as_bytes().to_vec()))
+    };
+    let mut out = Vec::with_capacity(raw.len());
+    expand_refs(raw.as_bytes(), resolve, &mut out, usize::MAX)?;
+    String::from_utf8(out)
+        .map(Cow::Owned)
+        .map_err(|err| Xml5Error::from(err.utf8_error()))
+}
+
+fn escape_str(raw: &str, must_escape: impl Fn(u8) -> bool) -> Cow<'_, str> {
+    match escape_bytes(raw.as_bytes(), must_escape) {
+        Cow::Borrowed(_) => Cow::Borrowed(raw),
+        // only ASCII was replaced with ASCII
+        Cow::Owned(bytes) => Cow::Owned(String::from_utf8(bytes).unwrap()),
+    }
+}
+
+/// Replaces bytes for which `must_escape` holds with the reference to the
+/// predefined entity for them.
+pub(crate) fn escape_bytes(raw: &[u8], must_escape: impl Fn(u8) -> bool) -> Cow<'_, [u8]> {
+    let mut escaped = None::<Vec<u8>>;
+    let mut start = 0;
+    for (pos, &chr) in raw.iter().enumerate() {
+        if !must_escape(chr) {
+            continue;
+        }
+        let name = match PREDEFINED_ENTITIES
+            .iter()
+            .find(|(_, value)| value.as_bytes() == [chr])
+        {
+            Some((name, _)) => name,
+            None => continue,
+        };
+        let out = escaped.get_or_insert_with(|| Vec::with_capacity(raw.len() + 8));
+        out.extend_from_slice(&raw[start..pos]);
+        out.push(b'&');
+        out.extend_from_slice(name);
+        out.push(b';');
+        start = pos + 1;
+    }
+    match escaped {
+        Some(mut out) => {
+            out.extend_from_slice(&raw[start..]);
+            Cow::Owned(out)
+        }
+        None => Cow::Borrowed(raw),
+    }
+}
+
+#[test]
+fn test_escape() {
+    assert!(matches!(escape("no markup"), Cow::Borrowed("no markup")));
+    assert_eq!(
+        "&lt;a href=&quot;x&apos;&quot;&gt; &amp;",
+        escape("<a href=\"x'\"> &")
+    );
+    assert_eq!(
+        "&lt;a href=\"x'\"&gt; &amp;",
+        partial_escape("<a href=\"x'\"> &")
+    );
+    assert_eq!("☃ &amp; é", escape("☃ & é"));
+}
+
+#[test]
+fn test_unescape() {
+    assert!(matches!(unescape("plain"), Ok(Cow::Borrowed("plain"))));
+    assert_eq!(
+        "<a> & \" {😀",
+        unescape("&lt;a&gt; &amp; &quot; &#123;&#x1F600;").unwrap()
+    );
+    assert_eq!(Err(Xml5Error::UnknownNamedCharRef), unescape("&foo;"));
+    assert_eq!(Err(Xml5Error::UnterminatedCharRef), unescape("&amp x"));
+
+    let resolve = |name: &str| (name == "foo").then_some("<bar>");
+    assert_eq!("<bar> &", unescape_with("&foo; &amp;", resolve).unwrap());
+    assert_eq!(
+        Err(Xml5Error::UnknownNamedCharRef),
+        unescape_with("&baz;", resolve)
+    );
+}
@@ -1,14 +1,20 @@
 use std::borrow::Cow;
-use std::borrow::Cow::Borrowed;
 use std::ops::Deref;
-use std::str::from_utf8_unchecked;
 
+use crate::attributes::Attributes;
 use crate::encoding::Decoder;
 use crate::errors::Xml5Error;
+use crate::tokenizer::Span;
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+/// List of `(name, value)` pairs of a tag's attributes.
+pub type Attrs<'a> = Vec<(Cow<'a, [u8]>, Cow<'a, [u8]>)>;
+
+/// Token of the input, along with the [`Span`] it was read from.
+///
+/// Tokens compare equal when their contents do, wherever they were read from.
+#[derive(Clone, Debug)]
 pub enum Token<'a> {
-    ///
+    /// Byte order mark at the start of the document.
     Bom(EncodedText<'a>),
     /// Character data between `Start` and `End` element.
     Text(BytesText<'a>),
@@ -23,87 +29,157 @@ pub enum Token<'a> {
     /// CData `<![CDATA[...]]>`.
     CData(BytesText<'a>),
     /// XML declaration `<?xml ...?>`.
-    Declaration(BytesText<'a>),
+    Declaration(DeclText<'a>),
     /// Processing instruction `<?...?>`.
     PI(PiText<'a>),
     /// Doctype `<!DOCTYPE ...>`.
     DocType(DocTypeText<'a>),
     /// End of XML document.
-    Eof,
-    /// Error
-    Error(Xml5Error),
+    Eof(Span),
+    /// Error, spanning from the start of the markup being read up to where
+    /// it was found.
+    Error(Xml5Error, Span),
 }
 
 impl<'a> Token<'a> {
     #[inline]
-    pub fn start_tag(cow: Cow<'a, [u8]>, attrs: Vec<(Cow<'a, [u8]>, Cow<'a, [u8]>)>) -> Token<'a> {
+    pub fn start_tag(cow: Cow<'a, [u8]>, attrs: Attrs<'a>) -> Token<'a> {
         Token::StartTag(TagAndAttrText {
             name: cow,
             attrs,
             self_closing: false,
+            span: Span::default(),
         })
     }
 
     #[inline]
-    pub fn empty_tag(cow: Cow<'a, [u8]>, attrs: Vec<(Cow<'a, [u8]>, Cow<'a, [u8]>)>) -> Token<'a> {
-        Token::StartTag(TagAndAttrText {
+    pub fn empty_tag(cow: Cow<'a, [u8]>, attrs: Attrs<'a>) -> Token<'a> {
+        Token::EmptyTag(TagAndAttrText {
             name: cow,
             attrs,
             self_closing: true,
+            span: Span::default(),
         })
     }
 
     #[inline]
-    pub fn end_tag(name: Cow<'a, [u8]>) -> Token<'_> {
-        Token::EndTag(BytesText { name })
+    pub fn end_tag(name: Cow<'a, [u8]>) -> Token<'a> {
+        Token::EndTag(BytesText::new(name))
     }
 
     #[inline]
     pub fn pi_tag(data: Cow<'a, [u8]>, target: Cow<'a, [u8]>) -> Token<'a> {
-        Token::PI(PiText { data, target })
+        Token::PI(PiText {
+            data,
+            target,
+            span: Span::default(),
+        })
     }
 
     #[inline]
     pub fn comment(name: Cow<'a, [u8]>) -> Token<'a> {
-        Token::Comment(BytesText { name })
+        Token::Comment(BytesText::new(name))
+    }
+
+    #[inline]
+    pub fn doctype(name: Cow<'a, [u8]>) -> Token<'a> {
+        Token::DocType(DocTypeText {
+            name,
+            public_id: None,
+            system_id: None,
+            internal_subset: None,
+            span: Span::default(),
+        })
     }
 
     #[inline]
-    pub fn declaration(text: Cow<'a, [u8]>) -> Token<'a> {
-        Token::Declaration(BytesText { name: text })
+    pub fn declaration(
+        version: Option<Cow<'a, [u8]>>,
+        encoding: Option<Cow<'a, [u8]>>,
+        standalone: Option<bool>,
+    ) -> Token<'a> {
+        Token::Declaration(DeclText {
+            version,
+            encoding,
+            standalone,
+            span: Span::default(),
+        })
     }
 
     #[inline]
     pub fn cdata(text: Cow<'a, [u8]>) -> Token<'a> {
-        Token::CData(BytesText { name: text })
+        Token::CData(BytesText::new(text))
     }
 
     #[inline]
     pub fn auto_close_tag() -> Token<'a> {
-        Token::EndTag(BytesText {
-            name: Cow::default(),
-        })
+        Token::EndTag(BytesText::default())
     }
 
     #[inline]
     pub fn text(text: Cow<'a, [u8]>) -> Token<'a> {
-        Token::Text(BytesText { name: text })
+        Token::Text(BytesText::new(text))
     }
 
     #[inline]
     pub fn error(err: Xml5Error) -> Token<'a> {
-        Token::Error(err)
+        Token::Error(err, Span::default())
+    }
+
+    #[inline]
+    pub fn eof() -> Token<'a> {
+        Token::Eof(Span::default())
+    }
+
+    /// Part of the input the token was read from. It's the default span for
+    /// tokens built with the constructors.
+    pub fn span(&self) -> Span {
+        match self {
+            Token::Bom(bom) => bom.span,
+            Token::Text(text) | Token::EndTag(text) | Token::Comment(text) | Token::CData(text) => {
+                text.span
+            }
+            Token::StartTag(tag) | Token::EmptyTag(tag) => tag.span,
+            Token::Declaration(decl) => decl.span,
+            Token::PI(pi) => pi.span,
+            Token::DocType(doctype) => doctype.span,
+            Token::Eof(span) | Token::Error(_, span) => *span,
+        }
+    }
+
+    /// Sets the span the token was read from.
+    pub(crate) fn with_span(mut self, span: Span) -> Token<'a> {
+        match &mut self {
+            Token::Bom(bom) => bom.span = span,
+            Token::Text(text) | Token::EndTag(text) | Token::Comment(text) | Token::CData(text) => {
+                text.span = span
+            }
+            Token::StartTag(tag) | Token::EmptyTag(tag) => tag.span = span,
+            Token::Declaration(decl) => decl.span = span,
+            Token::PI(pi) => pi.span = span,
+            Token::DocType(doctype) => doctype.span = span,
+            Token::Eof(old) | Token::Error(_, old) => *old = span,
+        }
+        self
     }
 
     #[inline]
     pub fn get_name(&self) -> Option<&[u8]> {
         match self {
-            Token::StartTag(start) => Some(start.name.deref()),
+            Token::StartTag(start) | Token::EmptyTag(start) => Some(start.name.deref()),
             Token::EndTag(end) => Some(end.name.deref()),
             _ => None,
         }
     }
 
+    #[inline]
+    pub fn get_attributes(&self) -> Option<Attributes<'_, 'a>> {
+        match self {
+            Token::StartTag(tag) | Token::EmptyTag(tag) => Some(tag.attributes()),
+            _ => None,
+        }
+    }
+
     #[inline]
     pub fn get_target(&self) -> Option<&[u8]> {
         match self {
@@ -129,12 +205,99 @@ impl<'a> Token<'a> {
     }
 
     #[inline]
-    pub fn get_declaration(&self) -> Option<&[u8]> {
+    pub fn get_declaration(&self) -> Option<&DeclText<'a>> {
         match self {
-            Token::Declaration(text) => Some(text.as_ref()),
+            Token::Declaration(decl) => Some(decl),
             _ => None,
         }
     }
+
+    /// Converts the token into one that owns all of its data, so it no longer
+    /// borrows from the input.
+    pub fn into_owned(self) -> Token<'static> {
+        match self {
+            Token::Bom(bom) => Token::Bom(EncodedText {
+                buf: into_owned(bom.buf),
+                decoder: bom.decoder,
+                span: bom.span,
+            }),
+            Token::Text(text) => Token::Text(text.into_owned()),
+            Token::StartTag(tag) => Token::StartTag(tag.into_owned()),
+            Token::EndTag(text) => Token::EndTag(text.into_owned()),
+            Token::EmptyTag(tag) => Token::EmptyTag(tag.into_owned()),
+            Token::Comment(text) => Token::Comment(text.into_owned()),
+            Token::CData(text) => Token::CData(text.into_owned()),
+            Token::Declaration(decl) => Token::Declaration(decl.into_owned()),
+            Token::PI(pi) => Token::PI(PiText {
+                target: into_owned(pi.target),
+                data: into_owned(pi.data),
+                span: pi.span,
+            }),
+            Token::DocType(doctype) => Token::DocType(doctype.into_owned()),
+            Token::Eof(span) => Token::Eof(span),
+            Token::Error(err, span) => Token::Error(err, span),
+        }
+    }
+}
+
+impl PartialEq for Token<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Token::Bom(bom), Token::Bom(other)) => bom == other,
+            (Token::Text(text), Token::Text(other))
+            | (Token::EndTag(text), Token::EndTag(other))
+            | (Token::Comment(text), Token::Comment(other))
+            | (Token::CData(text), Token::CData(other)) => text == other,
+            (Token::StartTag(tag), Token::StartTag(other))
+            | (Token::EmptyTag(tag), Token::EmptyTag(other)) => tag == other,
+            (Token::Declaration(decl), Token::Declaration(other)) => decl == other,
+            (Token::PI(pi), Token::PI(other)) => pi == other,
+            (Token::DocType(doctype), Token::DocType(other)) => doctype == other,
+            (Token::Eof(_), Token::Eof(_)) => true,
+            (Token::Error(err, _), Token::Error(other, _)) => err == other,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for Token<'_> {}
+
+/// Implements equality of a token's contents, leaving out its span.
+macro_rules! eq_without_span {
+    ($name:ident { $($field:ident),+ }) => {
+        impl PartialEq for $name<'_> {
+            fn eq(&self, other: &Self) -> bool {
+                $(self.$field == other.$field)&&+
+            }
+        }
+
+        impl Eq for $name<'_> {}
+    };
+}
+
+eq_without_span!(EncodedText { buf, decoder });
+eq_without_span!(TagAndAttrText {
+    name,
+    attrs,
+    self_closing
+});
+eq_without_span!(BytesText { name });
+eq_without_span!(PiText { target, data });
+eq_without_span!(DeclText {
+    version,
+    encoding,
+    standalone
+});
+eq_without_span!(DocTypeText {
+    name,
+    public_id,
+    system_id,
+    internal_subset
+});
+
+#[inline]
+fn into_owned(cow: Cow<'_, [u8]>) -> Cow<'static, [u8]> {
+    Cow::Owned(cow.into_owned())
 }
 
 impl PartialEq<BytesText<'_>> for &str {
@@ -143,40 +306,57 @@ impl PartialEq<BytesText<'_>> for &str {
     }
 }
 
-#[derive(Clone, Debug, Eq, PartialEq)]
+#[derive(Clone, Debug)]
 pub struct EncodedText<'a> {
     pub(crate) buf: Cow<'a, [u8]>,
     /// Encoding in which the `content` is stored inside the event
     pub(crate) decoder: Decoder,
+    pub(crate) span: Span,
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+impl<'a> EncodedText<'a> {
+    /// Bytes as they were in the input.
+    pub fn get_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Decoder of the encoding the bytes are in.
+    pub fn get_decoder(&self) -> Decoder {
+        self.decoder
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct TagAndAttrText<'a> {
     pub name: Cow<'a, [u8]>,
-    pub attrs: Vec<(Cow<'a, [u8]>, Cow<'a, [u8]>)>,
+    pub attrs: Attrs<'a>,
     pub(crate) self_closing: bool,
+    pub(crate) span: Span,
 }
 
 impl<'a> TagAndAttrText<'a> {
-    #[cfg(feature = "encoding")]
-    pub fn name_as_str(&self, decoding: Decoder) -> crate::encoding::Result<Cow<'_, str>> {
-        unsafe {
-            match &self.name {
-                Cow::Borrowed(x) => decoding.decode(x),
-                Cow::Owned(x) => decoding.decode(x),
-            }
-        }
+    /// Attributes of the tag, which can be looked up by name.
+    pub fn attributes(&self) -> Attributes<'_, 'a> {
+        Attributes::new(&self.attrs)
     }
 
-    #[cfg(not(feature = "encoding"))]
-    pub fn name_as_str(&self, decoding: Decoder) -> &str {
-        unsafe {
-            match &self.name {
-                Cow::Borrowed(x) => from_utf8_unchecked(x),
-                Cow::Owned(x) => from_utf8_unchecked(x),
-            }
+    pub fn into_owned(self) -> TagAndAttrText<'static> {
+        TagAndAttrText {
+            name: into_owned(self.name),
+            attrs: self
+                .attrs
+                .into_iter()
+                .map(|(name, value)| (into_owned(name), into_owned(value)))
+                .collect(),
+            self_closing: self.self_closing,
+            span: self.span,
         }
     }
+
+    /// Decodes the name, failing if it isn't valid in the `decoding`.
+    pub fn name_as_str(&self, decoding: Decoder) -> crate::encoding::Result<Cow<'_, str>> {
+        decoding.decode(&self.name)
+    }
 }
 
 impl<'a> Deref for TagAndAttrText<'a> {
@@ -187,9 +367,10 @@ impl<'a> Deref for TagAndAttrText<'a> {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq, Default)]
+#[derive(Clone, Debug, Default)]
 pub struct BytesText<'a> {
     pub(crate) name: Cow<'a, [u8]>,
+    pub(crate) span: Span,
 }
 
 impl<'a> Deref for BytesText<'a> {
@@ -201,16 +382,32 @@ impl<'a> Deref for BytesText<'a> {
 }
 
 impl<'a> BytesText<'a> {
+    #[inline]
+    pub(crate) fn new(name: Cow<'a, [u8]>) -> Self {
+        BytesText {
+            name,
+            span: Span::default(),
+        }
+    }
+
     #[inline]
     pub fn is_empty(&self) -> bool {
         self.name.is_empty()
     }
+
+    pub fn into_owned(self) -> BytesText<'static> {
+        BytesText {
+            name: into_owned(self.name),
+            span: self.span,
+        }
+    }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+#[derive(Clone, Debug)]
 pub struct PiText<'a> {
     pub(crate) target: Cow<'a, [u8]>,
     pub(crate) data: Cow<'a, [u8]>,
+    pub(crate) span: Span,
 }
 
 impl<'a> PiText<'a> {
@@ -223,7 +420,84 @@ impl<'a> PiText<'a> {
     }
 }
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+/// Pseudo-attributes of the XML declaration
+/// `<?xml version="1.0" encoding="UTF-8" standalone="yes"?>`.
+#[derive(Clone, Debug)]
+pub struct DeclText<'a> {
+    pub(crate) version: Option<Cow<'a, [u8]>>,
+    pub(crate) encoding: Option<Cow<'a, [u8]>>,
+    pub(crate) standalone: Option<bool>,
+    pub(crate) span: Span,
+}
+
+impl<'a> DeclText<'a> {
+    pub fn get_version(&self) -> Option<&[u8]> {
+        self.version.as_deref()
+    }
+
+    /// Label of the encoding, as written in the declaration.
+    pub fn get_encoding(&self) -> Option<&[u8]> {
+        self.encoding.as_deref()
+    }
+
+    /// Encoding the label stands for, `None` if it's missing or unknown.
+    #[cfg(feature = "encoding")]
+    pub fn get_encoder(&self) -> Option<&'static encoding_rs::Encoding> {
+        encoding_rs::Encoding::for_label(self.get_encoding()?)
+    }
+
+    /// `standalone`, which is `None` if missing or other than `yes` or `no`.
+    pub fn get_standalone(&self) -> Option<bool> {
+        self.standalone
+    }
+
+    pub fn into_owned(self) -> DeclText<'static> {
+        DeclText {
+            version: self.version.map(into_owned),
+            encoding: self.encoding.map(into_owned),
+            standalone: self.standalone,
+            span: self.span,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
 pub struct DocTypeText<'a> {
     pub(crate) name: Cow<'a, [u8]>,
+    pub(crate) public_id: Option<Cow<'a, [u8]>>,
+    pub(crate) system_id: Option<Cow<'a, [u8]>>,
+    pub(crate) internal_subset: Option<Cow<'a, [u8]>>,
+    pub(crate) span: Span,
+}
+
+impl<'a> DocTypeText<'a> {
+    pub fn get_name(&self) -> &[u8] {
+        self.name.deref()
+    }
+
+    /// Public identifier from `<!DOCTYPE name PUBLIC "public" "system">`.
+    pub fn get_public_id(&self) -> Option<&[u8]> {
+        self.public_id.as_deref()
+    }
+
+    /// System identifier from `<!DOCTYPE name SYSTEM "system">` or
+    /// `<!DOCTYPE name PUBLIC "public" "system">`.
+    pub fn get_system_id(&self) -> Option<&[u8]> {
+        self.system_id.as_deref()
+    }
+
+    /// Raw bytes between `[` and `]` of the doctype, without any processing.
+    pub fn get_internal_subset(&self) -> Option<&[u8]> {
+        self.internal_subset.as_deref()
+    }
+
+    pub fn into_owned(self) -> DocTypeText<'static> {
+        DocTypeText {
+            name: into_owned(self.name),
+            public_id: self.public_id.map(into_owned),
+            system_id: self.system_id.map(into_owned),
+            internal_subset: self.internal_subset.map(into_owned),
+            span: self.span,
+        }
+    }
 }
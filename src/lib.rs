@@ -1,15 +1,43 @@
 // Temporary disable
 #![allow(unused_must_use)]
+// Needle lists read better as a list of bytes
+#![allow(clippy::byte_char_slices)]
 
 extern crate core;
 #[cfg(feature = "encoding_rs")]
 extern crate encoding_rs;
 
-pub use tokenizer::Tokenizer;
+#[cfg(feature = "tokio")]
+pub use tokenizer::AsyncBufIterator;
+#[cfg(feature = "test-harness")]
+#[doc(hidden)]
+pub use tokenizer::TokenState;
+pub use tokenizer::{
+    BufIterator, ByteIterator, DefaultEmitter, DoctypeKind, Emitter, EmitterIterator, IntoBytes,
+    Mix, Position, PushTokenizer, SliceIterator, Span, SpanTokens, Spans, Tokenizer,
+    TokenizerConfig, XmlDeclAttr,
+};
+pub use tree_builder::{TreeBuilder, TreeSink};
 
-pub use crate::events::Token;
+pub use crate::attributes::{Attribute, Attributes};
+pub use crate::dtd::Dtd;
+pub use crate::errors::{Xml5Error, Xml5Result};
+pub use crate::events::{Attrs, Token};
+pub use crate::namespace::NsReader;
+pub use crate::writer::Writer;
 
+mod attributes;
+#[cfg(feature = "serialize")]
+pub mod de;
+pub mod dom;
+pub mod dtd;
 pub mod encoding;
 mod errors;
+pub mod escape;
 mod events;
+pub mod namespace;
+#[cfg(feature = "serialize")]
+pub mod ser;
 mod tokenizer;
+mod tree_builder;
+mod writer;
@@ -0,0 +1,235 @@
+//! Namespace resolution on top of a token stream.
+//!
+//! [`NsReader`] keeps track of `xmlns` and `xmlns:prefix` declarations of
+//! every open element, and resolves element and attribute names to their
+//! namespace URI and local name.
+
+use std::borrow::Cow;
+use std::collections::VecDeque;
+use std::ops::{Deref, Range};
+
+use crate::errors::Xml5Error;
+use crate::events::TagAndAttrText;
+use crate::{Span, Token};
+
+/// Namespace bound to the `xml` prefix.
+pub const XML_NAMESPACE: &[u8] = b"http://www.w3.org/XML/1998/namespace";
+/// Namespace of `xmlns` attributes.
+pub const XMLNS_NAMESPACE: &[u8] = b"http://www.w3.org/2000/xmlns/";
+
+/// Name split into its prefix and local name, with the prefix resolved.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ResolvedName<'a> {
+    /// Namespace URI, `None` if the name isn't in any namespace
+    pub namespace: Option<Cow<'a, [u8]>>,
+    pub prefix: Option<Cow<'a, [u8]>>,
+    pub local_name: Cow<'a, [u8]>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct NsTag<'a> {
+    pub name: ResolvedName<'a>,
+    pub attrs: Vec<(ResolvedName<'a>, Cow<'a, [u8]>)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum NsToken<'a> {
+    StartTag(NsTag<'a>),
+    EmptyTag(NsTag<'a>),
+    /// End tag, resolved in the scope of the element it closes. For `</>`
+    /// that's the name of the closed element.
+    EndTag(ResolvedName<'a>),
+    /// Any other token, passed through as is
+    Other(Token<'a>),
+}
+
+/// `(prefix, namespace)` pair, with an empty prefix for the default namespace
+/// and `None` when it is undeclared with `xmlns=""`.
+type Binding<'a> = (Cow<'a, [u8]>, Option<Cow<'a, [u8]>>);
+
+/// Iterator adapter that resolves namespaces of tags. Prefixes without a
+/// declaration in scope are reported as [`Xml5Error::UnboundPrefix`] right
+/// before the tag using them, and resolve to no namespace.
+pub struct NsReader<'a, I> {
+    tokens: I,
+    /// declarations in scope, innermost last
+    bindings: Vec<Binding<'a>>,
+    /// open elements with the number of bindings in scope before them
+    scopes: Vec<(Cow<'a, [u8]>, usize)>,
+    pending: VecDeque<NsToken<'a>>,
+}
+
+impl<'a, I> NsReader<'a, I>
+where
+    I: Iterator<Item = Token<'a>>,
+{
+    pub fn new<T>(tokens: T) -> Self
+    where
+        T: IntoIterator<IntoIter = I>,
+    {
+        NsReader {
+            tokens: tokens.into_iter(),
+            bindings: vec![],
+            scopes: vec![],
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Namespace bound to `prefix` at this point of the document. The empty
+    /// prefix looks up the default namespace.
+    pub fn lookup(&self, prefix: &[u8]) -> Option<&[u8]> {
+        match prefix {
+            b"xml" => Some(XML_NAMESPACE),
+            b"xmlns" => Some(XMLNS_NAMESPACE),
+            _ => self
+                .bindings
+                .iter()
+                .rev()
+                .find(|(bound, _)| bound.deref() == prefix)
+                .and_then(|(_, namespace)| namespace.as_deref()),
+        }
+    }
+
+    fn binding(&self, prefix: &[u8]) -> Option<Cow<'a, [u8]>> {
+        match prefix {
+            b"xml" => Some(Cow::Borrowed(XML_NAMESPACE)),
+            b"xmlns" => Some(Cow::Borrowed(XMLNS_NAMESPACE)),
+            _ => self
+                .bindings
+                .iter()
+                .rev()
+                .find(|(bound, _)| bound.deref() == prefix)
+                .and_then(|(_, namespace)| namespace.clone()),
+        }
+    }
+
+    /// Resolves the name of an element or attribute of the tag read from
+    /// `span`.
+    fn resolve(&mut self, qname: Cow<'a, [u8]>, is_element: bool, span: Span) -> ResolvedName<'a> {
+        let colon = qname
+            .iter()
+            .position(|&chr| chr == b':')
+            .filter(|&colon| colon > 0 && colon + 1 < qname.len());
+        let (prefix, local_name) = match colon {
+            Some(colon) => (
+                Some(sub_cow(&qname, 0..colon)),
+                sub_cow(&qname, colon + 1..qname.len()),
+            ),
+            None => (None, qname),
+        };
+
+        let namespace = match &prefix {
+            Some(prefix) => {
+                let namespace = self.binding(prefix);
+                if namespace.is_none() {
+                    let err = Xml5Error::UnboundPrefix(prefix.to_vec());
+                    self.pending
+                        .push_back(NsToken::Other(Token::Error(err, span)));
+                }
+                namespace
+            }
+            // Default namespace doesn't apply to attributes
+            None if is_element => self.binding(b""),
+            None if local_name.deref() == b"xmlns" => Some(Cow::Borrowed(XMLNS_NAMESPACE)),
+            None => None,
+        };
+        ResolvedName {
+            namespace,
+            prefix,
+            local_name,
+        }
+    }
+
+    fn resolve_tag(&mut self, tag: TagAndAttrText<'a>) -> NsTag<'a> {
+        for (name, value) in &tag.attrs {
+            let prefix = if name.deref() == b"xmlns" {
+                Cow::Borrowed(&b""[..])
+            } else if name.starts_with(b"xmlns:") {
+                sub_cow(name, 6..name.len())
+            } else {
+                continue;
+            };
+            let namespace = if value.is_empty() {
+                None
+            } else {
+                Some(value.clone())
+            };
+            self.bindings.push((prefix, namespace));
+        }
+
+        let span = tag.span;
+        NsTag {
+            name: self.resolve(tag.name, true, span),
+            attrs: tag
+                .attrs
+                .into_iter()
+                .map(|(name, value)| (self.resolve(name, false, span), value))
+                .collect(),
+        }
+    }
+
+    /// Closes elements up to the one matching `name`, same as the tree
+    /// builder does. Unmatched end tags are resolved in the current scope.
+    fn resolve_end_tag(&mut self, name: Cow<'a, [u8]>, span: Span) -> ResolvedName<'a> {
+        let position = if name.is_empty() {
+            self.scopes.len().checked_sub(1)
+        } else {
+            self.scopes
+                .iter()
+                .rposition(|(element, _)| element.deref() == name.deref())
+        };
+        let position = match position {
+            Some(position) => position,
+            None => return self.resolve(name, true, span),
+        };
+        // declarations of implicitly closed elements are out of scope already
+        if let Some(&(_, inner)) = self.scopes.get(position + 1) {
+            self.bindings.truncate(inner);
+        }
+        self.scopes.truncate(position + 1);
+        let (element, outer) = self.scopes.remove(position);
+        let resolved = self.resolve(element, true, span);
+        self.bindings.truncate(outer);
+        resolved
+    }
+}
+
+impl<'a, I> Iterator for NsReader<'a, I>
+where
+    I: Iterator<Item = Token<'a>>,
+{
+    type Item = NsToken<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if let Some(token) = self.pending.pop_front() {
+            return Some(token);
+        }
+        let token = match self.tokens.next()? {
+            Token::StartTag(tag) => {
+                let scope = (tag.name.clone(), self.bindings.len());
+                let tag = self.resolve_tag(tag);
+                self.scopes.push(scope);
+                NsToken::StartTag(tag)
+            }
+            Token::EmptyTag(tag) => {
+                let outer = self.bindings.len();
+                let tag = self.resolve_tag(tag);
+                self.bindings.truncate(outer);
+                NsToken::EmptyTag(tag)
+            }
+            Token::EndTag(tag) => NsToken::EndTag(self.resolve_end_tag(tag.name, tag.span)),
+            token => NsToken::Other(token),
+        };
+        // errors found while resolving go first
+        self.pending.push_back(token);
+        self.pending.pop_front()
+    }
+}
+
+/// Sub-slice of a `Cow`, which stays borrowed if the original one was.
+fn sub_cow<'a>(cow: &Cow<'a, [u8]>, range: Range<usize>) -> Cow<'a, [u8]> {
+    match cow {
+        Cow::Borrowed(slice) => Cow::Borrowed(&slice[range]),
+        Cow::Owned(vec) => Cow::Owned(vec[range].to_vec()),
+    }
+}
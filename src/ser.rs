@@ -0,0 +1,878 @@
+//! Serde serializer, available with the `serialize` feature.
+//!
+//! Values are mapped to XML the same way [`de`](crate::de) reads them back:
+//!
+//! - struct fields prefixed with `@` become attributes
+//! - the `$text` field becomes the text of the element
+//! - other fields become child elements named after the field, and
+//!   sequences become one element per item
+//! - unit variants are written as text, other variants as a child element
+//!   named after the variant
+//!
+//! `None` fields are left out. Everything is written through [`Writer`], so
+//! text and attribute values are escaped by the same rules.
+
+use std::borrow::Cow;
+use std::fmt;
+use std::io::Write;
+
+use serde::ser::{self, Impossible, Serialize};
+
+use crate::de::TEXT_KEY;
+use crate::errors::Xml5Error;
+use crate::{Token, Writer};
+
+#[derive(Debug)]
+pub enum SeError {
+    /// Error raised by the type being serialized
+    Custom(String),
+    /// Error while writing
+    Xml(Xml5Error),
+    /// Value has no name to use for the root element and none was configured
+    MissingRootName,
+    /// Map key that isn't a string or a primitive
+    InvalidKey,
+    /// Attribute value that isn't a primitive or a sequence of primitives
+    InvalidAttribute(String),
+    /// Element or attribute name, e.g. from a map key, that isn't an XML name
+    InvalidName(String),
+}
+
+impl fmt::Display for SeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SeError::Custom(msg) => f.write_str(msg),
+            SeError::Xml(err) => write!(f, "{:?}", err),
+            SeError::MissingRootName => f.write_str("no name for the root element"),
+            SeError::InvalidKey => f.write_str("map key must be a string or a primitive"),
+            SeError::InvalidAttribute(name) => write!(f, "invalid value of attribute `{}`", name),
+            SeError::InvalidName(name) => write!(f, "`{}` isn't a valid XML name", name),
+        }
+    }
+}
+
+impl std::error::Error for SeError {}
+
+impl ser::Error for SeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        SeError::Custom(msg.to_string())
+    }
+}
+
+impl From<Xml5Error> for SeError {
+    fn from(err: Xml5Error) -> Self {
+        SeError::Xml(err)
+    }
+}
+
+/// Serializes `value` into an XML string, with the root element named after
+/// the type.
+pub fn to_string<T: Serialize + ?Sized>(value: &T) -> Result<String, SeError> {
+    let mut buffer = vec![];
+    value.serialize(Serializer::new(&mut buffer))?;
+    Ok(String::from_utf8(buffer).expect("serialized XML is valid UTF-8"))
+}
+
+/// Serializes `value` as XML into `writer`.
+pub fn to_writer<W: Write, T: Serialize + ?Sized>(writer: W, value: &T) -> Result<(), SeError> {
+    value.serialize(Serializer::new(writer))
+}
+
+/// Serializer writing a single root element into `W`.
+pub struct Serializer<W: Write> {
+    writer: W,
+    root_name: Option<String>,
+    indent: Option<(u8, usize)>,
+    primitives_as_attributes: bool,
+}
+
+impl<W: Write> Serializer<W> {
+    pub fn new(writer: W) -> Self {
+        Serializer {
+            writer,
+            root_name: None,
+            indent: None,
+            primitives_as_attributes: false,
+        }
+    }
+
+    /// Name of the root element, instead of the name of the serialized type.
+    pub fn root_name(mut self, name: &str) -> Self {
+        self.root_name = Some(name.to_string());
+        self
+    }
+
+    /// Puts child elements on their own lines, indented by `size` times
+    /// `indent_char` per level. Elements with text are left as they are.
+    pub fn indent(mut self, indent_char: u8, size: usize) -> Self {
+        self.indent = Some((indent_char, size));
+        self
+    }
+
+    /// Writes fields holding a single primitive as attributes even without
+    /// the `@` prefix.
+    pub fn primitives_as_attributes(mut self, enabled: bool) -> Self {
+        self.primitives_as_attributes = enabled;
+        self
+    }
+
+    fn write_root(self, value: Value, type_name: Option<&'static str>) -> Result<(), SeError> {
+        let name = match (&self.root_name, type_name) {
+            (Some(name), _) => name.clone(),
+            (None, Some(name)) => name.to_string(),
+            (None, None) => return Err(SeError::MissingRootName),
+        };
+        let mut output = Output {
+            writer: Writer::new(self.writer),
+            indent: self.indent,
+            primitives_as_attributes: self.primitives_as_attributes,
+        };
+        match value {
+            // there has to be a root element
+            Value::None => output.write_element(&name, &Value::Text(String::new()), 0),
+            value => output.write_element(&name, &value, 0),
+        }
+    }
+}
+
+/// Serialized value, kept until the whole element is known since attributes
+/// may come after child elements in a struct.
+enum Value {
+    /// `None` and unit, which produce no output
+    None,
+    Text(String),
+    /// Items written as elements of the same name
+    Seq(Vec<Value>),
+    /// Fields of a struct or entries of a map
+    Struct(Vec<(String, Value)>),
+}
+
+struct Output<W: Write> {
+    writer: Writer<W>,
+    indent: Option<(u8, usize)>,
+    primitives_as_attributes: bool,
+}
+
+impl<W: Write> Output<W> {
+    fn write_element(&mut self, name: &str, value: &Value, depth: usize) -> Result<(), SeError> {
+        let fields = match value {
+            Value::None => return Ok(()),
+            Value::Seq(items) => {
+                for (pos, item) in items.iter().enumerate() {
+                    if pos > 0 {
+                        self.write_indent(depth)?;
+                    }
+                    self.write_element(name, item, depth)?;
+                }
+                return Ok(());
+            }
+            _ if !is_name(name) => return Err(SeError::InvalidName(name.to_string())),
+            Value::Text(text) if text.is_empty() => {
+                let tag = Token::empty_tag(Cow::Borrowed(name.as_bytes()), vec![]);
+                return Ok(self.writer.write_token(&tag)?);
+            }
+            Value::Text(text) => {
+                self.write_start(name, vec![])?;
+                self.write_text(text)?;
+                return self.write_end(name);
+            }
+            Value::Struct(fields) => fields,
+        };
+
+        let mut attrs = vec![];
+        let mut text = None;
+        let mut children = vec![];
+        for (field, value) in fields {
+            if let Some(attr) = field.strip_prefix('@') {
+                if let Some(value) = attribute_value(attr, value)? {
+                    attrs.push((attr, value));
+                }
+            } else if field == TEXT_KEY {
+                text = Some(value);
+            } else if let (true, Value::Text(value)) = (self.primitives_as_attributes, value) {
+                attrs.push((field, value.clone()));
+            } else if !matches!(value, Value::None) {
+                children.push((field, value));
+            }
+        }
+        let attrs = attrs
+            .into_iter()
+            .map(|(name, value)| {
+                if !is_name(name) {
+                    return Err(SeError::InvalidName(name.to_string()));
+                }
+                Ok((
+                    Cow::Borrowed(name.as_bytes()),
+                    Cow::Owned(value.into_bytes()),
+                ))
+            })
+            .collect::<Result<_, _>>()?;
+
+        let text = match text {
+            Some(Value::Text(text)) if !text.is_empty() => Some(text),
+            _ => None,
+        };
+        if text.is_none() && children.is_empty() {
+            let tag = Token::empty_tag(Cow::Borrowed(name.as_bytes()), attrs);
+            return Ok(self.writer.write_token(&tag)?);
+        }
+
+        self.write_start(name, attrs)?;
+        if let Some(text) = text {
+            self.write_text(text)?;
+            // mixed content is left without indentation
+            for (field, value) in children {
+                self.write_element(field, value, depth + 1)?;
+            }
+        } else {
+            for (field, value) in children {
+                self.write_indent(depth + 1)?;
+                self.write_element(field, value, depth + 1)?;
+            }
+            self.write_indent(depth)?;
+        }
+        self.write_end(name)
+    }
+
+    fn write_start(&mut self, name: &str, attrs: crate::Attrs<'_>) -> Result<(), SeError> {
+        let tag = Token::start_tag(Cow::Borrowed(name.as_bytes()), attrs);
+        Ok(self.writer.write_token(&tag)?)
+    }
+
+    fn write_end(&mut self, name: &str) -> Result<(), SeError> {
+        let tag = Token::end_tag(Cow::Borrowed(name.as_bytes()));
+        Ok(self.writer.write_token(&tag)?)
+    }
+
+    fn write_text(&mut self, text: &str) -> Result<(), SeError> {
+        let text = Token::text(Cow::Borrowed(text.as_bytes()));
+        Ok(self.writer.write_token(&text)?)
+    }
+
+    fn write_indent(&mut self, depth: usize) -> Result<(), SeError> {
+        if let Some((indent_char, size)) = self.indent {
+            let mut indent = vec![b'\n'];
+            indent.resize(1 + depth * size, indent_char);
+            self.writer.write_token(&Token::text(Cow::Owned(indent)))?;
+        }
+        Ok(())
+    }
+}
+
+/// Whether `name` matches the `Name` production of XML, so it can be written
+/// as an element or attribute name as it is.
+fn is_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars.next().is_some_and(is_name_start) && chars.all(is_name_char)
+}
+
+fn is_name_start(chr: char) -> bool {
+    matches!(chr,
+        ':' | 'A'..='Z' | '_' | 'a'..='z'
+        | '\u{C0}'..='\u{D6}'
+        | '\u{D8}'..='\u{F6}'
+        | '\u{F8}'..='\u{2FF}'
+        | '\u{370}'..='\u{37D}'
+        | '\u{37F}'..='\u{1FFF}'
+        | '\u{200C}'..='\u{200D}'
+        | '\u{2070}'..='\u{218F}'
+        | '\u{2C00}'..='\u{2FEF}'
+        | '\u{3001}'..='\u{D7FF}'
+        | '\u{F900}'..='\u{FDCF}'
+        | '\u{FDF0}'..='\u{FFFD}'
+        | '\u{10000}'..='\u{EFFFF}')
+}
+
+fn is_name_char(chr: char) -> bool {
+    is_name_start(chr)
+        || matches!(chr,
+            '-' | '.' | '0'..='9' | '\u{B7}'
+            | '\u{300}'..='\u{36F}'
+            | '\u{203F}'..='\u{2040}')
+}
+
+/// Attribute value as text, with sequences separated by spaces. `None` if
+/// the attribute is left out.
+fn attribute_value(name: &str, value: &Value) -> Result<Option<String>, SeError> {
+    match value {
+        Value::None => Ok(None),
+        Value::Text(text) => Ok(Some(text.clone())),
+        Value::Seq(items) => {
+            let mut list = vec![];
+            for item in items {
+                match item {
+                    Value::Text(text) => list.push(text.as_str()),
+                    _ => return Err(SeError::InvalidAttribute(name.to_string())),
+                }
+            }
+            Ok(Some(list.join(" ")))
+        }
+        Value::Struct(_) => Err(SeError::InvalidAttribute(name.to_string())),
+    }
+}
+
+/// Serializes primitives as their `Display` text, through `$into`.
+macro_rules! serialize_display {
+    ($into:ident: $($method:ident($ty:ty),)*) => {
+        $(
+            fn $method(self, v: $ty) -> Result<Self::Ok, SeError> {
+                self.$into(Value::Text(v.to_string()))
+            }
+        )*
+    };
+}
+
+/// Serializes a value into a [`Value`].
+struct ValueSerializer;
+
+impl ValueSerializer {
+    fn text(self, value: Value) -> Result<Value, SeError> {
+        Ok(value)
+    }
+}
+
+impl ser::Serializer for ValueSerializer {
+    type Ok = Value;
+    type Error = SeError;
+    type SerializeSeq = SeqSerializer;
+    type SerializeTuple = SeqSerializer;
+    type SerializeTupleStruct = SeqSerializer;
+    type SerializeTupleVariant = VariantSerializer<SeqSerializer>;
+    type SerializeMap = StructSerializer;
+    type SerializeStruct = StructSerializer;
+    type SerializeStructVariant = VariantSerializer<StructSerializer>;
+
+    serialize_display! {
+        text:
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<Value, SeError> {
+        Ok(Value::Text(String::from_utf8_lossy(v).into_owned()))
+    }
+
+    fn serialize_none(self) -> Result<Value, SeError> {
+        Ok(Value::None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<Value, SeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<Value, SeError> {
+        Ok(Value::Text(String::new()))
+    }
+
+    fn serialize_unit_struct(self, _name: &'static str) -> Result<Value, SeError> {
+        Ok(Value::Text(String::new()))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<Value, SeError> {
+        Ok(Value::Text(variant.to_string()))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        value: &T,
+    ) -> Result<Value, SeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<Value, SeError> {
+        let value = value.serialize(self)?;
+        Ok(Value::Struct(vec![(variant.to_string(), value)]))
+    }
+
+    fn serialize_seq(self, len: Option<usize>) -> Result<SeqSerializer, SeError> {
+        Ok(SeqSerializer(Vec::with_capacity(len.unwrap_or(0))))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<SeqSerializer, SeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<SeqSerializer, SeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, SeError> {
+        Ok(VariantSerializer {
+            variant,
+            inner: SeqSerializer(Vec::with_capacity(len)),
+        })
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<StructSerializer, SeError> {
+        Ok(StructSerializer {
+            fields: Vec::with_capacity(len.unwrap_or(0)),
+            key: None,
+        })
+    }
+
+    fn serialize_struct(
+        self,
+        _name: &'static str,
+        len: usize,
+    ) -> Result<StructSerializer, SeError> {
+        self.serialize_map(Some(len))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        _name: &'static str,
+        _index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, SeError> {
+        Ok(VariantSerializer {
+            variant,
+            inner: self.serialize_map(Some(len))?,
+        })
+    }
+}
+
+struct SeqSerializer(Vec<Value>);
+
+impl ser::SerializeSeq for SeqSerializer {
+    type Ok = Value;
+    type Error = SeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SeError> {
+        self.0.push(value.serialize(ValueSerializer)?);
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, SeError> {
+        Ok(Value::Seq(self.0))
+    }
+}
+
+impl ser::SerializeTuple for SeqSerializer {
+    type Ok = Value;
+    type Error = SeError;
+
+    fn serialize_element<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SeError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, SeError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+impl ser::SerializeTupleStruct for SeqSerializer {
+    type Ok = Value;
+    type Error = SeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SeError> {
+        ser::SerializeSeq::serialize_element(self, value)
+    }
+
+    fn end(self) -> Result<Value, SeError> {
+        ser::SerializeSeq::end(self)
+    }
+}
+
+struct StructSerializer {
+    fields: Vec<(String, Value)>,
+    key: Option<String>,
+}
+
+impl ser::SerializeStruct for StructSerializer {
+    type Ok = Value;
+    type Error = SeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SeError> {
+        let value = value.serialize(ValueSerializer)?;
+        self.fields.push((key.to_string(), value));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, SeError> {
+        Ok(Value::Struct(self.fields))
+    }
+}
+
+impl ser::SerializeMap for StructSerializer {
+    type Ok = Value;
+    type Error = SeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), SeError> {
+        match key.serialize(ValueSerializer)? {
+            Value::Text(key) => self.key = Some(key),
+            _ => return Err(SeError::InvalidKey),
+        }
+        Ok(())
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SeError> {
+        let key = self.key.take().ok_or(SeError::InvalidKey)?;
+        self.fields.push((key, value.serialize(ValueSerializer)?));
+        Ok(())
+    }
+
+    fn end(self) -> Result<Value, SeError> {
+        Ok(Value::Struct(self.fields))
+    }
+}
+
+/// Content of a variant, which is wrapped in an element named after it.
+struct VariantSerializer<S> {
+    variant: &'static str,
+    inner: S,
+}
+
+impl<S> VariantSerializer<S> {
+    fn wrap(variant: &'static str, value: Value) -> Value {
+        Value::Struct(vec![(variant.to_string(), value)])
+    }
+}
+
+impl ser::SerializeTupleVariant for VariantSerializer<SeqSerializer> {
+    type Ok = Value;
+    type Error = SeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SeError> {
+        ser::SerializeSeq::serialize_element(&mut self.inner, value)
+    }
+
+    fn end(self) -> Result<Value, SeError> {
+        let value = ser::SerializeSeq::end(self.inner)?;
+        Ok(Self::wrap(self.variant, value))
+    }
+}
+
+impl ser::SerializeStructVariant for VariantSerializer<StructSerializer> {
+    type Ok = Value;
+    type Error = SeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SeError> {
+        ser::SerializeStruct::serialize_field(&mut self.inner, key, value)
+    }
+
+    fn end(self) -> Result<Value, SeError> {
+        let value = ser::SerializeStruct::end(self.inner)?;
+        Ok(Self::wrap(self.variant, value))
+    }
+}
+
+/// Collects a compound value, then writes it as the root element.
+pub struct RootSerializer<W: Write> {
+    serializer: Serializer<W>,
+    type_name: Option<&'static str>,
+    inner: Compound,
+}
+
+enum Compound {
+    Seq(SeqSerializer),
+    Struct(StructSerializer),
+    SeqVariant(VariantSerializer<SeqSerializer>),
+    StructVariant(VariantSerializer<StructSerializer>),
+}
+
+impl<W: Write> Serializer<W> {
+    fn root(self, value: Value) -> Result<(), SeError> {
+        self.write_root(value, None)
+    }
+
+    fn compound(self, type_name: Option<&'static str>, inner: Compound) -> RootSerializer<W> {
+        RootSerializer {
+            serializer: self,
+            type_name,
+            inner,
+        }
+    }
+}
+
+impl<W: Write> ser::Serializer for Serializer<W> {
+    type Ok = ();
+    type Error = SeError;
+    type SerializeSeq = Impossible<(), SeError>;
+    type SerializeTuple = Impossible<(), SeError>;
+    type SerializeTupleStruct = RootSerializer<W>;
+    type SerializeTupleVariant = RootSerializer<W>;
+    type SerializeMap = RootSerializer<W>;
+    type SerializeStruct = RootSerializer<W>;
+    type SerializeStructVariant = RootSerializer<W>;
+
+    serialize_display! {
+        root:
+        serialize_bool(bool),
+        serialize_i8(i8),
+        serialize_i16(i16),
+        serialize_i32(i32),
+        serialize_i64(i64),
+        serialize_u8(u8),
+        serialize_u16(u16),
+        serialize_u32(u32),
+        serialize_u64(u64),
+        serialize_f32(f32),
+        serialize_f64(f64),
+        serialize_char(char),
+        serialize_str(&str),
+    }
+
+    fn serialize_bytes(self, v: &[u8]) -> Result<(), SeError> {
+        let value = ValueSerializer.serialize_bytes(v)?;
+        self.root(value)
+    }
+
+    fn serialize_none(self) -> Result<(), SeError> {
+        self.root(Value::None)
+    }
+
+    fn serialize_some<T: Serialize + ?Sized>(self, value: &T) -> Result<(), SeError> {
+        value.serialize(self)
+    }
+
+    fn serialize_unit(self) -> Result<(), SeError> {
+        self.root(Value::None)
+    }
+
+    fn serialize_unit_struct(self, name: &'static str) -> Result<(), SeError> {
+        self.write_root(Value::None, Some(name))
+    }
+
+    fn serialize_unit_variant(
+        self,
+        name: &'static str,
+        _index: u32,
+        variant: &'static str,
+    ) -> Result<(), SeError> {
+        self.write_root(Value::Text(variant.to_string()), Some(name))
+    }
+
+    fn serialize_newtype_struct<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        value: &T,
+    ) -> Result<(), SeError> {
+        let value = value.serialize(ValueSerializer)?;
+        self.write_root(value, Some(name))
+    }
+
+    fn serialize_newtype_variant<T: Serialize + ?Sized>(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        value: &T,
+    ) -> Result<(), SeError> {
+        let value = ValueSerializer.serialize_newtype_variant(name, index, variant, value)?;
+        self.write_root(value, Some(name))
+    }
+
+    /// A sequence would be several root elements.
+    fn serialize_seq(self, _len: Option<usize>) -> Result<Self::SerializeSeq, SeError> {
+        Err(SeError::Custom(
+            "sequence can't be serialized as the root element".to_string(),
+        ))
+    }
+
+    fn serialize_tuple(self, len: usize) -> Result<Self::SerializeTuple, SeError> {
+        self.serialize_seq(Some(len))
+    }
+
+    /// Fields of tuple structs are repeated `item` elements.
+    fn serialize_tuple_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleStruct, SeError> {
+        let inner = ValueSerializer.serialize_tuple_struct(name, len)?;
+        Ok(self.compound(Some(name), Compound::Seq(inner)))
+    }
+
+    fn serialize_tuple_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeTupleVariant, SeError> {
+        let inner = ValueSerializer.serialize_tuple_variant(name, index, variant, len)?;
+        Ok(self.compound(Some(name), Compound::SeqVariant(inner)))
+    }
+
+    fn serialize_map(self, len: Option<usize>) -> Result<Self::SerializeMap, SeError> {
+        let inner = ValueSerializer.serialize_map(len)?;
+        Ok(self.compound(None, Compound::Struct(inner)))
+    }
+
+    fn serialize_struct(
+        self,
+        name: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStruct, SeError> {
+        let inner = ValueSerializer.serialize_struct(name, len)?;
+        Ok(self.compound(Some(name), Compound::Struct(inner)))
+    }
+
+    fn serialize_struct_variant(
+        self,
+        name: &'static str,
+        index: u32,
+        variant: &'static str,
+        len: usize,
+    ) -> Result<Self::SerializeStructVariant, SeError> {
+        let inner = ValueSerializer.serialize_struct_variant(name, index, variant, len)?;
+        Ok(self.compound(Some(name), Compound::StructVariant(inner)))
+    }
+}
+
+impl<W: Write> RootSerializer<W> {
+    fn end(self) -> Result<(), SeError> {
+        let value = match self.inner {
+            // fields of tuple structs are repeated `item` elements
+            Compound::Seq(inner) => {
+                let items = ser::SerializeSeq::end(inner)?;
+                Value::Struct(vec![("item".to_string(), items)])
+            }
+            Compound::Struct(inner) => ser::SerializeStruct::end(inner)?,
+            Compound::SeqVariant(inner) => ser::SerializeTupleVariant::end(inner)?,
+            Compound::StructVariant(inner) => ser::SerializeStructVariant::end(inner)?,
+        };
+        self.serializer.write_root(value, self.type_name)
+    }
+}
+
+impl<W: Write> ser::SerializeTupleStruct for RootSerializer<W> {
+    type Ok = ();
+    type Error = SeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SeError> {
+        match &mut self.inner {
+            Compound::Seq(inner) => ser::SerializeSeq::serialize_element(inner, value),
+            _ => unreachable!(),
+        }
+    }
+
+    fn end(self) -> Result<(), SeError> {
+        RootSerializer::end(self)
+    }
+}
+
+impl<W: Write> ser::SerializeTupleVariant for RootSerializer<W> {
+    type Ok = ();
+    type Error = SeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SeError> {
+        match &mut self.inner {
+            Compound::SeqVariant(inner) => {
+                ser::SerializeTupleVariant::serialize_field(inner, value)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn end(self) -> Result<(), SeError> {
+        RootSerializer::end(self)
+    }
+}
+
+impl<W: Write> ser::SerializeMap for RootSerializer<W> {
+    type Ok = ();
+    type Error = SeError;
+
+    fn serialize_key<T: Serialize + ?Sized>(&mut self, key: &T) -> Result<(), SeError> {
+        match &mut self.inner {
+            Compound::Struct(inner) => ser::SerializeMap::serialize_key(inner, key),
+            _ => unreachable!(),
+        }
+    }
+
+    fn serialize_value<T: Serialize + ?Sized>(&mut self, value: &T) -> Result<(), SeError> {
+        match &mut self.inner {
+            Compound::Struct(inner) => ser::SerializeMap::serialize_value(inner, value),
+            _ => unreachable!(),
+        }
+    }
+
+    fn end(self) -> Result<(), SeError> {
+        RootSerializer::end(self)
+    }
+}
+
+impl<W: Write> ser::SerializeStruct for RootSerializer<W> {
+    type Ok = ();
+    type Error = SeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SeError> {
+        match &mut self.inner {
+            Compound::Struct(inner) => ser::SerializeStruct::serialize_field(inner, key, value),
+            _ => unreachable!(),
+        }
+    }
+
+    fn end(self) -> Result<(), SeError> {
+        RootSerializer::end(self)
+    }
+}
+
+impl<W: Write> ser::SerializeStructVariant for RootSerializer<W> {
+    type Ok = ();
+    type Error = SeError;
+
+    fn serialize_field<T: Serialize + ?Sized>(
+        &mut self,
+        key: &'static str,
+        value: &T,
+    ) -> Result<(), SeError> {
+        match &mut self.inner {
+            Compound::StructVariant(inner) => {
+                ser::SerializeStructVariant::serialize_field(inner, key, value)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn end(self) -> Result<(), SeError> {
+        RootSerializer::end(self)
+    }
+}
@@ -0,0 +1,57 @@
+use tokio::io::{AsyncBufRead, AsyncBufReadExt};
+
+use crate::dtd::Dtd;
+use crate::encoding::Decoder;
+use crate::errors::Xml5Error;
+use crate::tokenizer::emitter::DefaultEmitter;
+use crate::tokenizer::partial::PartialTokenizer;
+use crate::Token;
+
+/// Tokens read from an [`AsyncBufRead`] source, available with the `tokio`
+/// feature.
+///
+/// Tokens are returned as soon as they are complete, and reading only waits
+/// when the current token continues past the data received so far. Reading
+/// then resumes where it stopped, so a token that arrives in many reads is
+/// still read once.
+pub struct AsyncBufIterator<R, E> {
+    pub(crate) source: R,
+    pub(crate) tokenizer: PartialTokenizer<E>,
+}
+
+impl<R> AsyncBufIterator<R, DefaultEmitter>
+where
+    R: AsyncBufRead + Unpin,
+{
+    /// Declarations from the internal subset, if it was parsed.
+    pub fn dtd(&self) -> Option<&Dtd> {
+        self.tokenizer.dtd()
+    }
+
+    /// Decoder of the bytes read, as the BOM or the XML declaration tells, or
+    /// UTF-8 if neither does.
+    pub fn decoder(&self) -> Decoder {
+        self.tokenizer.decoder()
+    }
+
+    /// Next token, or `None` once the input is exhausted.
+    pub async fn next(&mut self) -> Option<Token<'static>> {
+        loop {
+            if let Some(token) = self.tokenizer.next_token() {
+                return Some(token);
+            } else if self.tokenizer.is_done() {
+                return None;
+            }
+            match self.source.fill_buf().await {
+                Ok([]) => self.tokenizer.finish(),
+                Ok(chunk) => {
+                    let size = chunk.len();
+                    self.tokenizer.feed(chunk);
+                    self.source.consume(size);
+                }
+                Err(err) if err.kind() == std::io::ErrorKind::Interrupted => (),
+                Err(err) => self.tokenizer.fail(Xml5Error::Io(err.to_string())),
+            }
+        }
+    }
+}
@@ -0,0 +1,120 @@
+#[cfg(feature = "encoding")]
+use std::borrow::Cow;
+use std::io::{self, BufRead};
+
+use crate::dtd::Dtd;
+use crate::encoding::Decoder;
+use crate::errors::Xml5Error;
+#[cfg(feature = "encoding")]
+use crate::events::EncodedText;
+use crate::tokenizer::emitter::DefaultEmitter;
+#[cfg(feature = "encoding")]
+use crate::tokenizer::encoding::Transcoder;
+use crate::tokenizer::partial::PartialTokenizer;
+use crate::Token;
+
+/// Tokens read from bytes in any supported encoding.
+///
+/// With the `encoding` feature, the encoding is detected from the BOM and the
+/// XML declaration, and the input is transcoded to UTF-8 as it is read. So
+/// tokens and their spans are always in UTF-8. Without the feature, input
+/// has to be UTF-8, and the first invalid byte ends it with an error.
+pub struct ByteIterator<R, E> {
+    pub(crate) source: R,
+    pub(crate) tokenizer: PartialTokenizer<E>,
+    #[cfg(feature = "encoding")]
+    pub(crate) transcoder: Transcoder,
+    /// transcoded chunk, or without the `encoding` feature, the start of a
+    /// character that is split across chunks
+    pub(crate) utf8: Vec<u8>,
+}
+
+impl<R> ByteIterator<R, DefaultEmitter> {
+    /// Declarations from the internal subset, if it was parsed.
+    pub fn dtd(&self) -> Option<&Dtd> {
+        self.tokenizer.dtd()
+    }
+
+    /// Decoder of the input, and whether its encoding came from the BOM, the
+    /// XML declaration or neither. Final once the first token is returned.
+    #[cfg(feature = "encoding")]
+    pub fn decoder(&self) -> Decoder {
+        self.transcoder.decoder()
+    }
+
+    /// Decoder of the input, which is UTF-8 without feature `encoding`, with
+    /// the BOM as its source if the input starts with one.
+    #[cfg(not(feature = "encoding"))]
+    pub fn decoder(&self) -> Decoder {
+        self.tokenizer.decoder()
+    }
+}
+
+impl<R: BufRead> ByteIterator<R, DefaultEmitter> {
+    /// Passes the source's buffer on to the tokenizer, `last` at the end of
+    /// input.
+    #[cfg(feature = "encoding")]
+    fn feed(&mut self, last: bool) {
+        if let Ok(chunk) = self.source.fill_buf() {
+            self.utf8.clear();
+            self.transcoder.decode(chunk, last, &mut self.utf8);
+            self.tokenizer.feed(&self.utf8);
+        }
+        if last {
+            self.tokenizer.finish();
+        }
+    }
+
+    #[cfg(not(feature = "encoding"))]
+    fn feed(&mut self, last: bool) {
+        if let Ok(chunk) = self.source.fill_buf() {
+            self.utf8.extend_from_slice(chunk);
+            let valid = match std::str::from_utf8(&self.utf8) {
+                Ok(_) => self.utf8.len(),
+                Err(err) => {
+                    if err.error_len().is_some() || last {
+                        self.tokenizer.fail(err.into());
+                    }
+                    err.valid_up_to()
+                }
+            };
+            self.tokenizer.feed(&self.utf8[..valid]);
+            self.utf8.drain(..valid);
+        }
+        if last {
+            self.tokenizer.finish();
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for ByteIterator<R, DefaultEmitter> {
+    type Item = Token<'static>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // transcoding removes the BOM before the tokenizer could see it
+            #[cfg(feature = "encoding")]
+            if let Some(bom) = self.transcoder.take_bom() {
+                return Some(Token::Bom(EncodedText {
+                    buf: Cow::Owned(bom),
+                    decoder: self.transcoder.decoder(),
+                    span: self.tokenizer.skip_bom(),
+                }));
+            }
+            if let Some(token) = self.tokenizer.next_token() {
+                return Some(token);
+            } else if self.tokenizer.is_done() {
+                return None;
+            }
+            match self.source.fill_buf() {
+                Ok(chunk) => {
+                    let size = chunk.len();
+                    self.feed(size == 0);
+                    self.source.consume(size);
+                }
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => self.tokenizer.fail(Xml5Error::Io(err.to_string())),
+            }
+        }
+    }
+}
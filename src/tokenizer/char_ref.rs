@@ -0,0 +1,302 @@
+use crate::dtd::{Dtd, Expansions};
+use crate::errors::{Xml5Error, Xml5Result};
+
+/// Entities that every XML document can use without declaring them.
+pub(crate) const PREDEFINED_ENTITIES: [(&[u8], &str); 5] = [
+    (b"amp", "&"),
+    (b"apos", "'"),
+    (b"gt", ">"),
+    (b"lt", "<"),
+    (b"quot", "\""),
+];
+
+/// Looks up value of one of the predefined entities, e.g. `lt` for `&lt;`.
+#[inline]
+pub(crate) fn predefined_entity(name: &[u8]) -> Option<&'static str> {
+    PREDEFINED_ENTITIES
+        .iter()
+        .find(|(entity, _)| *entity == name)
+        .map(|(_, value)| *value)
+}
+
+/// Resolves a named reference against the predefined entities, then the
+/// general entities declared in `dtd`, which are expanded with `expansions`.
+/// Returns `None` if the name is unknown.
+pub(crate) fn resolve_entity(
+    name: &[u8],
+    dtd: Option<&Dtd>,
+    expansions: &mut Expansions,
+) -> Option<Xml5Result<Vec<u8>>> {
+    match predefined_entity(name) {
+        Some(value) => Some(Ok(value.as_bytes().to_vec())),
+        None => expansions.expand_ref(dtd?, name),
+    }
+}
+
+/// Expands every reference in `text` into `out`, resolving named ones with
+/// `resolve`. Stops at the first reference that couldn't be expanded, or as
+/// soon as `out` grows past `limit`.
+pub(crate) fn expand_refs<F>(
+    text: &[u8],
+    mut resolve: F,
+    out: &mut Vec<u8>,
+    limit: usize,
+) -> Xml5Result<()>
+where
+    F: FnMut(&[u8]) -> Option<Xml5Result<Vec<u8>>>,
+{
+    let mut char_ref = CharRefTokenizer::default();
+    let mut pos = 0;
+    while let Some(amp) = text[pos..].iter().position(|&chr| chr == b'&') {
+        out.extend_from_slice(&text[pos..pos + amp]);
+        pos += amp + 1;
+        loop {
+            match char_ref.step(text.get(pos).copied(), &mut resolve) {
+                CharRefStatus::Consume => pos += 1,
+                CharRefStatus::Reconsume => (),
+                CharRefStatus::Done {
+                    consume,
+                    value,
+                    error,
+                } => {
+                    if let Some(err) = error {
+                        return Err(err);
+                    }
+                    out.extend_from_slice(&value);
+                    if consume {
+                        pos += 1;
+                    }
+                    break;
+                }
+            }
+        }
+        if out.len() > limit {
+            return Err(Xml5Error::EntityExpansionLimit);
+        }
+    }
+    out.extend_from_slice(&text[pos..]);
+    if out.len() > limit {
+        return Err(Xml5Error::EntityExpansionLimit);
+    }
+    Ok(())
+}
+
+/// Sub-tokenizer for character references like `&#123;`, `&#x1F600;` or
+/// `&lt;`. It is fed one byte at a time, starting with the byte right after
+/// the `&`, so it can be suspended at any point like the main state machine.
+#[derive(Default)]
+pub(crate) struct CharRefTokenizer {
+    state: CharRefState,
+    /// `x` or `X` if the numeric reference is hexadecimal
+    hex_marker: Option<u8>,
+    num: u32,
+    seen_digit: bool,
+    name: Vec<u8>,
+}
+
+#[derive(Default, Debug, Clone, Copy)]
+enum CharRefState {
+    #[default]
+    Begin,
+    Octothorpe,
+    Numeric,
+    Named,
+}
+
+pub(crate) enum CharRefStatus {
+    /// Byte is part of the reference, consume it and continue.
+    Consume,
+    /// Sub-state changed, same byte needs to be looked at again.
+    Reconsume,
+    /// Reference is complete. `value` holds the bytes it stands for, or the
+    /// raw text of the reference if it couldn't be resolved.
+    Done {
+        consume: bool,
+        value: Vec<u8>,
+        error: Option<Xml5Error>,
+    },
+}
+
+impl CharRefTokenizer {
+    /// Steps over `chr`, using `resolve` to look up the value of a named
+    /// reference once its `;` is reached.
+    pub(crate) fn step<F>(&mut self, chr: Option<u8>, resolve: F) -> CharRefStatus
+    where
+        F: FnOnce(&[u8]) -> Option<Xml5Result<Vec<u8>>>,
+    {
+        match self.state {
+            CharRefState::Begin => match chr {
+                Some(b'#') => {
+                    self.state = CharRefState::Octothorpe;
+                    CharRefStatus::Consume
+                }
+                Some(x) if is_name_start(x) => {
+                    self.state = CharRefState::Named;
+                    CharRefStatus::Reconsume
+                }
+                // Not a reference at all, `&` is kept as is
+                _ => self.done(false, b"&".to_vec(), None),
+            },
+            CharRefState::Octothorpe => {
+                self.state = CharRefState::Numeric;
+                match chr {
+                    Some(x @ b'x') | Some(x @ b'X') => {
+                        self.hex_marker = Some(x);
+                        CharRefStatus::Consume
+                    }
+                    _ => CharRefStatus::Reconsume,
+                }
+            }
+            CharRefState::Numeric => {
+                let base = if self.hex_marker.is_some() { 16 } else { 10 };
+                match chr.and_then(|x| (x as char).to_digit(base)) {
+                    Some(digit) => {
+                        self.seen_digit = true;
+                        self.num = self.num.saturating_mul(base).saturating_add(digit);
+                        CharRefStatus::Consume
+                    }
+                    None => self.finish_numeric(chr == Some(b';')),
+                }
+            }
+            CharRefState::Named => match chr {
+                Some(x) if is_name_char(x) => {
+                    self.name.push(x);
+                    CharRefStatus::Consume
+                }
+                Some(b';') => match resolve(&self.name) {
+                    Some(Ok(value)) => self.done(true, value, None),
+                    unresolved => {
+                        let error = match unresolved {
+                            Some(Err(err)) => err,
+                            _ => Xml5Error::UnknownNamedCharRef,
+                        };
+                        let mut value = self.raw_named();
+                        value.push(b';');
+                        self.done(true, value, Some(error))
+                    }
+                },
+                _ => {
+                    let value = self.raw_named();
+                    self.done(false, value, Some(Xml5Error::UnterminatedCharRef))
+                }
+            },
+        }
+    }
+
+    fn finish_numeric(&mut self, terminated: bool) -> CharRefStatus {
+        if !self.seen_digit {
+            let mut value = b"&#".to_vec();
+            value.extend(self.hex_marker);
+            return self.done(false, value, Some(Xml5Error::UnterminatedCharRef));
+        }
+
+        let (chr, error) = match char::from_u32(self.num) {
+            Some(chr) if chr != '\0' => (chr, None),
+            _ => (
+                char::REPLACEMENT_CHARACTER,
+                Some(Xml5Error::CharRefOutOfRange(self.num)),
+            ),
+        };
+        let error = match error {
+            None if !terminated => Some(Xml5Error::UnterminatedCharRef),
+            _ => error,
+        };
+        let mut buf = [0; 4];
+        let value = chr.encode_utf8(&mut buf).as_bytes().to_vec();
+        self.done(terminated, value, error)
+    }
+
+    fn raw_named(&self) -> Vec<u8> {
+        let mut value = Vec::with_capacity(self.name.len() + 2);
+        value.push(b'&');
+        value.extend_from_slice(&self.name);
+        value
+    }
+
+    fn done(&mut self, consume: bool, value: Vec<u8>, error: Option<Xml5Error>) -> CharRefStatus {
+        self.state = CharRefState::Begin;
+        self.hex_marker = None;
+        self.num = 0;
+        self.seen_digit = false;
+        self.name.clear();
+        CharRefStatus::Done {
+            consume,
+            value,
+            error,
+        }
+    }
+}
+
+#[inline]
+fn is_name_start(chr: u8) -> bool {
+    chr.is_ascii_alphabetic() || chr == b'_' || chr == b':' || chr >= 0x80
+}
+
+#[inline]
+fn is_name_char(chr: u8) -> bool {
+    is_name_start(chr) || chr.is_ascii_digit() || chr == b'-' || chr == b'.'
+}
+
+#[cfg(test)]
+fn resolve(input: &str) -> (String, Option<Xml5Error>) {
+    let mut tokenizer = CharRefTokenizer::default();
+    let mut expansions = Expansions::default();
+    let mut bytes = input.bytes().peekable();
+    loop {
+        match tokenizer.step(bytes.peek().copied(), |name| {
+            resolve_entity(name, None, &mut expansions)
+        }) {
+            CharRefStatus::Consume => {
+                bytes.next();
+            }
+            CharRefStatus::Reconsume => (),
+            CharRefStatus::Done { value, error, .. } => {
+                return (String::from_utf8(value).unwrap(), error);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_numeric_char_ref() {
+    assert_eq!(("{".to_string(), None), resolve("#123;"));
+    assert_eq!(("😀".to_string(), None), resolve("#x1F600;"));
+    assert_eq!(("😀".to_string(), None), resolve("#X1f600;"));
+    assert_eq!(
+        ("{".to_string(), Some(Xml5Error::UnterminatedCharRef)),
+        resolve("#123 ")
+    );
+    assert_eq!(
+        ("&#x".to_string(), Some(Xml5Error::UnterminatedCharRef)),
+        resolve("#x;")
+    );
+    assert_eq!(
+        (
+            "\u{FFFD}".to_string(),
+            Some(Xml5Error::CharRefOutOfRange(0x110000))
+        ),
+        resolve("#x110000;")
+    );
+    assert_eq!(
+        (
+            "\u{FFFD}".to_string(),
+            Some(Xml5Error::CharRefOutOfRange(0xD800))
+        ),
+        resolve("#xD800;")
+    );
+}
+
+#[test]
+fn test_named_char_ref() {
+    assert_eq!(("<".to_string(), None), resolve("lt;"));
+    assert_eq!(("\"".to_string(), None), resolve("quot;"));
+    assert_eq!(
+        ("&foo;".to_string(), Some(Xml5Error::UnknownNamedCharRef)),
+        resolve("foo;")
+    );
+    assert_eq!(
+        ("&amp".to_string(), Some(Xml5Error::UnterminatedCharRef)),
+        resolve("amp ")
+    );
+    assert_eq!(("&".to_string(), None), resolve(" "));
+}
@@ -0,0 +1,220 @@
+use std::collections::VecDeque;
+
+use crate::errors::Xml5Error;
+use crate::tokenizer::emitter::{SpanTokens, Spans};
+use crate::tokenizer::position::Span;
+
+/// Options for the tokens the tokenizer returns, used with
+/// [`Tokenizer::with_config`](crate::Tokenizer::with_config).
+///
+/// They apply to the iterators of [`Token`](crate::Token)s, and not to custom
+/// emitters or [`span_tokens`](crate::Tokenizer::span_tokens), which get
+/// tokens as they are read.
+#[derive(Clone, Debug)]
+pub struct TokenizerConfig {
+    trim_text: bool,
+    drop_comments: bool,
+    drop_pis: bool,
+    expand_empty_tags: bool,
+    cdata_as_text: bool,
+    coalesce_text: bool,
+    report_errors: bool,
+}
+
+impl Default for TokenizerConfig {
+    fn default() -> Self {
+        TokenizerConfig {
+            trim_text: false,
+            drop_comments: false,
+            drop_pis: false,
+            expand_empty_tags: false,
+            cdata_as_text: false,
+            coalesce_text: false,
+            report_errors: true,
+        }
+    }
+}
+
+impl TokenizerConfig {
+    /// Config that returns every token as it is read.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Drops text that is only whitespace, like indentation between tags.
+    /// This is decided for each run of text between other tokens, however
+    /// it's split into text tokens, e.g. at references, so whitespace next to
+    /// other text in the run is kept.
+    pub fn trim_text(mut self, enabled: bool) -> Self {
+        self.trim_text = enabled;
+        self
+    }
+
+    pub fn drop_comments(mut self, enabled: bool) -> Self {
+        self.drop_comments = enabled;
+        self
+    }
+
+    /// Drops processing instructions. The XML declaration is kept.
+    pub fn drop_pis(mut self, enabled: bool) -> Self {
+        self.drop_pis = enabled;
+        self
+    }
+
+    /// Returns `<a/>` as a start tag followed by an end tag.
+    pub fn expand_empty_tags(mut self, enabled: bool) -> Self {
+        self.expand_empty_tags = enabled;
+        self
+    }
+
+    /// Returns CDATA sections as text.
+    pub fn cdata_as_text(mut self, enabled: bool) -> Self {
+        self.cdata_as_text = enabled;
+        self
+    }
+
+    /// Returns adjacent text and CDATA sections, e.g. text around references,
+    /// as a single text token. The text borrows the input when its parts are
+    /// next to each other in it, and is copied otherwise. With
+    /// [`trim_text`](Self::trim_text), it's dropped when it's only whitespace.
+    pub fn coalesce_text(mut self, enabled: bool) -> Self {
+        self.coalesce_text = enabled;
+        self
+    }
+
+    /// Whether to return errors the tokenizer recovers from. Errors that stop
+    /// it, like failing to read or decode the input, are always returned.
+    pub fn report_errors(mut self, enabled: bool) -> Self {
+        self.report_errors = enabled;
+        self
+    }
+}
+
+/// Applies the config to tokens as they are read.
+///
+/// Text tokens next to each other, with the errors found between them, make
+/// up a run of text, which ends at any other token that isn't dropped. Runs
+/// are coalesced and trimmed as a whole, however they are split into tokens.
+#[derive(Default)]
+pub(crate) struct TokenFilter {
+    pub(crate) config: TokenizerConfig,
+    /// tokens to return, with the spans they were read from
+    ready: VecDeque<(SpanTokens, Span)>,
+    /// text being coalesced, until the run ends
+    run: Option<(Spans, Span)>,
+    /// tokens of the run held back until it ends: errors behind coalesced
+    /// text, and text that is only whitespace so far with the errors in it
+    held: Vec<(SpanTokens, Span)>,
+    /// whether the run has text other than whitespace
+    in_text: bool,
+}
+
+impl TokenFilter {
+    #[inline]
+    pub(crate) fn pop(&mut self) -> Option<(SpanTokens, Span)> {
+        self.ready.pop_front()
+    }
+
+    /// Adds a token read from `input`, which can then be popped unless it's
+    /// dropped or held back.
+    pub(crate) fn push(&mut self, token: SpanTokens, span: Span, input: &[u8]) {
+        let config = &self.config;
+        let token = match token {
+            SpanTokens::Comment(_) if config.drop_comments => return,
+            SpanTokens::PiData { .. } if config.drop_pis => return,
+            SpanTokens::Error(err) if !config.report_errors && !is_fatal(&err) => return,
+            SpanTokens::CData(text) if config.cdata_as_text => SpanTokens::Text(text),
+            token => token,
+        };
+        match token {
+            SpanTokens::Text(text) | SpanTokens::CData(text) if self.config.coalesce_text => {
+                match &mut self.run {
+                    Some((run, run_span)) => {
+                        run.append(text);
+                        run_span.end = span.end;
+                    }
+                    None => self.run = Some((text, span)),
+                }
+            }
+            SpanTokens::Text(text) if self.config.trim_text && !self.in_text => {
+                if is_whitespace(&text, input) {
+                    // held text can outlive the input it was read from
+                    let text = Spans::owned(text.bytes(input).into_owned());
+                    self.held.push((SpanTokens::Text(text), span));
+                } else {
+                    self.in_text = true;
+                    self.ready.extend(self.held.drain(..));
+                    self.ready.push_back((SpanTokens::Text(text), span));
+                }
+            }
+            // errors don't split text, and stay behind the text before them
+            SpanTokens::Error(err) if !is_fatal(&err) => {
+                let token = (SpanTokens::Error(err), span);
+                if self.run.is_some() || !self.held.is_empty() {
+                    self.held.push(token);
+                } else {
+                    self.ready.push_back(token);
+                }
+            }
+            SpanTokens::Text(text) => self.ready.push_back((SpanTokens::Text(text), span)),
+            SpanTokens::StartTag {
+                name,
+                attrs,
+                self_close: true,
+            } if self.config.expand_empty_tags => {
+                self.end_run(input);
+                let end = SpanTokens::EndTag(Some(name.clone()));
+                self.ready.push_back((
+                    SpanTokens::StartTag {
+                        name,
+                        attrs,
+                        self_close: false,
+                    },
+                    span,
+                ));
+                self.ready.push_back((end, span));
+            }
+            token => {
+                self.end_run(input);
+                self.ready.push_back((token, span));
+            }
+        }
+    }
+
+    /// Copies held text out of `input`, before it's changed.
+    pub(crate) fn detach(&mut self, input: &[u8]) {
+        if let Some((run, _)) = &mut self.run {
+            run.detach(input);
+        }
+    }
+
+    /// Returns what was held back of the run of text, which ends.
+    fn end_run(&mut self, input: &[u8]) {
+        if let Some((text, span)) = self.run.take() {
+            if !(self.config.trim_text && is_whitespace(&text, input)) {
+                self.ready.push_back((SpanTokens::Text(text), span));
+            }
+        }
+        // text that is still held is only whitespace
+        let errors = self
+            .held
+            .drain(..)
+            .filter(|(token, _)| !matches!(token, SpanTokens::Text(_)));
+        self.ready.extend(errors);
+        self.in_text = false;
+    }
+}
+
+/// Errors after which the tokenizer stops.
+fn is_fatal(err: &Xml5Error) -> bool {
+    matches!(
+        err,
+        Xml5Error::Io(_) | Xml5Error::Utf8(_) | Xml5Error::NonDecodable(_)
+    )
+}
+
+fn is_whitespace(text: &Spans, input: &[u8]) -> bool {
+    text.bytes(input)
+        .iter()
+        .all(|chr| matches!(chr, b' ' | b'\t' | b'\r' | b'\n'))
+}
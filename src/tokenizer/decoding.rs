@@ -134,7 +134,7 @@ impl Decoder {
 pub fn decode<'b>(bytes: &'b [u8], encoding: &'static Encoding) -> Xml5Result<Cow<'b, str>> {
     encoding
         .decode_without_bom_handling_and_without_replacement(bytes)
-        .ok_or(Xml5Result::NonDecodable(None))
+        .ok_or(crate::errors::Xml5Error::NonDecodable(None))
 }
 
 /// Decodes a slice with an unknown encoding, removing the BOM if it is present
@@ -155,9 +155,9 @@ pub fn decode_with_bom_removal<'b>(bytes: &'b [u8]) -> Xml5Result<Cow<'b, str>>
 fn split_at_bom<'b>(bytes: &'b [u8], encoding: &'static Encoding) -> (&'b [u8], &'b [u8]) {
     if encoding == UTF_8 && bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
         bytes.split_at(3)
-    } else if encoding == UTF_16LE && bytes.starts_with(&[0xFF, 0xFE]) {
-        bytes.split_at(2)
-    } else if encoding == UTF_16BE && bytes.starts_with(&[0xFE, 0xFF]) {
+    } else if (encoding == UTF_16LE && bytes.starts_with(&[0xFF, 0xFE]))
+        || (encoding == UTF_16BE && bytes.starts_with(&[0xFE, 0xFF]))
+    {
         bytes.split_at(2)
     } else {
         (&[], bytes)
@@ -1,51 +1,100 @@
+use std::borrow::Cow;
 use std::collections::VecDeque;
 use std::mem;
-use std::ops::Range;
+use std::slice;
 
 use crate::errors::Xml5Error;
+use crate::tokenizer::position::{Position, Span};
 use crate::tokenizer::DoctypeKind;
-use crate::Tokenizer;
 
+/// Receiver of what the tokenizer reads, which builds tokens out of it.
+///
+/// Parts of tokens are passed as `start..end` byte ranges of the input, or as
+/// bytes to append (the `_now` methods) where the input was changed, like in
+/// expanded references. A token is started by one of the `create_` methods,
+/// and finished by the matching `emit_` method. Finished tokens are taken out
+/// with [`pop_token`](Self::pop_token), before the tokenizer reads further.
+///
+/// Positions are passed to [`set_token_start`](Self::set_token_start) and
+/// [`set_position`](Self::set_position), for emitters that record where
+/// tokens were read from.
+///
+/// Use one with [`Tokenizer::with_emitter`](crate::Tokenizer::with_emitter).
 pub trait Emitter {
     type Output;
 
+    /// Next finished token, in the order they were emitted.
     fn pop_token(&mut self) -> Option<Self::Output>;
+
+    /// Marks where the next token starts, be it text or markup.
+    #[inline]
+    fn set_token_start(&mut self, _position: Position) {}
+    /// Marks how far the input has been read, after each step of the
+    /// tokenizer. Tokens and errors emitted in the step end there.
+    #[inline]
+    fn set_position(&mut self, _position: Position) {}
+
+    /// Starts a start tag, which becomes empty with `set_empty_tag`.
     fn create_tag(&mut self);
+    /// Appends to the name of the start or end tag.
     fn append_tag(&mut self, start: usize, end: usize);
+    /// Starts an end tag. One without a name is `</>`.
     fn create_end_tag(&mut self);
     fn set_empty_tag(&mut self);
+    /// Starts an attribute of the start tag.
     fn create_attr(&mut self);
     fn attr_values(&mut self, start: usize, end: usize);
     fn attr_names(&mut self, start: usize, end: usize);
+    fn attr_values_now<T: IntoBytes>(&mut self, x: T);
 
+    /// Starts a processing instruction.
     fn create_pi_tag(&mut self);
     fn pi_data(&mut self, start: usize, end: usize);
+    fn pi_data_now<T: IntoBytes>(&mut self, x: T);
     fn pi_target(&mut self, start: usize, end: usize);
 
     fn create_doctype(&mut self);
+    /// Appends to the identifier started last by `clear_doctype_id`.
     fn doctype_id(&mut self, start: usize, end: usize);
     fn doctype_name(&mut self, start: usize, end: usize);
-    fn doctype_name_now(&mut self, chr: u8);
+    /// Starts the public or system identifier, as empty.
     fn clear_doctype_id(&mut self, doctype: DoctypeKind);
+    /// Starts the internal subset, as empty.
+    fn create_doctype_subset(&mut self);
+    fn doctype_subset(&mut self, start: usize, end: usize);
 
     fn create_comment_token(&mut self);
     fn emit_comment(&mut self);
     fn to_comment(&mut self, start: usize, end: usize);
     fn to_comment_now<T: IntoBytes>(&mut self, x: T);
 
+    fn create_cdata(&mut self);
+    fn cdata(&mut self, start: usize, end: usize);
+    fn cdata_now<T: IntoBytes>(&mut self, x: T);
+    fn emit_cdata(&mut self);
+
+    /// Emits the byte order mark, which was skipped in the input.
+    fn emit_bom(&mut self);
     fn emit_eof(&mut self);
     fn emit_pi(&mut self);
     fn emit_error(&mut self, err: Xml5Error);
+    /// Emits text, which isn't part of any other token.
     fn emit_chars(&mut self, start: usize, end: usize);
     fn emit_chars_now<T: IntoBytes>(&mut self, x: T);
     fn emit_end_tag(&mut self);
+    /// Emits the start or empty tag, with its attributes.
     fn emit_tag(&mut self);
     fn emit_doctype(&mut self);
 
+    /// Starts the value of a pseudo-attribute of the XML declaration, as
+    /// empty. The declaration itself starts with the first of them.
     fn set_xml_declaration(&mut self, attr_name: XmlDeclAttr);
+    /// Appends to the value of the pseudo-attribute started last.
     fn emit_decl_value(&mut self, start: usize, end: usize);
+    fn emit_xml_decl(&mut self);
 }
 
+/// Pseudo-attribute of the XML declaration.
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum XmlDeclAttr {
     Version,
@@ -53,6 +102,7 @@ pub enum XmlDeclAttr {
     Standalone,
 }
 
+/// Bytes appended to a token where they differ from the input.
 pub trait IntoBytes {
     fn to_bytes(&self) -> Vec<u8>;
 }
@@ -69,21 +119,70 @@ impl IntoBytes for &str {
     }
 }
 
+impl IntoBytes for &[u8] {
+    fn to_bytes(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+}
+
+/// Emitter of [`SpanTokens`] with the [`Span`]s they were read from, which
+/// the tokenizer's iterators turn into [`Token`](crate::Token)s.
 #[derive(Default)]
 pub struct DefaultEmitter {
-    tokens: VecDeque<SpanTokens>,
-    current_token_type: CurrentToken,
+    tokens: VecDeque<(SpanTokens, Span)>,
+    /// where the token being read started
+    token_start: Position,
+    /// number of tokens at the back of `tokens` whose span has no end yet
+    unended: usize,
     current_token_bounds: Spans,
-    encoding: Spans,
     current_token_secondary_bound: Spans,
     current_attrs: Vec<(Spans, Spans)>,
+    current_doctype: DoctypeSpans,
+    current_decl: DeclSpans,
+    self_close: bool,
 }
 
+/// Pseudo-attributes of the XML declaration, which may be missing.
+#[derive(Default)]
+struct DeclSpans {
+    version: Option<Spans>,
+    encoding: Option<Spans>,
+    standalone: Option<Spans>,
+    /// pseudo-attribute that `emit_decl_value` appends to
+    current_attr: Option<XmlDeclAttr>,
+}
+
+/// Parts of the doctype that may be missing, as opposed to being empty.
+#[derive(Default)]
+struct DoctypeSpans {
+    public_id: Option<Spans>,
+    system_id: Option<Spans>,
+    internal_subset: Option<Spans>,
+    /// identifier that `doctype_id` appends to
+    current_id: Option<DoctypeKind>,
+}
+
+/// Token as byte ranges of the input, instead of the bytes themselves.
+///
+/// Parts that are missing are `None`, as opposed to empty. An end tag
+/// without a name is `</>`.
+#[derive(Debug)]
 pub enum SpanTokens {
+    Bom,
     Text(Spans),
     CData(Spans),
-    Decl(Spans),
+    Decl {
+        version: Option<Spans>,
+        encoding: Option<Spans>,
+        standalone: Option<Spans>,
+    },
     Comment(Spans),
+    DocType {
+        name: Spans,
+        public_id: Option<Spans>,
+        system_id: Option<Spans>,
+        internal_subset: Option<Spans>,
+    },
     EndTag(Option<Spans>),
     PiData {
         target: Spans,
@@ -98,205 +197,473 @@ pub enum SpanTokens {
     Eof,
 }
 
-#[derive(Default, Debug)]
+/// Bytes of a part of a token, as ranges of the input and bytes that
+/// differ from it, in order.
+///
+/// A single part, which is the usual case, is kept inline, so only parts
+/// that differ from the input allocate.
+#[derive(Clone, Default, Debug)]
 pub struct Spans {
-    pub(crate) data: Vec<Mix>,
+    parts: Parts,
+}
+
+#[derive(Clone, Default, Debug)]
+enum Parts {
+    #[default]
+    Empty,
+    One(Mix),
+    Many(Vec<Mix>),
 }
 
 impl Spans {
+    /// Spans of the single range `start..end`.
     #[inline]
-    pub fn to_range(&self) -> Option<(usize, usize)> {
-        if self.data.len() == 1 {
-            if let Some(Mix::Range(start, end)) = self.data.first() {
-                return Some((*start, *end));
+    pub fn range(start: usize, end: usize) -> Spans {
+        Spans {
+            parts: Parts::One(Mix::Range(start, end)),
+        }
+    }
+
+    /// Spans of just `bytes`, which differ from the input.
+    #[inline]
+    pub fn owned(bytes: Vec<u8>) -> Spans {
+        Spans {
+            parts: Parts::One(Mix::Owned(bytes)),
+        }
+    }
+
+    /// Ranges and bytes, in order.
+    #[inline]
+    pub fn parts(&self) -> &[Mix] {
+        match &self.parts {
+            Parts::Empty => &[],
+            Parts::One(part) => slice::from_ref(part),
+            Parts::Many(parts) => parts,
+        }
+    }
+
+    /// Bytes of these spans of `input`, borrowed if they are a single range.
+    pub fn bytes<'i>(&self, input: &'i [u8]) -> Cow<'i, [u8]> {
+        match self.parts() {
+            [] => Cow::Borrowed(&[]),
+            [Mix::Range(start, end)] => Cow::Borrowed(&input[*start..*end]),
+            parts => {
+                let mut bytes = vec![];
+                for part in parts {
+                    part.extend_into(input, &mut bytes);
+                }
+                Cow::Owned(bytes)
             }
-        };
-        None
+        }
+    }
+
+    /// Like [`bytes`](Self::bytes), but owned bytes of a single part are
+    /// moved instead of copied.
+    pub fn into_bytes(self, input: &[u8]) -> Cow<'_, [u8]> {
+        match self.parts {
+            Parts::Empty => Cow::Borrowed(&[]),
+            Parts::One(Mix::Range(start, end)) => Cow::Borrowed(&input[start..end]),
+            Parts::One(Mix::Owned(bytes)) => Cow::Owned(bytes),
+            Parts::Many(parts) => {
+                let mut bytes = vec![];
+                for part in &parts {
+                    part.extend_into(input, &mut bytes);
+                }
+                Cow::Owned(bytes)
+            }
+        }
     }
 
+    /// The range of the input, if the spans are exactly one.
+    #[inline]
+    pub fn to_range(&self) -> Option<(usize, usize)> {
+        match self.parts {
+            Parts::One(Mix::Range(start, end)) => Some((start, end)),
+            _ => None,
+        }
+    }
+
+    /// Appends a range, which is merged with the last one if they meet.
     #[inline(always)]
     pub fn add_span(&mut self, start: usize, end: usize) {
-        if let Some(Mix::Range(_, r2)) = self.data.last_mut() {
-            if &start == r2 {
-                *r2 = end;
+        if let Some(Mix::Range(_, last_end)) = self.last_mut() {
+            if *last_end == start {
+                *last_end = end;
+                return;
             }
+        }
+        self.push(Mix::Range(start, end));
+    }
+
+    /// Appends bytes that differ from the input.
+    #[inline(always)]
+    pub fn add_owned(&mut self, mut bytes: Vec<u8>) {
+        if let Some(Mix::Owned(last)) = self.last_mut() {
+            last.append(&mut bytes);
         } else {
-            self.data.push(Mix::Range(start, end));
+            self.push(Mix::Owned(bytes));
+        }
+    }
+
+    /// Appends the parts of `other`, merging them where they meet.
+    pub fn append(&mut self, other: Spans) {
+        match other.parts {
+            Parts::Empty => {}
+            Parts::One(part) => self.add_part(part),
+            Parts::Many(parts) => parts.into_iter().for_each(|part| self.add_part(part)),
+        }
+    }
+
+    /// Moves the ranges back by `offset`, when that much of the input
+    /// before them is dropped.
+    pub(crate) fn rebase(&mut self, offset: usize) {
+        let parts = match &mut self.parts {
+            Parts::Empty => return,
+            Parts::One(part) => slice::from_mut(part),
+            Parts::Many(parts) => parts,
+        };
+        for part in parts {
+            if let Mix::Range(start, end) = part {
+                *start -= offset;
+                *end -= offset;
+            }
+        }
+    }
+
+    /// Copies the ranges out of `input`, so the spans no longer refer to it.
+    /// Bytes that were copied before are moved, not copied again.
+    pub(crate) fn detach(&mut self, input: &[u8]) {
+        if !self.parts().iter().any(Mix::is_borrowed) {
+            return;
+        }
+        let parts = match mem::take(&mut self.parts) {
+            Parts::Empty => vec![],
+            Parts::One(part) => vec![part],
+            Parts::Many(parts) => parts,
+        };
+        for part in parts {
+            match part {
+                Mix::Range(start, end) => self.add_owned(input[start..end].to_vec()),
+                Mix::Owned(bytes) => self.add_owned(bytes),
+            }
+        }
+    }
+
+    fn add_part(&mut self, part: Mix) {
+        match part {
+            Mix::Range(start, end) => self.add_span(start, end),
+            Mix::Owned(bytes) => self.add_owned(bytes),
+        }
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        matches!(self.parts, Parts::Empty)
+    }
+
+    #[inline(always)]
+    fn last_mut(&mut self) -> Option<&mut Mix> {
+        match &mut self.parts {
+            Parts::Empty => None,
+            Parts::One(part) => Some(part),
+            Parts::Many(parts) => parts.last_mut(),
         }
     }
+
+    fn push(&mut self, part: Mix) {
+        self.parts = match mem::take(&mut self.parts) {
+            Parts::Empty => Parts::One(part),
+            Parts::One(first) => Parts::Many(vec![first, part]),
+            Parts::Many(mut parts) => {
+                parts.push(part);
+                Parts::Many(parts)
+            }
+        };
+    }
 }
 
-#[derive(Debug)]
+/// Part of [`Spans`].
+#[derive(Clone, Debug)]
 pub enum Mix {
+    /// `start..end` byte range of the input.
     Range(usize, usize),
+    /// Bytes that aren't in the input as they are, like expanded references.
     Owned(Vec<u8>),
 }
 
 impl Mix {
     #[inline(always)]
     pub const fn is_borrowed(&self) -> bool {
+        matches!(self, Mix::Range(..))
+    }
+
+    #[inline]
+    fn extend_into(&self, input: &[u8], bytes: &mut Vec<u8>) {
         match self {
-            Mix::Owned(_) => true,
-            _ => false,
+            Mix::Range(start, end) => bytes.extend_from_slice(&input[*start..*end]),
+            Mix::Owned(owned) => bytes.extend_from_slice(owned),
+        }
+    }
+}
+
+impl DefaultEmitter {
+    /// Queues a token, which ends where the current step does.
+    #[inline]
+    fn push(&mut self, token: SpanTokens) {
+        let span = Span {
+            start: self.token_start,
+            end: self.token_start,
+        };
+        self.tokens.push_back((token, span));
+        self.unended += 1;
+    }
+
+    /// Moves the token being read back by `offset`, when that much of the
+    /// input before it is dropped. Finished tokens have to be popped first.
+    pub(crate) fn rebase(&mut self, offset: usize) {
+        debug_assert!(self.tokens.is_empty());
+        self.current_token_bounds.rebase(offset);
+        self.current_token_secondary_bound.rebase(offset);
+        for (name, value) in &mut self.current_attrs {
+            name.rebase(offset);
+            value.rebase(offset);
+        }
+        let doctype = &mut self.current_doctype;
+        let decl = &mut self.current_decl;
+        for spans in [
+            &mut doctype.public_id,
+            &mut doctype.system_id,
+            &mut doctype.internal_subset,
+            &mut decl.version,
+            &mut decl.encoding,
+            &mut decl.standalone,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            spans.rebase(offset);
         }
     }
 }
 
 impl Emitter for DefaultEmitter {
-    type Output = SpanTokens;
+    type Output = (SpanTokens, Span);
 
-    fn pop_token(&mut self) -> Option<SpanTokens> {
+    fn pop_token(&mut self) -> Option<(SpanTokens, Span)> {
         self.tokens.pop_front()
     }
 
+    #[inline]
+    fn set_token_start(&mut self, position: Position) {
+        self.token_start = position;
+    }
+
+    #[inline]
+    fn set_position(&mut self, position: Position) {
+        let unended = mem::take(&mut self.unended);
+        for (_, span) in self.tokens.iter_mut().rev().take(unended) {
+            span.end = position;
+        }
+    }
+
     fn create_tag(&mut self) {}
 
     fn append_tag(&mut self, start: usize, end: usize) {
         self.current_token_bounds.add_span(start, end);
     }
 
-    fn create_end_tag(&mut self) {
-        self.current_token_type = CurrentToken::EndTag;
-    }
+    fn create_end_tag(&mut self) {}
 
     fn set_empty_tag(&mut self) {
-        todo!()
+        self.self_close = true;
     }
 
     fn create_attr(&mut self) {
-        todo!()
+        self.current_attrs.push(Default::default());
     }
 
     fn attr_values(&mut self, start: usize, end: usize) {
-        todo!()
+        if let Some((_, value)) = self.current_attrs.last_mut() {
+            value.add_span(start, end);
+        }
     }
 
     fn attr_names(&mut self, start: usize, end: usize) {
-        todo!()
+        if let Some((name, _)) = self.current_attrs.last_mut() {
+            name.add_span(start, end);
+        }
     }
 
-    fn create_pi_tag(&mut self) {
-        self.current_token_type = CurrentToken::ProcessingInstruction;
+    fn attr_values_now<T: IntoBytes>(&mut self, x: T) {
+        if let Some((_, value)) = self.current_attrs.last_mut() {
+            value.add_owned(x.to_bytes());
+        }
     }
 
+    fn create_pi_tag(&mut self) {}
+
     fn pi_data(&mut self, start: usize, end: usize) {
         self.current_token_bounds.add_span(start, end);
     }
 
+    fn pi_data_now<T: IntoBytes>(&mut self, x: T) {
+        self.current_token_bounds.add_owned(x.to_bytes());
+    }
+
     fn pi_target(&mut self, start: usize, end: usize) {
         self.current_token_secondary_bound.add_span(start, end);
     }
 
-    fn create_doctype(&mut self) {
-        self.current_token_type = CurrentToken::Doctype;
-    }
+    fn create_doctype(&mut self) {}
 
     fn doctype_id(&mut self, start: usize, end: usize) {
-        self.current_token_bounds.add_span(start, end);
+        let doctype = &mut self.current_doctype;
+        let id = match doctype.current_id {
+            Some(DoctypeKind::Public) => &mut doctype.public_id,
+            Some(DoctypeKind::System) => &mut doctype.system_id,
+            None => return,
+        };
+        id.get_or_insert_with(Spans::default).add_span(start, end);
     }
 
     fn doctype_name(&mut self, start: usize, end: usize) {
         self.current_token_secondary_bound.add_span(start, end);
     }
 
-    fn doctype_name_now(&mut self, chr: u8) {
-        todo!()
+    fn clear_doctype_id(&mut self, doctype: DoctypeKind) {
+        let current = &mut self.current_doctype;
+        match doctype {
+            DoctypeKind::Public => current.public_id = Some(Spans::default()),
+            DoctypeKind::System => current.system_id = Some(Spans::default()),
+        }
+        current.current_id = Some(doctype);
     }
 
-    fn clear_doctype_id(&mut self, doctype: DoctypeKind) {
-        todo!()
+    fn create_doctype_subset(&mut self) {
+        self.current_doctype.internal_subset = Some(Spans::default());
     }
 
-    fn create_comment_token(&mut self) {
-        todo!()
+    fn doctype_subset(&mut self, start: usize, end: usize) {
+        if let Some(subset) = &mut self.current_doctype.internal_subset {
+            subset.add_span(start, end);
+        }
     }
 
+    fn create_comment_token(&mut self) {}
+
     fn emit_comment(&mut self) {
-        todo!()
+        let text = mem::take(&mut self.current_token_bounds);
+        self.push(SpanTokens::Comment(text));
     }
 
     fn to_comment(&mut self, start: usize, end: usize) {
-        todo!()
+        self.current_token_bounds.add_span(start, end);
     }
 
     fn to_comment_now<T: IntoBytes>(&mut self, x: T) {
-        todo!()
+        self.current_token_bounds.add_owned(x.to_bytes());
+    }
+
+    fn create_cdata(&mut self) {}
+
+    fn cdata(&mut self, start: usize, end: usize) {
+        self.current_token_bounds.add_span(start, end);
+    }
+
+    fn cdata_now<T: IntoBytes>(&mut self, x: T) {
+        self.current_token_bounds.add_owned(x.to_bytes());
+    }
+
+    fn emit_cdata(&mut self) {
+        let text = mem::take(&mut self.current_token_bounds);
+        self.push(SpanTokens::CData(text));
+    }
+
+    fn emit_bom(&mut self) {
+        self.push(SpanTokens::Bom);
     }
 
     fn emit_eof(&mut self) {
-        self.tokens.push_back(SpanTokens::Eof);
+        self.push(SpanTokens::Eof);
     }
 
     fn emit_pi(&mut self) {
-        self.tokens.push_back(SpanTokens::PiData {
+        let token = SpanTokens::PiData {
             data: mem::take(&mut self.current_token_bounds),
             target: mem::take(&mut self.current_token_secondary_bound),
-        });
+        };
+        self.push(token);
     }
 
     fn emit_error(&mut self, err: Xml5Error) {
-        self.tokens.push_back(SpanTokens::Error(err));
+        self.push(SpanTokens::Error(err));
     }
 
     fn emit_chars(&mut self, start: usize, end: usize) {
-        self.tokens.push_back(SpanTokens::Text(Spans {
-            data: vec![Mix::Range(start, end)],
-        }));
+        self.push(SpanTokens::Text(Spans::range(start, end)));
     }
 
     fn emit_chars_now<T: IntoBytes>(&mut self, x: T) {
-        todo!()
+        self.push(SpanTokens::Text(Spans::owned(x.to_bytes())));
     }
 
     fn emit_end_tag(&mut self) {
-        self.tokens.push_back(SpanTokens::EndTag(Some(mem::take(
-            &mut self.current_token_bounds,
-        ))));
+        let name = mem::take(&mut self.current_token_bounds);
+        // `</>` closes whichever element is currently open
+        let name = if name.is_empty() { None } else { Some(name) };
+        self.push(SpanTokens::EndTag(name));
     }
 
     fn emit_tag(&mut self) {
-        // TODO add attributes
-        self.tokens.push_back(SpanTokens::StartTag {
+        let token = SpanTokens::StartTag {
             name: mem::take(&mut self.current_token_bounds),
             attrs: mem::take(&mut self.current_attrs),
-            self_close: false,
-        });
+            self_close: mem::take(&mut self.self_close),
+        };
+        self.push(token);
     }
 
     fn emit_doctype(&mut self) {
-        // self.tokens.push_back(SpanTokens::D)
+        let doctype = mem::take(&mut self.current_doctype);
+        let token = SpanTokens::DocType {
+            name: mem::take(&mut self.current_token_secondary_bound),
+            public_id: doctype.public_id,
+            system_id: doctype.system_id,
+            internal_subset: doctype.internal_subset,
+        };
+        self.push(token);
     }
 
     fn set_xml_declaration(&mut self, attr_name: XmlDeclAttr) {
-        todo!()
+        let decl = &mut self.current_decl;
+        let value = match attr_name {
+            XmlDeclAttr::Version => &mut decl.version,
+            XmlDeclAttr::Encoding => &mut decl.encoding,
+            XmlDeclAttr::Standalone => &mut decl.standalone,
+        };
+        *value = Some(Spans::default());
+        decl.current_attr = Some(attr_name);
     }
 
     fn emit_decl_value(&mut self, start: usize, end: usize) {
-        todo!()
+        let decl = &mut self.current_decl;
+        let value = match decl.current_attr {
+            Some(XmlDeclAttr::Version) => &mut decl.version,
+            Some(XmlDeclAttr::Encoding) => &mut decl.encoding,
+            Some(XmlDeclAttr::Standalone) => &mut decl.standalone,
+            None => return,
+        };
+        if let Some(value) = value {
+            value.add_span(start, end);
+        }
     }
-}
-
-#[derive(Copy, Clone)]
-pub enum CurrentToken {
-    NoToken,
-    StartTag,
-    EndTag,
-    ProcessingInstruction,
-    Doctype,
-}
 
-impl Default for CurrentToken {
-    fn default() -> Self {
-        CurrentToken::NoToken
+    fn emit_xml_decl(&mut self) {
+        let decl = mem::take(&mut self.current_decl);
+        self.push(SpanTokens::Decl {
+            version: decl.version,
+            encoding: decl.encoding,
+            standalone: decl.standalone,
+        });
     }
 }
-
-#[repr(u32)]
-pub enum Test {
-    Characters(String),
-    Test(u8),
-}
-
-fn main() {
-    println!("{}", mem::size_of::<Test>());
-}
@@ -22,8 +22,12 @@
 // OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN
 // THE SOFTWARE.
 
+use std::mem;
+
 #[cfg(feature = "encoding")]
-use encoding_rs::{Encoding, UTF_8};
+use encoding_rs::{CoderResult, Decoder, Encoding, UTF_8};
+
+use crate::encoding::{detect_encoding, EncodingSource};
 
 /// A reference to an encoding together with information about how it was retrieved.
 ///
@@ -31,7 +35,6 @@ use encoding_rs::{Encoding, UTF_8};
 ///
 /// ```mermaid
 /// flowchart LR
-///   Implicit    -- from_str       --> Explicit
 ///   Implicit    -- BOM            --> BomDetected
 ///   Implicit    -- "encoding=..." --> XmlDetected
 ///   BomDetected -- "encoding=..." --> XmlDetected
@@ -42,9 +45,6 @@ pub(crate) enum EncodingRef {
     /// Encoding was implicitly assumed to have a specified value. It can be refined
     /// using BOM or by the XML declaration event (`<?xml encoding=... ?>`)
     Implicit(&'static Encoding),
-    /// Encoding was explicitly set to the desired value. It cannot be changed
-    /// nor by BOM, nor by parsing XML declaration (`<?xml encoding=... ?>`)
-    Explicit(&'static Encoding),
     /// Encoding was detected from a byte order mark (BOM) or by the first bytes
     /// of the content. It can be refined by the XML declaration event (`<?xml encoding=... ?>`)
     BomDetected(&'static Encoding),
@@ -58,7 +58,6 @@ impl EncodingRef {
     pub(crate) fn encoding(&self) -> &'static Encoding {
         match self {
             Self::Implicit(e) => e,
-            Self::Explicit(e) => e,
             Self::BomDetected(e) => e,
             Self::XmlDetected(e) => e,
         }
@@ -67,7 +66,7 @@ impl EncodingRef {
     fn can_be_refined(&self) -> bool {
         match self {
             Self::Implicit(_) | Self::BomDetected(_) => true,
-            Self::Explicit(_) | Self::XmlDetected(_) => false,
+            Self::XmlDetected(_) => false,
         }
     }
 }
@@ -77,3 +76,209 @@ impl Default for EncodingRef {
         Self::Implicit(UTF_8)
     }
 }
+
+/// How many bytes are held back at most to look for the encoding declaration.
+const MAX_PROLOG_LEN: usize = 1024;
+
+/// Transcoder of input in any supported encoding into UTF-8.
+///
+/// The start of input is held back until the encoding is known, which is
+/// sniffed from the BOM or the first bytes, and then refined by the
+/// `encoding` of the XML declaration if that is ASCII compatible.
+#[derive(Default)]
+pub(crate) struct Transcoder {
+    encoding: EncodingRef,
+    /// `None` until the encoding is known
+    decoder: Option<Decoder>,
+    /// start of input, until the encoding is known
+    prolog: Vec<u8>,
+    /// BOM removed from the input, until it's taken
+    bom: Option<Vec<u8>>,
+}
+
+impl Transcoder {
+    /// Decoder of the detected encoding and where it was found.
+    pub(crate) fn decoder(&self) -> crate::encoding::Decoder {
+        let source = match self.encoding {
+            EncodingRef::Implicit(_) => EncodingSource::Default,
+            EncodingRef::BomDetected(_) => EncodingSource::Bom,
+            EncodingRef::XmlDetected(_) => EncodingSource::Declaration,
+        };
+        crate::encoding::Decoder {
+            encoding: self.encoding.encoding(),
+            source,
+            transcoded: true,
+        }
+    }
+
+    /// Bytes of the BOM, once the encoding is known and if the input had one.
+    pub(crate) fn take_bom(&mut self) -> Option<Vec<u8>> {
+        self.decoder.as_ref()?;
+        self.bom.take()
+    }
+
+    /// Appends `chunk` transcoded to UTF-8 to `out`. The BOM isn't part of
+    /// the output.
+    pub(crate) fn decode(&mut self, chunk: &[u8], last: bool, out: &mut Vec<u8>) {
+        if let Some(decoder) = &mut self.decoder {
+            transcode(decoder, chunk, last, out);
+            return;
+        }
+
+        self.prolog.extend_from_slice(chunk);
+        if !last && is_signature_start(&self.prolog) {
+            return;
+        }
+        let bom_len = match detect_encoding(&self.prolog) {
+            Some(encoding) => {
+                let len = bom_len(&self.prolog, encoding);
+                // `<?xm` only tells the encoding is ASCII compatible
+                if len > 0 || encoding != UTF_8 {
+                    self.encoding = EncodingRef::BomDetected(encoding);
+                }
+                len
+            }
+            None => 0,
+        };
+        let text = &self.prolog[bom_len..];
+        if self.encoding.encoding().is_ascii_compatible() {
+            if !last && !is_prolog_complete(text) {
+                return;
+            }
+            let declared = declared_label(text).and_then(Encoding::for_label);
+            if let (true, Some(encoding)) = (self.encoding.can_be_refined(), declared) {
+                if encoding.is_ascii_compatible() {
+                    self.encoding = EncodingRef::XmlDetected(encoding);
+                }
+            }
+        }
+
+        let mut decoder = self.encoding.encoding().new_decoder_without_bom_handling();
+        let prolog = mem::take(&mut self.prolog);
+        if bom_len > 0 {
+            self.bom = Some(prolog[..bom_len].to_vec());
+        }
+        transcode(&mut decoder, &prolog[bom_len..], last, out);
+        self.decoder = Some(decoder);
+    }
+}
+
+fn transcode(decoder: &mut Decoder, mut input: &[u8], last: bool, out: &mut Vec<u8>) {
+    loop {
+        let start = out.len();
+        let needed = decoder
+            .max_utf8_buffer_length(input.len())
+            .unwrap_or(input.len() * 3 + 16);
+        out.resize(start + needed, 0);
+        // malformed sequences are replaced, same as other errors are recovered
+        let (result, read, written, _) = decoder.decode_to_utf8(input, &mut out[start..], last);
+        out.truncate(start + written);
+        input = &input[read..];
+        if let CoderResult::InputEmpty = result {
+            break;
+        }
+    }
+}
+
+fn bom_len(bytes: &[u8], encoding: &'static Encoding) -> usize {
+    match bytes {
+        [0xEF, 0xBB, 0xBF, ..] if encoding == UTF_8 => 3,
+        [0xFF, 0xFE, ..] | [0xFE, 0xFF, ..] if encoding != UTF_8 => 2,
+        _ => 0,
+    }
+}
+
+/// Whether `bytes` are the start of a BOM or of the first bytes that tell
+/// the encoding, so the encoding can't be detected before more arrive.
+fn is_signature_start(bytes: &[u8]) -> bool {
+    const SIGNATURES: &[&[u8]] = &[
+        &[0xEF, 0xBB, 0xBF],
+        &[0xFE, 0xFF],
+        &[0xFF, 0xFE],
+        &[0x00, b'<', 0x00, b'?'],
+        &[b'<', 0x00, b'?', 0x00],
+        b"<?xm",
+    ];
+    SIGNATURES
+        .iter()
+        .any(|signature| bytes.len() < signature.len() && signature.starts_with(bytes))
+}
+
+/// Whether there's enough of `text` to find the declared encoding.
+fn is_prolog_complete(text: &[u8]) -> bool {
+    if text.len() < 5 {
+        !b"<?xml".starts_with(text)
+    } else {
+        !text.starts_with(b"<?xml")
+            || text.windows(2).any(|x| x == b"?>")
+            || text.len() >= MAX_PROLOG_LEN
+    }
+}
+
+/// Value of the `encoding` pseudo-attribute of the XML declaration at the
+/// start of `text`.
+fn declared_label(text: &[u8]) -> Option<&[u8]> {
+    let text = text.strip_prefix(b"<?xml")?;
+    if !text.first()?.is_ascii_whitespace() {
+        return None;
+    }
+    let end = text
+        .windows(2)
+        .position(|x| x == b"?>")
+        .unwrap_or(text.len());
+    let decl = &text[..end];
+    let start = decl.windows(8).position(|x| x == b"encoding")? + 8;
+    let rest = decl[start..].trim_ascii_start().strip_prefix(b"=")?;
+    let rest = rest.trim_ascii_start();
+    let quote = *rest.first()?;
+    if quote != b'"' && quote != b'\'' {
+        return None;
+    }
+    let len = rest[1..].iter().position(|&chr| chr == quote)?;
+    Some(&rest[1..1 + len])
+}
+
+#[test]
+fn test_declared_label() {
+    assert_eq!(
+        Some(&b"Shift_JIS"[..]),
+        declared_label(b"<?xml version='1.0' encoding = \"Shift_JIS\"?><a/>")
+    );
+    assert_eq!(
+        None,
+        declared_label(b"<?xml version='1.0'?><a encoding='x'/>")
+    );
+    assert_eq!(None, declared_label(b"<?xml-stylesheet encoding='x'?>"));
+}
+
+#[test]
+fn test_transcode() {
+    fn decode_chunks(chunks: &[&[u8]]) -> (String, &'static Encoding) {
+        let mut transcoder = Transcoder::default();
+        let mut out = vec![];
+        for (pos, chunk) in chunks.iter().enumerate() {
+            transcoder.decode(chunk, pos + 1 == chunks.len(), &mut out);
+        }
+        (
+            String::from_utf8(out).unwrap(),
+            transcoder.encoding.encoding(),
+        )
+    }
+
+    let (text, encoding) = decode_chunks(&[b"<?xml encoding='windows-1252'?><a>\xE9</a>"]);
+    assert_eq!("<?xml encoding='windows-1252'?><a>\u{E9}</a>", text);
+    assert_eq!(encoding_rs::WINDOWS_1252, encoding);
+
+    // declaration split between chunks
+    let (text, encoding) = decode_chunks(&[b"<?xml encoding='Shi", b"ft_JIS'?>\x82\xA0", b""]);
+    assert_eq!("<?xml encoding='Shift_JIS'?>\u{3042}", text);
+    assert_eq!(encoding_rs::SHIFT_JIS, encoding);
+
+    let (text, encoding) = decode_chunks(&[b"\xFF\xFE<\x00a\x00", b"/\x00>\x00", b""]);
+    assert_eq!("<a/>", text);
+    assert_eq!(encoding_rs::UTF_16LE, encoding);
+
+    let (text, encoding) = decode_chunks(&[b"\xEF\xBB\xBF<a/>", b""]);
+    assert_eq!("<a/>", text);
+    assert_eq!(UTF_8, encoding);
+}
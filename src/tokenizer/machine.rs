@@ -1,16 +1,29 @@
+#[cfg(feature = "test-harness")]
+use std::collections::BTreeSet;
 use std::io::BufRead;
+use std::mem;
 
-use FastRead::EOF;
-
+use crate::dtd::Dtd;
+use crate::encoding::{Decoder, EncodingSource};
 use crate::errors::Xml5Error;
+use crate::tokenizer::char_ref::{resolve_entity, CharRefStatus};
 use crate::tokenizer::emitter::{DefaultEmitter, Emitter, XmlDeclAttr};
-use crate::tokenizer::reader::FastRead::{Char, InterNeedle};
-use crate::tokenizer::reader::{FastRead, Reader, SliceReader};
+#[cfg(feature = "encoding")]
+use crate::tokenizer::encoding::Transcoder;
+use crate::tokenizer::partial::PartialTokenizer;
+use crate::tokenizer::reader::FastRead::{Char, Eof, InterNeedle};
+use crate::tokenizer::reader::{BuffReader, Reader, SliceReader};
+#[cfg(feature = "tokio")]
+use crate::tokenizer::AsyncBufIterator;
 use crate::tokenizer::AttrValueKind::{DoubleQuoted, SingleQuoted, Unquoted};
-use crate::tokenizer::Control::Eof;
 use crate::tokenizer::DoctypeKind::{Public, System};
+#[cfg(feature = "test-harness")]
+use crate::tokenizer::TokenState;
 use crate::tokenizer::TokenState::*;
-use crate::tokenizer::{Control, DeclQuote, SliceIterator};
+use crate::tokenizer::{
+    BufIterator, ByteIterator, Control, DeclQuote, EmitterIterator, PushTokenizer, SliceIterator,
+    TokenizerConfig,
+};
 use crate::Tokenizer;
 
 impl Tokenizer {
@@ -18,7 +31,38 @@ impl Tokenizer {
         Self::default()
     }
 
-    pub fn from_str_reader(self, input: &str) -> SliceIterator<DefaultEmitter> {
+    /// Creates a tokenizer that applies `config` to the tokens it returns.
+    pub fn with_config(config: TokenizerConfig) -> Self {
+        let mut tokenizer = Self::default();
+        tokenizer.filter.config = config;
+        tokenizer
+    }
+
+    /// Enables parsing of the doctype's internal subset. General entities
+    /// declared in it are then expanded in text and attribute values, and the
+    /// declarations are available through the iterator's `dtd` method.
+    pub fn parse_internal_subset(mut self, enabled: bool) -> Self {
+        self.parse_dtd = enabled;
+        self
+    }
+
+    /// Starts in `state` instead of `Data`, for conformance tests.
+    #[cfg(feature = "test-harness")]
+    #[doc(hidden)]
+    pub fn initial_state(mut self, state: TokenState) -> Self {
+        self.state = state;
+        self
+    }
+
+    /// Records the states the tokenizer goes through, for conformance tests.
+    #[cfg(feature = "test-harness")]
+    #[doc(hidden)]
+    pub fn trace_states(mut self) -> Self {
+        self.visited = Some(BTreeSet::new());
+        self
+    }
+
+    pub fn from_str_reader(self, input: &str) -> SliceIterator<'_, DefaultEmitter> {
         SliceIterator {
             state: self,
             reader: SliceReader::from_str(input),
@@ -26,6 +70,90 @@ impl Tokenizer {
         }
     }
 
+    /// Creates an iterator over the tokens that `emitter` builds from `input`.
+    ///
+    /// The emitter is given byte ranges of `input` instead of tokens, so it
+    /// can count, filter or collect them into its own structures, without
+    /// tokens being allocated first.
+    pub fn with_emitter<E: Emitter>(self, input: &str, emitter: E) -> EmitterIterator<'_, E> {
+        EmitterIterator {
+            state: self,
+            reader: SliceReader::from_str(input),
+            emitter,
+        }
+    }
+
+    /// Creates an iterator over the tokens of `input` as
+    /// [`SpanTokens`](crate::SpanTokens), which are byte ranges of `input`
+    /// instead of its bytes. Useful to only keep positions, e.g. of a
+    /// memory-mapped file.
+    ///
+    /// `input` is read as UTF-8, but isn't checked to be valid.
+    pub fn span_tokens(self, input: &[u8]) -> EmitterIterator<'_, DefaultEmitter> {
+        EmitterIterator {
+            state: self,
+            reader: SliceReader::from_slice(input),
+            emitter: DefaultEmitter::default(),
+        }
+    }
+
+    /// Creates an iterator over tokens read from any [`BufRead`] source.
+    ///
+    /// Bytes of the token that is being parsed are copied into `buffer`, which
+    /// is cleared in between tokens, so it only needs to be as large as the
+    /// largest token in the input.
+    pub fn from_buf_reader<R: BufRead>(
+        self,
+        source: R,
+        buffer: &mut Vec<u8>,
+    ) -> BufIterator<'_, R, DefaultEmitter> {
+        BufIterator {
+            state: self,
+            reader: BuffReader::from_reader(source, buffer),
+            emitter: DefaultEmitter::default(),
+        }
+    }
+
+    /// Creates an iterator over tokens of `input` in any supported encoding.
+    /// See [`ByteIterator`] for how the encoding is found.
+    pub fn from_bytes(self, input: &[u8]) -> ByteIterator<&[u8], DefaultEmitter> {
+        self.from_byte_reader(input)
+    }
+
+    /// Creates an iterator over tokens read from a [`BufRead`] source in any
+    /// supported encoding. See [`ByteIterator`] for how the encoding is found.
+    pub fn from_byte_reader<R: BufRead>(self, source: R) -> ByteIterator<R, DefaultEmitter> {
+        ByteIterator {
+            source,
+            tokenizer: PartialTokenizer::new(self),
+            #[cfg(feature = "encoding")]
+            transcoder: Transcoder::default(),
+            utf8: vec![],
+        }
+    }
+
+    /// Turns this into a [`PushTokenizer`], which is fed input in chunks.
+    pub fn into_push(self) -> PushTokenizer {
+        PushTokenizer {
+            inner: PartialTokenizer::new(self),
+        }
+    }
+
+    /// Creates a reader of tokens from an [`AsyncBufRead`] source, with
+    /// `async fn next`.
+    ///
+    /// [`AsyncBufRead`]: tokio::io::AsyncBufRead
+    #[cfg(feature = "tokio")]
+    pub fn from_async_reader<R: tokio::io::AsyncBufRead + Unpin>(
+        self,
+        source: R,
+    ) -> AsyncBufIterator<R, DefaultEmitter> {
+        AsyncBufIterator {
+            source,
+            tokenizer: PartialTokenizer::new(self),
+        }
+    }
+
     #[inline]
     pub(crate) fn next_state<'r, E, R>(&mut self, reader: &mut R, emitter: &mut E) -> Control
     where
@@ -34,10 +162,6 @@ impl Tokenizer {
     {
         let mut amt = 1;
         let next_char = match reader.peek_byte() {
-            Ok(None) => {
-                self.eof = true;
-                return Eof;
-            }
             Ok(x) => x,
             Err(e) => return Control::Err(e),
         };
@@ -62,14 +186,35 @@ impl Tokenizer {
             }};
         }
 
+        // internal subset is also kept aside, when it needs to be parsed
+        macro_rules! append_subset {
+            () => {{
+                let tmp = reader.append_curr_char();
+                append_subset!(tmp, tmp + 1);
+            }};
+            ($start:expr, $end:expr) => {{
+                let (start, end) = ($start, $end);
+                if self.parse_dtd {
+                    self.subset
+                        .extend_from_slice(reader.slice_bytes(start, end));
+                }
+                emitter.doctype_subset(start, end);
+            }};
+        }
+
         match self.state {
+            Data if reader.position().offset == 0 && reader.try_read_slice_exact("\u{FEFF}") => {
+                amt = 0;
+                self.decoder = Decoder::utf8().with_source(EncodingSource::Bom);
+                emitter.emit_bom();
+            }
             Data => {
                 amt = 0;
                 match reader.read_fast_until(&[b'<', b'&']) {
-                    Char(b'&') => switch_to!(CharRefInData(Unquoted)),
+                    Char(b'&') => switch_to!(CharRefInData),
                     Char(b'<') => switch_to!(TagOpen),
                     InterNeedle(start, end) => emitter.emit_chars(start, end),
-                    _ => emitter.emit_eof(),
+                    _ => return Control::Eof,
                 }
             }
             TagOpen => match next_char {
@@ -80,7 +225,7 @@ impl Tokenizer {
                 | Some(b'>') => {
                     emitter.emit_error(Xml5Error::UnexpectedSymbolOrEof(next_char));
                     emitter.emit_chars_now(b'<');
-                    switch_to!(Data);
+                    reconsume!(Data);
                 }
                 Some(_) => {
                     emitter.create_tag();
@@ -89,7 +234,8 @@ impl Tokenizer {
             },
             EndTagOpen => match next_char {
                 Some(b'>') => {
-                    emitter.emit_tag();
+                    emitter.create_end_tag();
+                    emitter.emit_end_tag();
                     switch_to!(Data);
                 }
                 None | Some(b'\t') | Some(b'\n') | Some(b' ') | Some(b':') | Some(b'<') => {
@@ -118,7 +264,9 @@ impl Tokenizer {
                     }
                     InterNeedle(start, end) => emitter.append_tag(start, end),
                     _ => {
-                        emitter.emit_error(Xml5Error::UnexpectedEof);
+                        emitter.emit_error(Xml5Error::EofInTag);
+                        emitter.emit_end_tag();
+                        reconsume!(Data);
                     }
                 }
             }
@@ -144,10 +292,7 @@ impl Tokenizer {
                         emitter.emit_tag();
                         switch_to!(Data);
                     }
-                    Char(b'/') => {
-                        emitter.set_empty_tag();
-                        switch_to!(EmptyTag);
-                    }
+                    Char(b'/') => switch_to!(EmptyTag),
                     InterNeedle(start, end) => {
                         emitter.append_tag(start, end);
                     }
@@ -160,10 +305,19 @@ impl Tokenizer {
             }
             EmptyTag => match next_char {
                 Some(b'>') => {
+                    emitter.set_empty_tag();
                     emitter.emit_tag();
                     switch_to!(Data);
                 }
-                _ => reconsume!(TagAttrValueBefore),
+                None => {
+                    emitter.emit_error(Xml5Error::EofInTag);
+                    emitter.emit_tag();
+                    reconsume!(Data);
+                }
+                Some(x) => {
+                    emitter.emit_error(Xml5Error::UnexpectedSymbol(x as char));
+                    reconsume!(TagAttrNameBefore);
+                }
             },
             TagAttrNameBefore => match next_char {
                 Some(b'\t') | Some(b'\n') | Some(b' ') => (),
@@ -171,10 +325,7 @@ impl Tokenizer {
                     emitter.emit_tag();
                     switch_to!(Data);
                 }
-                Some(b'/') => {
-                    emitter.set_empty_tag();
-                    switch_to!(EmptyTag);
-                }
+                Some(b'/') => switch_to!(EmptyTag),
                 Some(b':') => emitter.emit_error(Xml5Error::ColonBeforeAttrName),
                 None => {
                     emitter.emit_error(Xml5Error::EofInTag);
@@ -183,24 +334,22 @@ impl Tokenizer {
                 }
                 Some(_) => {
                     emitter.create_attr();
-                    append_curr_char!(attr_values);
+                    append_curr_char!(attr_names);
                     switch_to!(TagAttrName);
                 }
             },
             TagAttrName => {
                 amt = 0;
                 match reader.read_fast_until(&[b'\t', b'\n', b' ', b'=', b'>', b'/']) {
-                    Char(b'\t') | Char(b'\n') | Char(b' ') => switch_to!(TagAttrValueBefore),
+                    Char(b'\t') | Char(b'\n') | Char(b' ') => switch_to!(TagAttrNameAfter),
                     Char(b'=') => switch_to!(TagAttrValueBefore),
                     Char(b'>') => {
                         emitter.emit_tag();
                         switch_to!(Data);
                     }
-                    Char(b'/') => {
-                        emitter.set_empty_tag();
-                        switch_to!(EmptyTag);
-                    }
-                    EOF | _ => {
+                    Char(b'/') => switch_to!(EmptyTag),
+                    InterNeedle(start, end) => emitter.attr_names(start, end),
+                    _ => {
                         emitter.emit_error(Xml5Error::EofInTag);
                         emitter.emit_tag();
                         reconsume!(Data);
@@ -212,12 +361,9 @@ impl Tokenizer {
                 Some(b'=') => switch_to!(TagAttrValueBefore),
                 Some(b'>') => {
                     emitter.emit_tag();
-                    switch_to!(EmptyTag);
-                }
-                Some(b'/') => {
-                    emitter.set_empty_tag();
-                    switch_to!(EmptyTag);
+                    switch_to!(Data);
                 }
+                Some(b'/') => switch_to!(EmptyTag),
                 None => {
                     emitter.emit_error(Xml5Error::EofInTag);
                     emitter.emit_tag();
@@ -225,7 +371,7 @@ impl Tokenizer {
                 }
                 Some(_) => {
                     emitter.create_attr();
-                    append_curr_char!(attr_values);
+                    append_curr_char!(attr_names);
                     switch_to!(TagAttrName)
                 }
             },
@@ -252,9 +398,9 @@ impl Tokenizer {
                 amt = 0;
                 match reader.read_fast_until(&[b'&', b'"']) {
                     Char(b'"') => switch_to!(TagAttrNameBefore),
-                    Char(_) => switch_to!(CharRefInData(DoubleQuoted)),
+                    Char(_) => switch_to!(CharRefInAttrValue(DoubleQuoted)),
                     InterNeedle(start, end) => emitter.attr_values(start, end),
-                    EOF => {
+                    Eof => {
                         emitter.emit_error(Xml5Error::EofInTag);
                         emitter.emit_tag();
                         reconsume!(Data);
@@ -265,9 +411,9 @@ impl Tokenizer {
                 amt = 0;
                 match reader.read_fast_until(&[b'&', b'\'']) {
                     Char(b'\'') => switch_to!(TagAttrNameBefore),
-                    Char(_) => switch_to!(CharRefInData(DoubleQuoted)),
+                    Char(_) => switch_to!(CharRefInAttrValue(SingleQuoted)),
                     InterNeedle(start, end) => emitter.attr_values(start, end),
-                    EOF => {
+                    Eof => {
                         emitter.emit_error(Xml5Error::EofInTag);
                         emitter.emit_tag();
                         reconsume!(Data);
@@ -278,13 +424,13 @@ impl Tokenizer {
                 amt = 0;
                 match reader.read_fast_until(&[b'\t', b'\n', b' ', b'&', b'>']) {
                     Char(b'\t') | Char(b'\n') | Char(b' ') => switch_to!(TagAttrNameBefore),
-                    Char(b'&') => switch_to!(CharRefInData(Unquoted)),
+                    Char(b'&') => switch_to!(CharRefInAttrValue(Unquoted)),
                     Char(_) => {
                         emitter.emit_tag();
                         switch_to!(Data);
                     }
                     InterNeedle(start, end) => emitter.attr_values(start, end),
-                    EOF => {
+                    Eof => {
                         emitter.emit_error(Xml5Error::EofInTag);
                         emitter.emit_tag();
                         reconsume!(Data);
@@ -293,12 +439,17 @@ impl Tokenizer {
             }
             Pi => {
                 amt = 0;
-                if reader.try_read_slice_exact("xml") {
+                // `<?xml-stylesheet` and alike are processing instructions
+                if ["xml ", "xml\t", "xml\n"]
+                    .iter()
+                    .any(|decl| reader.try_read_slice_exact(decl))
+                {
                     switch_to!(XmlDecl);
                 } else {
                     match next_char {
                         None | Some(b' ') | Some(b'\n') | Some(b'\t') => {
                             emitter.emit_error(Xml5Error::UnexpectedSymbolOrEof(next_char));
+                            emitter.create_comment_token();
                             reconsume!(BogusComment);
                         }
                         Some(_) => {
@@ -310,90 +461,137 @@ impl Tokenizer {
             }
             XmlDecl => match next_char {
                 Some(b'\t') | Some(b'\n') | Some(b' ') => {}
-                Some(b'v') | Some(b'e') | Some(b's') => {
-                    reconsume!(XmlDeclAttrName)
-                }
-                Some(_) => {
-                    // TODO
-                    // emitter.pi_target(xml)
-                    emitter.emit_error(Xml5Error::InvalidXmlDeclaration);
-                }
+                Some(b'?') => switch_to!(XmlDeclAfter),
+                Some(_) => reconsume!(XmlDeclAttrName),
                 None => {
-                    // TODO
-                    // emitter.pi_target()
                     emitter.emit_error(Xml5Error::EofInXmlDeclaration);
+                    emitter.emit_xml_decl();
+                    reconsume!(Data);
                 }
             },
             XmlDeclAttrName => {
                 amt = 0;
-                if reader.try_read_slice_exact("version") {
-                    emitter.set_xml_declaration(XmlDeclAttr::Version);
-                    switch_to!(XmlDeclAttrNameAfter);
+                let attr = if reader.try_read_slice_exact("version") {
+                    Some(XmlDeclAttr::Version)
                 } else if reader.try_read_slice_exact("encoding") {
-                    emitter.set_xml_declaration(XmlDeclAttr::Encoding);
-                    switch_to!(XmlDeclAttrNameAfter);
+                    Some(XmlDeclAttr::Encoding)
                 } else if reader.try_read_slice_exact("standalone") {
-                    emitter.set_xml_declaration(XmlDeclAttr::Standalone);
-                    switch_to!(XmlDeclAttrNameAfter);
+                    Some(XmlDeclAttr::Standalone)
                 } else {
-                    emitter.emit_error(Xml5Error::InvalidXmlDeclaration);
-                    // TODO
-                    // emitter.pi_target()
-                    switch_to!(PiTarget);
+                    None
+                };
+                match attr {
+                    Some(attr) => switch_to!(XmlDeclAttrNameAfter(attr)),
+                    None => {
+                        emitter.emit_error(Xml5Error::UnknownXmlDeclarationAttribute);
+                        switch_to!(XmlDeclBogus);
+                    }
                 }
             }
-            XmlDeclAttrNameAfter => match next_char {
+            XmlDeclAttrNameAfter(attr) => match next_char {
                 Some(b'\t') | Some(b'\n') | Some(b' ') => {}
-                Some(b'=') => switch_to!(XmlDeclAttrValueBefore),
-                Some(_) => reconsume!(PiTarget),
+                Some(b'=') => switch_to!(XmlDeclAttrValueBefore(attr)),
+                Some(_) => {
+                    emitter.emit_error(Xml5Error::InvalidXmlDeclaration);
+                    reconsume!(XmlDeclBogus);
+                }
                 None => {
                     emitter.emit_error(Xml5Error::EofInXmlDeclaration);
+                    emitter.emit_xml_decl();
+                    reconsume!(Data);
                 }
             },
-            XmlDeclAttrValueBefore => match next_char {
+            XmlDeclAttrValueBefore(attr) => match next_char {
                 Some(b'\t') | Some(b'\n') | Some(b' ') => {}
-                Some(b'\'') => switch_to!(XmlDeclAttrValue(DeclQuote::SingleQuoted)),
-                Some(b'"') => switch_to!(XmlDeclAttrValue(DeclQuote::DoubleQuoted)),
-                Some(_) => reconsume!(PiTarget),
+                Some(quote @ b'\'') | Some(quote @ b'"') => {
+                    emitter.set_xml_declaration(attr);
+                    switch_to!(XmlDeclAttrValue(attr, DeclQuote::from_byte(quote), false))
+                }
+                Some(_) => {
+                    emitter.emit_error(Xml5Error::InvalidXmlDeclaration);
+                    reconsume!(XmlDeclBogus);
+                }
                 None => {
                     emitter.emit_error(Xml5Error::EofInXmlDeclaration);
+                    emitter.emit_xml_decl();
+                    reconsume!(Data);
                 }
             },
-            XmlDeclAttrValue(DeclQuote::DoubleQuoted) => {
+            XmlDeclAttrValue(attr, quote, checked) => {
                 amt = 0;
-                match reader.read_fast_until(&[b'"', b'?']) {
-                    Char(b'"') => {
-                        switch_to!(XmlDecl)
-                    }
+                match reader.read_fast_until(&[quote.as_byte(), b'?']) {
                     Char(b'?') => {
-                        // emitter.emit_xml_decl();
+                        self.declared.clear();
                         emitter.emit_error(Xml5Error::AbruptClosingXmlDeclaration);
-                        switch_to!(PiAfter);
+                        switch_to!(XmlDeclAfter);
                     }
-                    InterNeedle(start, end) => emitter.emit_decl_value(start, end),
-                    EOF | _ => {
+                    Char(_) => {
+                        if attr == XmlDeclAttr::Encoding {
+                            self.decoder = self.decoder.declared(&self.declared);
+                            self.declared.clear();
+                        }
+                        switch_to!(XmlDecl)
+                    }
+                    // a value cut off where received input ends is read in
+                    // more than one piece, the first of which is checked
+                    InterNeedle(start, end) => {
+                        let value = reader.slice_bytes(start, end);
+                        if attr == XmlDeclAttr::Standalone
+                            && !checked
+                            && value != b"yes"
+                            && value != b"no"
+                        {
+                            emitter.emit_error(Xml5Error::InvalidXmlDeclaration);
+                        }
+                        if attr == XmlDeclAttr::Encoding {
+                            self.declared.extend_from_slice(value);
+                        }
+                        emitter.emit_decl_value(start, end);
+                        switch_to!(XmlDeclAttrValue(attr, quote, true));
+                    }
+                    Eof => {
                         emitter.emit_error(Xml5Error::EofInXmlDeclaration);
+                        emitter.emit_xml_decl();
+                        reconsume!(Data);
                     }
                 }
             }
-            XmlDeclAttrValue(DeclQuote::SingleQuoted) => {
+            XmlDeclAfter => match next_char {
+                Some(b'>') => {
+                    emitter.emit_xml_decl();
+                    switch_to!(Data);
+                }
+                None => {
+                    emitter.emit_error(Xml5Error::EofInXmlDeclaration);
+                    emitter.emit_xml_decl();
+                    reconsume!(Data);
+                }
+                Some(_) => {
+                    emitter.emit_error(Xml5Error::InvalidXmlDeclaration);
+                    reconsume!(XmlDeclBogus);
+                }
+            },
+            XmlDeclBogus => {
+                // rest of the declaration is skipped, up to `?>`
                 amt = 0;
-                match reader.read_fast_until(&[b'\'', b'?']) {
-                    Char(b'\'') => switch_to!(XmlDecl),
-                    Char(b'?') => {
-                        emitter.emit_error(Xml5Error::AbruptClosingXmlDeclaration);
-                        switch_to!(PiAfter);
+                match reader.read_fast_until(&[b'?']) {
+                    Char(_) => {
+                        if reader.try_read_slice_exact(">") {
+                            emitter.emit_xml_decl();
+                            switch_to!(Data);
+                        }
                     }
-                    InterNeedle(start, end) => emitter.emit_decl_value(start, end),
-                    EOF | _ => {
+                    InterNeedle(..) => {}
+                    Eof => {
                         emitter.emit_error(Xml5Error::EofInXmlDeclaration);
+                        emitter.emit_xml_decl();
+                        reconsume!(Data);
                     }
                 }
             }
-            XmlDeclAfter => {}
             PiTarget => {
                 amt = 0;
-                match reader.read_fast_until(&[b'\t', b'\n', b' ']) {
+                match reader.read_fast_until(&[b'\t', b'\n', b' ', b'?']) {
                     Char(b'\t') | Char(b'\n') | Char(b' ') => switch_to!(PiTargetAfter),
                     Char(b'?') => switch_to!(PiAfter),
                     InterNeedle(start, end) => emitter.pi_target(start, end),
@@ -425,19 +623,26 @@ impl Tokenizer {
                     emitter.emit_pi();
                     switch_to!(Data);
                 }
-                Some(b'?') => append_curr_char!(pi_target),
-                _ => reconsume!(PiData),
+                // the `?` before wasn't the end of the instruction
+                Some(b'?') => emitter.pi_data_now(b'?'),
+                _ => {
+                    emitter.pi_data_now(b'?');
+                    reconsume!(PiData);
+                }
             },
             MarkupDecl => {
+                amt = 0;
                 if reader.try_read_slice_exact("--") {
                     emitter.create_comment_token();
                     switch_to!(CommentStart)
                 } else if reader.try_read_slice("DOCTYPE", false) {
                     switch_to!(Doctype)
                 } else if reader.try_read_slice_exact("[CDATA[") {
+                    emitter.create_cdata();
                     switch_to!(Cdata)
                 } else {
                     emitter.emit_error(Xml5Error::IncorrectlyOpenedComment);
+                    emitter.create_comment_token();
                     switch_to!(BogusComment)
                 }
             }
@@ -460,7 +665,7 @@ impl Tokenizer {
                 None => {
                     emitter.emit_error(Xml5Error::EofInComment);
                     emitter.emit_comment();
-                    emitter.emit_eof();
+                    reconsume!(Data);
                 }
                 _ => {
                     emitter.to_comment_now(b'-');
@@ -474,14 +679,14 @@ impl Tokenizer {
                         emitter.to_comment(start, end);
                     }
                     Char(b'<') => {
-                        append_curr_char!(to_comment);
+                        emitter.to_comment_now(b'<');
                         switch_to!(CommentLessThan)
                     }
                     Char(b'-') => switch_to!(CommentEndDash),
                     _ => {
                         emitter.emit_error(Xml5Error::EofInComment);
                         emitter.emit_comment();
-                        emitter.emit_eof();
+                        reconsume!(Data);
                     }
                 }
             }
@@ -503,16 +708,13 @@ impl Tokenizer {
                 Some(b'-') => switch_to!(CommentLessThanBangDashDash),
                 _ => reconsume!(CommentEndDash),
             },
-            CommentLessThanBangDashDash => match next_char {
-                Some(b'>') | None => switch_to!(CommentEnd),
-                _ => reconsume!(CommentEndDash),
-            },
+            CommentLessThanBangDashDash => reconsume!(CommentEnd),
             CommentEndDash => match next_char {
                 Some(b'-') => switch_to!(CommentEnd),
                 None => {
                     emitter.emit_error(Xml5Error::EofInComment);
                     emitter.emit_comment();
-                    emitter.emit_eof();
+                    reconsume!(Data);
                 }
                 _ => {
                     emitter.to_comment_now(b'-');
@@ -529,7 +731,7 @@ impl Tokenizer {
                 None => {
                     emitter.emit_error(Xml5Error::EofInComment);
                     emitter.emit_comment();
-                    emitter.emit_eof();
+                    reconsume!(Data);
                 }
                 _ => {
                     emitter.to_comment_now("--");
@@ -538,7 +740,7 @@ impl Tokenizer {
             },
             CommentEndBang => match next_char {
                 Some(b'-') => {
-                    emitter.to_comment_now("-!");
+                    emitter.to_comment_now("--!");
                     switch_to!(CommentEndDash);
                 }
                 Some(b'>') => {
@@ -549,10 +751,10 @@ impl Tokenizer {
                 None => {
                     emitter.emit_error(Xml5Error::EofInComment);
                     emitter.emit_comment();
-                    emitter.emit_eof();
+                    reconsume!(Data);
                 }
                 _ => {
-                    emitter.to_comment_now("--");
+                    emitter.to_comment_now("--!");
                     reconsume!(Comment)
                 }
             },
@@ -560,9 +762,10 @@ impl Tokenizer {
                 amt = 0;
                 match reader.read_fast_until(&[b']']) {
                     Char(b']') => switch_to!(CdataBracket),
-                    InterNeedle(start, end) => emitter.emit_chars(start, end),
-                    EOF | _ => {
+                    InterNeedle(start, end) => emitter.cdata(start, end),
+                    _ => {
                         emitter.emit_error(Xml5Error::EofInCdata);
+                        emitter.emit_cdata();
                         reconsume!(Data);
                     }
                 }
@@ -570,38 +773,44 @@ impl Tokenizer {
             CdataBracket => match next_char {
                 Some(b']') => switch_to!(CdataEnd),
                 None => {
+                    emitter.cdata_now(b']');
                     emitter.emit_error(Xml5Error::EofInCdata);
+                    emitter.emit_cdata();
                     reconsume!(Data);
                 }
                 Some(_) => {
-                    emitter.emit_chars_now(b']');
-                    append_curr_char!(emit_chars);
-                    switch_to!(CdataBracket);
+                    emitter.cdata_now(b']');
+                    reconsume!(Cdata);
                 }
             },
             CdataEnd => match next_char {
-                Some(b'>') => switch_to!(Data),
-                Some(b']') => emitter.emit_chars_now(b']'),
+                Some(b'>') => {
+                    emitter.emit_cdata();
+                    switch_to!(Data);
+                }
+                Some(b']') => emitter.cdata_now(b']'),
                 None => {
+                    emitter.cdata_now("]]");
                     emitter.emit_error(Xml5Error::EofInCdata);
+                    emitter.emit_cdata();
                     reconsume!(Data);
                 }
-                Some(c) => {
-                    emitter.emit_chars_now("]]");
-                    emitter.emit_chars_now(c);
-                    switch_to!(Cdata);
+                Some(_) => {
+                    emitter.cdata_now("]]");
+                    reconsume!(Cdata);
                 }
             },
             BogusComment => {
                 amt = 0;
                 match reader.read_fast_until(&[b'>']) {
                     Char(_) => {
+                        emitter.emit_comment();
                         switch_to!(Data);
                     }
                     InterNeedle(start, end) => emitter.to_comment(start, end),
                     _ => {
                         emitter.emit_comment();
-                        emitter.emit_eof();
+                        reconsume!(Data);
                     }
                 }
             }
@@ -610,7 +819,7 @@ impl Tokenizer {
                 None => {
                     emitter.emit_error(Xml5Error::EofInDoctype);
                     emitter.emit_doctype();
-                    emitter.emit_eof();
+                    reconsume!(Data);
                 }
                 _ => {
                     emitter.emit_error(Xml5Error::MissingWhitespaceDoctype);
@@ -627,60 +836,311 @@ impl Tokenizer {
                 None => {
                     emitter.emit_error(Xml5Error::EofInDoctype);
                     emitter.emit_doctype();
-                    emitter.emit_eof();
+                    reconsume!(Data);
                 }
-                Some(x) => {
+                Some(_) => {
                     emitter.create_doctype();
-                    let chr = if x.is_ascii_uppercase() {
-                        x.to_ascii_lowercase()
-                    } else {
-                        x
-                    };
-                    emitter.doctype_name_now(chr);
-                    switch_to!(DoctypeName);
+                    reconsume!(DoctypeName);
                 }
             },
-            DoctypeName => match next_char {
-                Some(b'\t') | Some(b'\n') | Some(b' ') => switch_to!(AfterDoctypeName(0)),
+            DoctypeName => {
+                amt = 0;
+                match reader.read_fast_until(&[b'\t', b'\n', b' ', b'>', b'[']) {
+                    Char(b'>') => {
+                        emitter.emit_doctype();
+                        switch_to!(Data);
+                    }
+                    Char(b'[') => {
+                        emitter.create_doctype_subset();
+                        switch_to!(DoctypeInternalSubset(0));
+                    }
+                    Char(_) => switch_to!(AfterDoctypeName),
+                    InterNeedle(start, end) => emitter.doctype_name(start, end),
+                    Eof => {
+                        emitter.emit_error(Xml5Error::EofInDoctype);
+                        emitter.emit_doctype();
+                        reconsume!(Data);
+                    }
+                }
+            }
+            AfterDoctypeName => match next_char {
+                Some(b'\t') | Some(b'\n') | Some(b' ') => (),
                 Some(b'>') => {
                     emitter.emit_doctype();
                     switch_to!(Data);
                 }
+                Some(b'[') => {
+                    emitter.create_doctype_subset();
+                    switch_to!(DoctypeInternalSubset(0));
+                }
                 None => {
                     emitter.emit_error(Xml5Error::EofInDoctype);
                     emitter.emit_doctype();
-                    emitter.emit_eof();
+                    reconsume!(Data);
                 }
-                Some(x) => {
-                    let chr = if x.is_ascii_uppercase() {
-                        x.to_ascii_lowercase()
+                Some(_) => {
+                    amt = 0;
+                    if reader.try_read_slice("PUBLIC", false) {
+                        switch_to!(AfterDoctypeKeyword(Public));
+                    } else if reader.try_read_slice("SYSTEM", false) {
+                        switch_to!(AfterDoctypeKeyword(System));
                     } else {
-                        x
-                    };
-                    emitter.doctype_name_now(chr);
-                    switch_to!(DoctypeName);
+                        emitter.emit_error(Xml5Error::InvalidCharactersInAfterDoctypeName);
+                        switch_to!(BogusDoctype);
+                    }
                 }
-                _ => {}
             },
-            AfterDoctypeName(depth) => match reader.read_fast_until(&[b'[', b']', b'>']) {
-                Char(b'[') => switch_to!(AfterDoctypeName(depth + 1)),
-                Char(b']') => {
-                    if depth == 0 {
-                        switch_to!(BogusDoctype);
-                    } else {
-                        switch_to!(AfterDoctypeName(depth - 1));
+            AfterDoctypeKeyword(kind) => match next_char {
+                Some(b'\t') | Some(b'\n') | Some(b' ') => switch_to!(BeforeDoctypeIdentifier(kind)),
+                Some(quote @ b'"') | Some(quote @ b'\'') => {
+                    emitter.emit_error(Xml5Error::MissingWhitespaceAfterDoctypeKeyword);
+                    emitter.clear_doctype_id(kind);
+                    switch_to!(DoctypeIdentifier(kind, DeclQuote::from_byte(quote)));
+                }
+                Some(b'>') => {
+                    emitter.emit_error(Xml5Error::MissingDoctypeIdentifier);
+                    emitter.emit_doctype();
+                    switch_to!(Data);
+                }
+                None => {
+                    emitter.emit_error(Xml5Error::EofInDoctype);
+                    emitter.emit_doctype();
+                    reconsume!(Data);
+                }
+                Some(_) => {
+                    emitter.emit_error(Xml5Error::MissingQuoteBeforeIdentifier);
+                    reconsume!(BogusDoctype);
+                }
+            },
+            BeforeDoctypeIdentifier(kind) => match next_char {
+                Some(b'\t') | Some(b'\n') | Some(b' ') => (),
+                Some(quote @ b'"') | Some(quote @ b'\'') => {
+                    emitter.clear_doctype_id(kind);
+                    switch_to!(DoctypeIdentifier(kind, DeclQuote::from_byte(quote)));
+                }
+                Some(b'>') => {
+                    emitter.emit_error(Xml5Error::MissingDoctypeIdentifier);
+                    emitter.emit_doctype();
+                    switch_to!(Data);
+                }
+                None => {
+                    emitter.emit_error(Xml5Error::EofInDoctype);
+                    emitter.emit_doctype();
+                    reconsume!(Data);
+                }
+                Some(_) => {
+                    emitter.emit_error(Xml5Error::MissingQuoteBeforeIdentifier);
+                    reconsume!(BogusDoctype);
+                }
+            },
+            DoctypeIdentifier(kind, quote) => {
+                amt = 0;
+                match reader.read_fast_until(&[quote.as_byte(), b'>']) {
+                    Char(b'>') => {
+                        emitter.emit_error(Xml5Error::AbruptEndDoctypeIdentifier);
+                        emitter.emit_doctype();
+                        switch_to!(Data);
+                    }
+                    Char(_) => switch_to!(AfterDoctypeIdentifier(kind)),
+                    InterNeedle(start, end) => emitter.doctype_id(start, end),
+                    Eof => {
+                        emitter.emit_error(Xml5Error::EofInDoctype);
+                        emitter.emit_doctype();
+                        reconsume!(Data);
                     }
                 }
-                Char(b'>') if depth == 0 => {
+            }
+            AfterDoctypeIdentifier(kind) => match next_char {
+                Some(b'\t') | Some(b'\n') | Some(b' ') => {
+                    if let Public = kind {
+                        switch_to!(BetweenDoctypePublicAndSystem);
+                    }
+                }
+                Some(b'>') => {
                     emitter.emit_doctype();
                     switch_to!(Data);
                 }
-                EOF => {
+                Some(b'[') => {
+                    emitter.create_doctype_subset();
+                    switch_to!(DoctypeInternalSubset(0));
+                }
+                Some(quote @ b'"') | Some(quote @ b'\'') if matches!(kind, Public) => {
+                    emitter.emit_error(Xml5Error::MissingWhitespaceBetweenDoctypePublicAndSystem);
+                    emitter.clear_doctype_id(System);
+                    switch_to!(DoctypeIdentifier(System, DeclQuote::from_byte(quote)));
+                }
+                None => {
+                    emitter.emit_error(Xml5Error::EofInDoctype);
+                    emitter.emit_doctype();
+                    reconsume!(Data);
+                }
+                Some(_) => {
+                    emitter.emit_error(Xml5Error::InvalidCharactersInAfterDoctypeName);
+                    reconsume!(BogusDoctype);
+                }
+            },
+            BetweenDoctypePublicAndSystem => match next_char {
+                Some(b'\t') | Some(b'\n') | Some(b' ') => (),
+                Some(b'>') => {
+                    emitter.emit_doctype();
+                    switch_to!(Data);
+                }
+                Some(b'[') => {
+                    emitter.create_doctype_subset();
+                    switch_to!(DoctypeInternalSubset(0));
+                }
+                Some(quote @ b'"') | Some(quote @ b'\'') => {
+                    emitter.clear_doctype_id(System);
+                    switch_to!(DoctypeIdentifier(System, DeclQuote::from_byte(quote)));
+                }
+                None => {
+                    emitter.emit_error(Xml5Error::EofInDoctype);
+                    emitter.emit_doctype();
+                    reconsume!(Data);
+                }
+                Some(_) => {
+                    emitter.emit_error(Xml5Error::MissingQuoteBeforeIdentifier);
+                    reconsume!(BogusDoctype);
+                }
+            },
+            DoctypeInternalSubset(depth) => match next_char {
+                Some(b']') if depth == 0 => {
+                    if self.parse_dtd {
+                        self.dtd = Some(Dtd::parse(&mem::take(&mut self.subset)));
+                    }
+                    switch_to!(AfterDoctypeInternalSubset);
+                }
+                Some(b']') => {
+                    append_subset!();
+                    switch_to!(DoctypeInternalSubset(depth - 1));
+                }
+                Some(b'[') => {
+                    append_subset!();
+                    switch_to!(DoctypeInternalSubset(depth + 1));
+                }
+                Some(quote @ b'"') | Some(quote @ b'\'') => {
+                    append_subset!();
+                    switch_to!(DoctypeInternalSubsetQuoted(
+                        depth,
+                        DeclQuote::from_byte(quote)
+                    ));
+                }
+                Some(b'<') => {
+                    append_subset!();
+                    switch_to!(DoctypeInternalSubsetMarkup(depth, 1));
+                }
+                None => {
+                    emitter.emit_error(Xml5Error::EofInDoctype);
+                    emitter.emit_doctype();
+                    reconsume!(Data);
+                }
+                // next byte isn't a needle, so this always stops right before one
+                Some(_) => {
+                    amt = 0;
+                    if let InterNeedle(start, end) =
+                        reader.read_fast_until(&[b'[', b']', b'"', b'\'', b'<'])
+                    {
+                        append_subset!(start, end);
+                    }
+                }
+            },
+            // quotes and brackets don't count in comments and processing
+            // instructions, so `<!--` and `<?` are looked for, `matched` being
+            // how many bytes of `<!--` were read
+            DoctypeInternalSubsetMarkup(depth, matched) => match (matched, next_char) {
+                (1, Some(b'?')) => {
+                    append_subset!();
+                    switch_to!(DoctypeInternalSubsetPi(depth, false));
+                }
+                (1, Some(b'!')) | (2, Some(b'-')) => {
+                    append_subset!();
+                    switch_to!(DoctypeInternalSubsetMarkup(depth, matched + 1));
+                }
+                (3, Some(b'-')) => {
+                    append_subset!();
+                    switch_to!(DoctypeInternalSubsetComment(depth, 0));
+                }
+                _ => reconsume!(DoctypeInternalSubset(depth)),
+            },
+            DoctypeInternalSubsetComment(depth, dashes) => match next_char {
+                Some(b'-') => {
+                    append_subset!();
+                    switch_to!(DoctypeInternalSubsetComment(
+                        depth,
+                        dashes.saturating_add(1)
+                    ));
+                }
+                Some(b'>') if dashes >= 2 => {
+                    append_subset!();
+                    switch_to!(DoctypeInternalSubset(depth));
+                }
+                None => {
+                    emitter.emit_error(Xml5Error::EofInDoctype);
+                    emitter.emit_doctype();
+                    reconsume!(Data);
+                }
+                Some(_) => {
+                    amt = 0;
+                    if let InterNeedle(start, end) = reader.read_fast_until(&[b'-']) {
+                        append_subset!(start, end);
+                    }
+                    switch_to!(DoctypeInternalSubsetComment(depth, 0));
+                }
+            },
+            DoctypeInternalSubsetPi(depth, question_mark) => match next_char {
+                Some(b'?') => {
+                    append_subset!();
+                    switch_to!(DoctypeInternalSubsetPi(depth, true));
+                }
+                Some(b'>') if question_mark => {
+                    append_subset!();
+                    switch_to!(DoctypeInternalSubset(depth));
+                }
+                None => {
+                    emitter.emit_error(Xml5Error::EofInDoctype);
+                    emitter.emit_doctype();
+                    reconsume!(Data);
+                }
+                Some(_) => {
+                    amt = 0;
+                    if let InterNeedle(start, end) = reader.read_fast_until(&[b'?']) {
+                        append_subset!(start, end);
+                    }
+                    switch_to!(DoctypeInternalSubsetPi(depth, false));
+                }
+            },
+            DoctypeInternalSubsetQuoted(depth, quote) => match next_char {
+                Some(x) if x == quote.as_byte() => {
+                    append_subset!();
+                    switch_to!(DoctypeInternalSubset(depth));
+                }
+                None => {
+                    emitter.emit_error(Xml5Error::EofInDoctype);
                     emitter.emit_doctype();
+                    reconsume!(Data);
+                }
+                Some(_) => {
+                    amt = 0;
+                    if let InterNeedle(start, end) = reader.read_fast_until(&[quote.as_byte()]) {
+                        append_subset!(start, end);
+                    }
+                }
+            },
+            AfterDoctypeInternalSubset => match next_char {
+                Some(b'\t') | Some(b'\n') | Some(b' ') => (),
+                Some(b'>') => {
+                    emitter.emit_doctype();
+                    switch_to!(Data);
+                }
+                None => {
                     emitter.emit_error(Xml5Error::EofInDoctype);
-                    emitter.emit_eof();
+                    emitter.emit_doctype();
+                    reconsume!(Data);
+                }
+                Some(_) => {
+                    emitter.emit_error(Xml5Error::InvalidCharactersInAfterDoctypeName);
+                    reconsume!(BogusDoctype);
                 }
-                _ => {}
             },
             BogusDoctype => match next_char {
                 Some(b'>') => {
@@ -689,15 +1149,73 @@ impl Tokenizer {
                 }
                 None => {
                     emitter.emit_doctype();
-                    emitter.emit_eof();
+                    reconsume!(Data);
                 }
                 _ => (),
             },
-            CharRefInData(_) => {
-                // TODO char ref
+            CharRefInData | CharRefInAttrValue(_) => {
+                amt = 0;
+                let dtd = self.dtd.as_ref();
+                let expansions = &mut self.expansions;
+                match self
+                    .char_ref
+                    .step(next_char, |name| resolve_entity(name, dtd, expansions))
+                {
+                    CharRefStatus::Consume => amt = 1,
+                    CharRefStatus::Reconsume => (),
+                    CharRefStatus::Done {
+                        consume,
+                        value,
+                        error,
+                    } => {
+                        if let Some(err) = error {
+                            emitter.emit_error(err);
+                        }
+                        if let CharRefInAttrValue(kind) = self.state {
+                            emitter.attr_values_now(&value[..]);
+                            switch_to!(TagAttrValue(kind));
+                        } else {
+                            emitter.emit_chars_now(&value[..]);
+                            switch_to!(Data);
+                        }
+                        if consume {
+                            amt = 1;
+                        }
+                    }
+                }
             }
         };
-        reader.consume_bytes(amt);
+        if next_char.is_some() {
+            reader.consume_bytes(amt);
+        }
         Control::Continue
     }
+
+    /// Whether the next step could go differently if there was more input
+    /// than `rest`, the part of it received after the current position, so
+    /// it has to wait for more. Steps mostly look at one byte, and the rest
+    /// read fewer bytes than there are, except for the needles below.
+    pub(crate) fn needs_more(&self, rest: &[u8], offset: usize) -> bool {
+        let prefix_of = |needle: &str| {
+            rest.len() < needle.len() && needle.as_bytes()[..rest.len()].eq_ignore_ascii_case(rest)
+        };
+        match self.state {
+            _ if rest.is_empty() => true,
+            Data => offset == 0 && prefix_of("\u{FEFF}"),
+            Pi => prefix_of("xml "),
+            XmlDeclAttrName => ["version", "encoding", "standalone"]
+                .iter()
+                .any(|name| prefix_of(name)),
+            // value that may still turn out to be `yes` or `no`
+            XmlDeclAttrValue(XmlDeclAttr::Standalone, quote, false) => {
+                !rest.contains(&quote.as_byte()) && !rest.contains(&b'?') && rest.len() <= 3
+            }
+            XmlDeclBogus => rest.iter().position(|&chr| chr == b'?') == Some(rest.len() - 1),
+            MarkupDecl => ["--", "DOCTYPE", "[CDATA["]
+                .iter()
+                .any(|keyword| prefix_of(keyword)),
+            AfterDoctypeName => prefix_of("PUBLIC") || prefix_of("SYSTEM"),
+            _ => false,
+        }
+    }
 }
@@ -1,19 +1,42 @@
 use std::borrow::Cow;
+#[cfg(feature = "test-harness")]
+use std::collections::BTreeSet;
+use std::io::BufRead;
 
+use crate::dtd::{Dtd, Expansions};
+use crate::encoding::{Decoder, EncodingSource};
 use crate::errors::Xml5Error;
-use crate::tokenizer::emitter::{Emitter, Mix, SpanTokens, Spans};
-#[cfg(feature = "encoding")]
-use crate::tokenizer::encoding::EncodingRef;
+use crate::events::{Attrs, DocTypeText, EncodedText};
+use crate::tokenizer::char_ref::CharRefTokenizer;
+use crate::tokenizer::config::TokenFilter;
+pub use crate::tokenizer::config::TokenizerConfig;
+pub use crate::tokenizer::emitter::{
+    DefaultEmitter, Emitter, IntoBytes, Mix, SpanTokens, Spans, XmlDeclAttr,
+};
+pub use crate::tokenizer::position::{Position, Span};
+pub use crate::tokenizer::push::PushTokenizer;
 use crate::tokenizer::reader::{BuffReader, Reader, SliceReader};
-use crate::tokenizer::TokenState::Cdata;
 use crate::Token;
-use crate::Token::Text;
 
+#[cfg(feature = "tokio")]
+pub use crate::tokenizer::async_iter::AsyncBufIterator;
+pub use crate::tokenizer::byte_iter::ByteIterator;
+
+#[cfg(feature = "tokio")]
+mod async_iter;
+mod byte_iter;
+pub(crate) mod char_ref;
+mod config;
+// superseded by `crate::encoding`, which the tokenizer uses
+#[allow(dead_code)]
 mod decoding;
 mod emitter;
 #[cfg(feature = "encoding")]
 mod encoding;
 mod machine;
+mod partial;
+mod position;
+mod push;
 mod reader;
 
 #[derive(Default)]
@@ -22,115 +45,298 @@ pub struct Tokenizer {
     state: TokenState,
     /// End of file reached - parsing stops
     eof: bool,
-    /// encoding specified in the xml, or utf8 if none found
-    #[cfg(feature = "encoding")]
-    encoder_ref: EncodingRef,
-    /// checks if xml5 could identify encoding
-    #[cfg(feature = "encoding")]
-    is_encoding_set: bool,
+    /// sub-tokenizer for the character reference being read
+    char_ref: CharRefTokenizer,
+    /// whether the internal subset of the doctype gets parsed
+    parse_dtd: bool,
+    /// raw internal subset, collected while it is being read
+    subset: Vec<u8>,
+    /// declarations from the internal subset
+    dtd: Option<Dtd>,
+    /// entities expanded so far in the document
+    expansions: Expansions,
+    /// decoder of the input, UTF-8 unless found otherwise
+    decoder: Decoder,
+    /// `encoding` of the XML declaration, while it is being read
+    declared: Vec<u8>,
+    /// names of the states gone through, when traced
+    #[cfg(feature = "test-harness")]
+    visited: Option<BTreeSet<String>>,
+    /// config applied to the tokens before they are returned
+    filter: TokenFilter,
 }
 
+impl Tokenizer {
+    /// Runs the state machine until the emitter has a token ready, or the input
+    /// is exhausted.
+    fn next_token<'r, R, E>(&mut self, reader: &mut R, emitter: &mut E) -> Option<E::Output>
+    where
+        R: Reader<'r>,
+        E: Emitter,
+    {
+        loop {
+            if let Some(token) = emitter.pop_token() {
+                return Some(token);
+            } else if self.eof {
+                return None;
+            }
+            self.step(reader, emitter);
+        }
+    }
+
+    /// Runs the state machine for a single step.
+    fn step<'r, R, E>(&mut self, reader: &mut R, emitter: &mut E)
+    where
+        R: Reader<'r>,
+        E: Emitter,
+    {
+        // every token starts in data state, be it text or markup
+        if let TokenState::Data = self.state {
+            emitter.set_token_start(reader.position());
+        }
+        #[cfg(feature = "test-harness")]
+        if let Some(visited) = &mut self.visited {
+            let state = format!("{:?}", self.state);
+            let name = state.split('(').next().unwrap_or_default();
+            visited.insert(name.to_string());
+        }
+        match self.next_state(reader, emitter) {
+            Control::Continue => (),
+            Control::Eof => {
+                self.eof = true;
+                emitter.emit_eof();
+            }
+            Control::Err(err) => {
+                self.eof = true;
+                emitter.emit_error(err);
+            }
+        }
+        emitter.set_position(reader.position());
+    }
+}
+
+/// Tokens of a string, created with
+/// [`Tokenizer::from_str_reader`]. Tokens borrow from the string where they
+/// can.
 pub struct SliceIterator<'a, E> {
     state: Tokenizer,
     reader: SliceReader<'a>,
     emitter: E,
 }
 
+impl<'a, E> SliceIterator<'a, E> {
+    /// Declarations from the internal subset, if it was parsed.
+    pub fn dtd(&self) -> Option<&Dtd> {
+        self.state.dtd.as_ref()
+    }
+
+    /// Decoder named by the BOM or the XML declaration, UTF-8 otherwise. The
+    /// string is read as UTF-8 regardless, so this tells what it declares.
+    pub fn decoder(&self) -> Decoder {
+        self.state.decoder
+    }
+
+    /// Names of the states gone through so far, if traced.
+    #[cfg(feature = "test-harness")]
+    #[doc(hidden)]
+    pub fn visited_states(&self) -> Option<&BTreeSet<String>> {
+        self.state.visited.as_ref()
+    }
+}
+
 impl<'a, E> Iterator for SliceIterator<'a, E>
 where
-    E: Emitter<Output = SpanTokens>,
+    E: Emitter<Output = (SpanTokens, Span)>,
 {
     type Item = Token<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let span = loop {
-            if let Some(token) = self.emitter.pop_token() {
-                break token;
-            } else if !self.state.eof {
-                match self.state.next_state(&mut self.reader, &mut self.emitter) {
-                    Control::Continue => (),
-                    Control::Eof => {
-                        self.state.eof = true;
-                        self.emitter.emit_eof();
-                    }
-                    _ => return None,
-                }
-            } else {
-                return None;
+        loop {
+            if let Some((token, span)) = self.state.filter.pop() {
+                return Some(to_token(self.reader.slice, token, span));
             }
-        };
-        Some(match span {
-            SpanTokens::EndTag(Some(sp)) => Token::end_tag(self.to_cow(sp)),
-            SpanTokens::PiData { data, target } => {
-                Token::pi_tag(self.to_cow(data), self.to_cow(target))
-            }
-
-            SpanTokens::Comment(text) => Token::comment(self.to_cow(text)),
-            SpanTokens::Decl(text) => Token::declaration(self.to_cow(text)),
-            SpanTokens::CData(text) => Token::cdata(self.to_cow(text)),
-
-            SpanTokens::Text(text) => Token::text(self.to_cow(text)),
-            SpanTokens::StartTag {
-                name,
-                attrs,
-                self_close: true,
-                ..
-            } => Token::start_tag(self.to_cow(name), self.to_attrs(attrs)),
-            SpanTokens::StartTag {
-                name,
-                attrs,
-                self_close: false,
-                ..
-            } => Token::empty_tag(self.to_cow(name), self.to_attrs(attrs)),
-            SpanTokens::Error(err) => Token::Error(err),
-            SpanTokens::Eof => Token::Eof,
-            SpanTokens::EndTag(None) => Token::auto_close_tag(),
-        })
+            let (token, span) = self.state.next_token(&mut self.reader, &mut self.emitter)?;
+            self.state.filter.push(token, span, self.reader.slice);
+        }
     }
 }
 
-impl<'a, E> SliceIterator<'a, E> {
-    fn to_cow(&self, span: Spans) -> Cow<'a, [u8]> {
-        if let Some((start, end)) = span.to_range() {
-            Cow::Borrowed(&self.reader.slice[start..end])
-        } else {
-            let mut vec = vec![];
-            for datum in span.data {
-                match datum {
-                    Mix::Owned(bytes) => vec.extend_from_slice(&bytes),
-                    Mix::Range(s, e) => vec.extend_from_slice(&self.reader.slice[s..e]),
-                }
-            }
-            Cow::Owned(vec)
-        }
+/// Iterator over the tokens of a custom [`Emitter`], created with
+/// [`Tokenizer::with_emitter`]. Ranges given to the emitter are of the input
+/// string's bytes.
+pub struct EmitterIterator<'a, E> {
+    state: Tokenizer,
+    reader: SliceReader<'a>,
+    emitter: E,
+}
+
+impl<'a, E> EmitterIterator<'a, E> {
+    /// Declarations from the internal subset, if it was parsed.
+    pub fn dtd(&self) -> Option<&Dtd> {
+        self.state.dtd.as_ref()
     }
 
-    fn to_attrs(&self, attrs: Vec<(Spans, Spans)>) -> Vec<(Cow<'a, [u8]>, Cow<'a, [u8]>)> {
-        let mut vec = vec![];
-        for (name, val) in attrs {
-            vec.push((self.to_cow(name), self.to_cow(val)));
-        }
-        vec
+    /// Decoder of the input, from its BOM or XML declaration once those are
+    /// read.
+    pub fn decoder(&self) -> Decoder {
+        self.state.decoder
+    }
+
+    /// The emitter tokens are built with.
+    pub fn emitter(&self) -> &E {
+        &self.emitter
+    }
+
+    /// Stops reading and returns the emitter, e.g. with what it has collected.
+    pub fn into_emitter(self) -> E {
+        self.emitter
     }
 }
 
+impl<'a, E: Emitter> Iterator for EmitterIterator<'a, E> {
+    type Item = E::Output;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.state.next_token(&mut self.reader, &mut self.emitter)
+    }
+}
+
+/// Tokens read from a [`BufRead`] source, created with
+/// [`Tokenizer::from_buf_reader`].
 pub struct BufIterator<'a, R, E> {
     state: Tokenizer,
     reader: BuffReader<'a, R>,
     emitter: E,
 }
 
+impl<'a, R, E> BufIterator<'a, R, E> {
+    /// Declarations from the internal subset, if it was parsed.
+    pub fn dtd(&self) -> Option<&Dtd> {
+        self.state.dtd.as_ref()
+    }
+
+    /// Decoder of the bytes read, from the BOM or the `encoding` of the XML
+    /// declaration once read. Tokens hold the bytes to be decoded with it.
+    pub fn decoder(&self) -> Decoder {
+        self.state.decoder
+    }
+}
+
+impl<'a, R, E> Iterator for BufIterator<'a, R, E>
+where
+    R: BufRead,
+    E: Emitter<Output = (SpanTokens, Span)>,
+{
+    type Item = Token<'static>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((token, span)) = self.state.filter.pop() {
+                return Some(to_token(self.reader.buffer, token, span).into_owned());
+            }
+            let (token, span) = match self.emitter.pop_token() {
+                Some(token) => token,
+                None => {
+                    // Every queued token was already copied out of the buffer,
+                    // so it can be reused as long as no token is partially read.
+                    if let TokenState::Data = self.state.state {
+                        self.state.filter.detach(self.reader.buffer);
+                        self.reader.buffer.clear();
+                    }
+                    self.state.next_token(&mut self.reader, &mut self.emitter)?
+                }
+            };
+            self.state.filter.push(token, span, self.reader.buffer);
+        }
+    }
+}
+
+fn to_cow(input: &[u8], span: Spans) -> Cow<'_, [u8]> {
+    span.into_bytes(input)
+}
+
+fn to_attrs(input: &[u8], attrs: Vec<(Spans, Spans)>) -> Attrs<'_> {
+    attrs
+        .into_iter()
+        .map(|(name, val)| (to_cow(input, name), to_cow(input, val)))
+        .collect()
+}
+
+/// UTF-8 encoded byte order mark.
+const UTF8_BOM: &[u8] = b"\xEF\xBB\xBF";
+
+/// Token of `input` read from `span`.
+fn to_token(input: &[u8], token: SpanTokens, span: Span) -> Token<'_> {
+    let token = match token {
+        SpanTokens::Bom => Token::Bom(EncodedText {
+            buf: Cow::Borrowed(UTF8_BOM),
+            decoder: Decoder::utf8().with_source(EncodingSource::Bom),
+            span,
+        }),
+        SpanTokens::EndTag(Some(sp)) => Token::end_tag(to_cow(input, sp)),
+        SpanTokens::PiData { data, target } => {
+            Token::pi_tag(to_cow(input, data), to_cow(input, target))
+        }
+
+        SpanTokens::Comment(text) => Token::comment(to_cow(input, text)),
+        SpanTokens::DocType {
+            name,
+            public_id,
+            system_id,
+            internal_subset,
+        } => Token::DocType(DocTypeText {
+            name: to_cow(input, name),
+            public_id: public_id.map(|id| to_cow(input, id)),
+            system_id: system_id.map(|id| to_cow(input, id)),
+            internal_subset: internal_subset.map(|subset| to_cow(input, subset)),
+            span,
+        }),
+        SpanTokens::Decl {
+            version,
+            encoding,
+            standalone,
+        } => Token::declaration(
+            version.map(|version| to_cow(input, version)),
+            encoding.map(|encoding| to_cow(input, encoding)),
+            standalone.and_then(|standalone| match &*to_cow(input, standalone) {
+                b"yes" => Some(true),
+                b"no" => Some(false),
+                _ => None,
+            }),
+        ),
+        SpanTokens::CData(text) => Token::cdata(to_cow(input, text)),
+
+        SpanTokens::Text(text) => Token::text(to_cow(input, text)),
+        SpanTokens::StartTag {
+            name,
+            attrs,
+            self_close: false,
+        } => Token::start_tag(to_cow(input, name), to_attrs(input, attrs)),
+        SpanTokens::StartTag {
+            name,
+            attrs,
+            self_close: true,
+        } => Token::empty_tag(to_cow(input, name), to_attrs(input, attrs)),
+        SpanTokens::Error(err) => Token::Error(err, span),
+        SpanTokens::Eof => Token::Eof(span),
+        SpanTokens::EndTag(None) => Token::auto_close_tag(),
+    };
+    token.with_span(span)
+}
+
 pub(crate) enum Control {
     Continue,
     Eof,
     Err(Xml5Error),
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Default)]
 #[doc(hidden)]
-enum TokenState {
+pub enum TokenState {
+    #[default]
     Data,
-    CharRefInData(AttrValueKind),
+    CharRefInData,
+    CharRefInAttrValue(AttrValueKind),
     TagOpen,
     EndTagOpen,
     EndTagName,
@@ -142,10 +348,11 @@ enum TokenState {
     PiAfter,
     XmlDecl,
     XmlDeclAttrName,
-    XmlDeclAttrNameAfter,
-    XmlDeclAttrValueBefore,
-    XmlDeclAttrValue(DeclQuote),
+    XmlDeclAttrNameAfter(XmlDeclAttr),
+    XmlDeclAttrValueBefore(XmlDeclAttr),
+    XmlDeclAttrValue(XmlDeclAttr, DeclQuote, bool),
     XmlDeclAfter,
+    XmlDeclBogus,
     MarkupDecl,
     CommentStart,
     CommentStartDash,
@@ -171,16 +378,21 @@ enum TokenState {
     Doctype,
     BeforeDoctypeName,
     DoctypeName,
-    AfterDoctypeName(usize),
+    AfterDoctypeName,
+    AfterDoctypeKeyword(DoctypeKind),
+    BeforeDoctypeIdentifier(DoctypeKind),
+    DoctypeIdentifier(DoctypeKind, DeclQuote),
+    AfterDoctypeIdentifier(DoctypeKind),
+    BetweenDoctypePublicAndSystem,
+    DoctypeInternalSubset(usize),
+    DoctypeInternalSubsetQuoted(usize, DeclQuote),
+    DoctypeInternalSubsetMarkup(usize, u8),
+    DoctypeInternalSubsetComment(usize, u8),
+    DoctypeInternalSubsetPi(usize, bool),
+    AfterDoctypeInternalSubset,
     BogusDoctype,
 }
 
-impl Default for TokenState {
-    fn default() -> Self {
-        TokenState::Data
-    }
-}
-
 #[derive(Debug, Clone, Copy)]
 #[doc(hidden)]
 pub enum AttrValueKind {
@@ -196,14 +408,27 @@ pub enum DeclQuote {
     DoubleQuoted,
 }
 
-#[derive(Debug, Clone, Copy)]
-#[doc(hidden)]
-pub enum XmlDeclQuoteKind {
-    SingleQuoted,
-    DoubleQuoted,
+impl DeclQuote {
+    #[inline]
+    fn from_byte(quote: u8) -> DeclQuote {
+        if quote == b'\'' {
+            DeclQuote::SingleQuoted
+        } else {
+            DeclQuote::DoubleQuoted
+        }
+    }
+
+    #[inline]
+    fn as_byte(self) -> u8 {
+        match self {
+            DeclQuote::SingleQuoted => b'\'',
+            DeclQuote::DoubleQuoted => b'"',
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
+/// Which identifier of the doctype is meant.
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum DoctypeKind {
     Public,
     System,
@@ -0,0 +1,185 @@
+use crate::encoding::Decoder;
+use crate::errors::Xml5Error;
+use crate::tokenizer::emitter::{DefaultEmitter, Emitter, SpanTokens, Spans};
+use crate::tokenizer::position::{Position, Span};
+use crate::tokenizer::reader::{Reader, SliceReader};
+#[cfg(feature = "encoding")]
+use crate::tokenizer::UTF8_BOM;
+use crate::tokenizer::{to_token, TokenState};
+use crate::{Token, Tokenizer};
+
+/// Tokenizer over input that arrives in chunks.
+///
+/// Received bytes are kept from the start of the token being read. The state
+/// machine only takes a step once it has the input to finish it, so when a
+/// chunk ends in the middle of a token, reading resumes right there once the
+/// next one arrives, and every byte is read once. Text that reaches the end
+/// of the received input is joined with the text read after it.
+pub(crate) struct PartialTokenizer<E> {
+    state: Tokenizer,
+    emitter: E,
+    /// input received so far, starting with the current token
+    buffer: Vec<u8>,
+    /// offset of the next byte to read within `buffer`
+    pos: usize,
+    position: Position,
+    /// offset of the token being read within `buffer`
+    token_pos: usize,
+    /// set once there's no more input to come
+    complete: bool,
+    /// error that ends the input, reported after the tokens before it
+    error: Option<Xml5Error>,
+    /// text cut off where the received input ends, which goes on with the
+    /// text read next
+    text: Option<(Spans, Span)>,
+}
+
+impl PartialTokenizer<DefaultEmitter> {
+    pub(crate) fn new(state: Tokenizer) -> Self {
+        PartialTokenizer {
+            state,
+            emitter: DefaultEmitter::default(),
+            buffer: vec![],
+            pos: 0,
+            position: Position::default(),
+            token_pos: 0,
+            complete: false,
+            error: None,
+            text: None,
+        }
+    }
+
+    pub(crate) fn feed(&mut self, chunk: &[u8]) {
+        // input before the current token is dropped, once the tokens that
+        // still refer to it are copied out of it
+        let keep = match self.state.state {
+            TokenState::Data => self.pos,
+            _ => self.token_pos,
+        };
+        if keep > 0 {
+            self.state.filter.detach(&self.buffer);
+            if let Some((text, _)) = &mut self.text {
+                text.detach(&self.buffer);
+            }
+            self.emitter.rebase(keep);
+            self.buffer.drain(..keep);
+            self.pos -= keep;
+            self.token_pos = 0;
+        }
+        self.buffer.extend_from_slice(chunk);
+    }
+
+    /// Skips a byte order mark that was taken off the input before it's fed,
+    /// so positions are as if it was there in UTF-8.
+    #[cfg(feature = "encoding")]
+    pub(crate) fn skip_bom(&mut self) -> Span {
+        let start = self.position;
+        self.position.advance(UTF8_BOM);
+        Span {
+            start,
+            end: self.position,
+        }
+    }
+
+    /// Marks the end of input, so the remaining tokens can be completed.
+    pub(crate) fn finish(&mut self) {
+        self.complete = true;
+    }
+
+    /// Ends the input with `err`, e.g. when reading it fails. Tokens of the
+    /// input received so far are still returned, followed by the error.
+    pub(crate) fn fail(&mut self, err: Xml5Error) {
+        self.complete = true;
+        self.error.get_or_insert(err);
+    }
+
+    /// Whether every token has been returned.
+    pub(crate) fn is_done(&self) -> bool {
+        self.state.eof
+    }
+
+    pub(crate) fn dtd(&self) -> Option<&crate::Dtd> {
+        self.state.dtd.as_ref()
+    }
+
+    pub(crate) fn decoder(&self) -> Decoder {
+        self.state.decoder
+    }
+
+    /// Next token, or `None` if more input is needed or all tokens were
+    /// returned, which `is_done` tells apart.
+    pub(crate) fn next_token(&mut self) -> Option<Token<'static>> {
+        loop {
+            if let Some((token, span)) = self.state.filter.pop() {
+                return Some(to_token(&self.buffer, token, span).into_owned());
+            }
+            if let Some((token, span)) = self.emitter.pop_token() {
+                self.push(token, span);
+                continue;
+            } else if self.state.eof {
+                return None;
+            }
+
+            let rest = &self.buffer[self.pos..];
+            if !self.complete && self.state.needs_more(rest, self.position.offset) {
+                return None;
+            }
+            let in_data = matches!(self.state.state, TokenState::Data);
+            if in_data {
+                self.token_pos = self.pos;
+            }
+            let mut reader =
+                SliceReader::resume(&self.buffer, self.pos, self.position, !self.complete);
+            self.state.step(&mut reader, &mut self.emitter);
+            self.pos = reader.pos();
+            self.position = reader.position();
+            if in_data {
+                self.join_text(reader.is_cut());
+            }
+        }
+    }
+
+    /// Joins text read in data state with text that was cut off before it,
+    /// so tokens are the same however the input is split. `cut` is whether
+    /// this text is cut off too.
+    fn join_text(&mut self, cut: bool) {
+        let (text, span) = match (self.text.take(), self.emitter.pop_token()) {
+            (Some((mut text, span)), Some((SpanTokens::Text(more), more_span))) => {
+                text.append(more);
+                let span = Span {
+                    start: span.start,
+                    end: more_span.end,
+                };
+                (text, span)
+            }
+            (None, Some((SpanTokens::Text(text), span))) => (text, span),
+            (text, token) => {
+                if let Some((text, span)) = text {
+                    self.push(SpanTokens::Text(text), span);
+                }
+                if let Some((token, span)) = token {
+                    self.push(token, span);
+                }
+                return;
+            }
+        };
+        if cut {
+            self.text = Some((text, span));
+        } else {
+            self.push(SpanTokens::Text(text), span);
+        }
+    }
+
+    /// Passes a token on to the filter, with the error that ended the input
+    /// right before `Eof`.
+    fn push(&mut self, token: SpanTokens, span: Span) {
+        if let SpanTokens::Eof = token {
+            if let Some(err) = self.error.take() {
+                self.state
+                    .filter
+                    .push(SpanTokens::Error(err), span, &self.buffer);
+            }
+        }
+        self.state.filter.push(token, span, &self.buffer);
+    }
+}
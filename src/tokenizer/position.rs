@@ -0,0 +1,53 @@
+/// Location within the input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Position {
+    /// Offset in bytes from the start of the input
+    pub offset: usize,
+    /// Line number, starting at 1
+    pub line: usize,
+    /// Column number, starting at 1 and counted in bytes
+    pub column: usize,
+}
+
+impl Default for Position {
+    fn default() -> Self {
+        Position {
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+}
+
+impl Position {
+    /// Moves the position past `bytes`.
+    pub(crate) fn advance(&mut self, bytes: &[u8]) {
+        self.offset += bytes.len();
+        match bytes.iter().rposition(|&chr| chr == b'\n') {
+            Some(last) => {
+                self.line += bytes.iter().filter(|&&chr| chr == b'\n').count();
+                self.column = bytes.len() - last;
+            }
+            None => self.column += bytes.len(),
+        }
+    }
+}
+
+/// Part of the input a token was read from. For errors, it spans from the
+/// start of the markup being read up to the point the error was found.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Span {
+    pub start: Position,
+    pub end: Position,
+}
+
+#[test]
+fn test_advance() {
+    let mut position = Position::default();
+    position.advance(b"ab");
+    assert_eq!((2, 1, 3), (position.offset, position.line, position.column));
+    position.advance(b"c\n\nde");
+    assert_eq!((7, 3, 3), (position.offset, position.line, position.column));
+    position.advance(b"\n");
+    assert_eq!((8, 4, 1), (position.offset, position.line, position.column));
+}
@@ -0,0 +1,65 @@
+use crate::dtd::Dtd;
+use crate::encoding::Decoder;
+use crate::tokenizer::emitter::DefaultEmitter;
+use crate::tokenizer::partial::PartialTokenizer;
+use crate::{Token, Tokenizer};
+
+/// Tokenizer that is fed input in chunks, for input that doesn't come from a
+/// reader.
+///
+/// Chunks can be split anywhere, even in the middle of a tag or a `]]>`.
+/// Iterating returns tokens completed by the input fed so far, and `None`
+/// when more input is needed. Once [`finish`](PushTokenizer::finish) is
+/// called, the rest of the tokens are returned, ending with `Eof`.
+///
+/// A token split across chunks is read on from where the chunk ended when
+/// the next one arrives, so every byte is read once whatever the chunk size.
+/// Text at the end of a chunk is returned once it's known where it ends.
+pub struct PushTokenizer {
+    pub(crate) inner: PartialTokenizer<DefaultEmitter>,
+}
+
+impl Default for PushTokenizer {
+    fn default() -> Self {
+        Tokenizer::new().into_push()
+    }
+}
+
+impl PushTokenizer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the next chunk of input.
+    pub fn feed(&mut self, chunk: &[u8]) {
+        self.inner.feed(chunk);
+    }
+
+    /// Marks the end of input.
+    pub fn finish(&mut self) {
+        self.inner.finish();
+    }
+
+    /// Whether every token, up to `Eof`, has been returned.
+    pub fn is_done(&self) -> bool {
+        self.inner.is_done()
+    }
+
+    /// Declarations from the internal subset, if it was parsed.
+    pub fn dtd(&self) -> Option<&Dtd> {
+        self.inner.dtd()
+    }
+
+    /// Decoder of the input fed so far, as its BOM or XML declaration tells.
+    pub fn decoder(&self) -> Decoder {
+        self.inner.decoder()
+    }
+}
+
+impl Iterator for PushTokenizer {
+    type Item = Token<'static>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next_token()
+    }
+}
@@ -1,10 +1,8 @@
-use std::borrow::Cow;
 use std::io;
-use std::io::{BufRead, Read};
+use std::io::BufRead;
 
-use crate::errors::Xml5Error::UnexpectedEof;
 use crate::errors::{Xml5Error, Xml5Result};
-use crate::tokenizer::emitter::Spans;
+use crate::tokenizer::position::Position;
 
 pub(crate) trait Reader<'r> {
     fn peek_byte(&mut self) -> Xml5Result<Option<u8>>;
@@ -18,17 +16,75 @@ pub(crate) trait Reader<'r> {
         self.try_read_slice(needle, true)
     }
     fn read_fast_until(&mut self, needle: &[u8]) -> FastRead;
+    /// Position of the next byte to be read.
+    fn position(&self) -> Position;
 }
 
 pub struct BuffReader<'a, S> {
     pub source: S,
     pub buffer: &'a mut Vec<u8>,
+    position: Position,
+    /// bytes taken from the source to look further ahead than it buffers
+    ahead: Vec<u8>,
+    /// error of the source, returned by the next `peek_byte`
+    error: Option<Xml5Error>,
 }
 
 impl<'a, R: BufRead> BuffReader<'a, R> {
-    pub(crate) fn from_str<'s>(source: R, buffer: &'a mut Vec<u8>) -> BuffReader<'a, R> {
-        Self { source, buffer }
+    pub(crate) fn from_reader(source: R, buffer: &'a mut Vec<u8>) -> BuffReader<'a, R> {
+        Self {
+            source,
+            buffer,
+            position: Position::default(),
+            ahead: vec![],
+            error: None,
+        }
+    }
+
+    /// Next `len` bytes, or fewer at the end of input. When the source
+    /// buffers fewer, they are moved aside until there are enough.
+    fn look_ahead(&mut self, len: usize) -> &[u8] {
+        if self.ahead.is_empty() && available(&[], &mut self.source, &mut self.error).len() >= len {
+            return available(&[], &mut self.source, &mut self.error);
+        }
+        while self.ahead.len() < len {
+            let bytes = available(&[], &mut self.source, &mut self.error);
+            if bytes.is_empty() {
+                break;
+            }
+            let size = bytes.len().min(len - self.ahead.len());
+            self.ahead.extend_from_slice(&bytes[..size]);
+            self.source.consume(size);
+        }
+        &self.ahead
+    }
+}
+
+/// Bytes that can be read next, the ones in `ahead` first. Reading from the
+/// source is retried when interrupted, and any other error is kept in
+/// `error`, with no bytes to read until it's returned.
+fn available<'s, R: BufRead>(
+    ahead: &'s [u8],
+    source: &'s mut R,
+    error: &mut Option<Xml5Error>,
+) -> &'s [u8] {
+    if !ahead.is_empty() {
+        return ahead;
+    } else if error.is_some() {
+        return &[];
+    }
+    loop {
+        match source.fill_buf() {
+            Ok(_) => break,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => {
+                *error = Some(Xml5Error::Io(e.to_string()));
+                return &[];
+            }
+        }
     }
+    // already filled, so this doesn't read again
+    source.fill_buf().unwrap_or_default()
 }
 
 impl<'b, B> Reader<'b> for BuffReader<'b, B>
@@ -36,18 +92,27 @@ where
     B: BufRead,
 {
     fn peek_byte(&mut self) -> Xml5Result<Option<u8>> {
-        loop {
-            break match self.source.fill_buf() {
-                Ok(n) if n.is_empty() => Ok(None),
-                Ok(n) => Ok(Some(n[0])),
-                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
-                Err(e) => break Err(Xml5Error::Io(e.to_string())),
-            };
+        let byte = available(&self.ahead, &mut self.source, &mut self.error)
+            .first()
+            .copied();
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => Ok(byte),
         }
     }
 
     fn consume_bytes(&mut self, amount: usize) {
-        self.source.consume(amount);
+        if self.ahead.is_empty() {
+            if let Ok(available) = self.source.fill_buf() {
+                self.position
+                    .advance(&available[..amount.min(available.len())]);
+            }
+            self.source.consume(amount);
+        } else {
+            let amount = amount.min(self.ahead.len());
+            self.position.advance(&self.ahead[..amount]);
+            self.ahead.drain(..amount);
+        }
     }
 
     fn slice_bytes(&self, start: usize, end: usize) -> &[u8] {
@@ -63,83 +128,134 @@ where
     }
 
     fn try_read_slice(&mut self, needle: &str, case_sensitive: bool) -> bool {
-        let mut buff: Vec<u8> = Vec::new();
-        while buff.is_empty() {
-            match self.source.fill_buf() {
-                Ok(n) if n.is_empty() => return false,
-                Ok(n) => buff.extend_from_slice(n),
-                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
-                Err(_) => return false,
-            };
-        }
-
+        let buff = self.look_ahead(needle.len());
         if buff.len() < needle.len() {
             return false;
         }
 
         let read = if case_sensitive {
-            buff[0..needle.len()].starts_with(needle.as_bytes())
+            buff.starts_with(needle.as_bytes())
         } else {
-            needle.as_bytes().iter().enumerate().all(|(offset, char)| {
-                buff[offset].to_ascii_lowercase() == char.to_ascii_lowercase()
-            })
+            buff[..needle.len()].eq_ignore_ascii_case(needle.as_bytes())
         };
 
         if read {
-            self.source.consume(needle.len());
+            self.consume_bytes(needle.len());
         }
         read
     }
 
     fn read_fast_until(&mut self, needle: &[u8]) -> FastRead {
+        let start = self.buffer.len();
         loop {
-            // fill buffer
-            let available = match self.source.fill_buf() {
-                Ok(n) if n.is_empty() => return FastRead::EOF,
-                Ok(n) => n,
-                Err(ref e) if e.kind() == io::ErrorKind::Interrupted => continue,
-                Err(_) => return FastRead::EOF,
-            };
+            let available = available(&self.ahead, &mut self.source, &mut self.error);
+            if available.is_empty() {
+                break;
+            }
 
-            let (read, n) = match fast_find(needle, &available[..]) {
-                Some(0) => (FastRead::Char(available[0]), 1),
+            match fast_find(needle, available) {
+                Some(0) if start == self.buffer.len() => {
+                    let chr = available[0];
+                    self.consume_bytes(1);
+                    return FastRead::Char(chr);
+                }
                 Some(size) => {
-                    let start = self.buffer.len();
                     self.buffer.extend_from_slice(&available[..size]);
-                    (FastRead::InterNeedle(start, start + size), size)
+                    self.consume_bytes(size);
+                    break;
+                }
+                None => {
+                    // needle wasn't found in this chunk, keep reading until it
+                    // is found or the source runs out
+                    let size = available.len();
+                    self.buffer.extend_from_slice(available);
+                    self.consume_bytes(size);
                 }
-                None => (FastRead::EOF, 0),
             };
-            self.consume_bytes(n);
-            return read;
         }
+
+        if start == self.buffer.len() {
+            FastRead::Eof
+        } else {
+            FastRead::InterNeedle(start, self.buffer.len())
+        }
+    }
+
+    fn position(&self) -> Position {
+        self.position
     }
 }
 
 pub struct SliceReader<'a> {
     pub slice: &'a [u8],
     pos: usize,
+    position: Position,
+    /// whether more input can follow the slice
+    partial: bool,
+    /// set once a fast read stopped at the end of the slice, where the
+    /// input doesn't end
+    cut: bool,
 }
 
 impl<'a> SliceReader<'a> {
     pub(crate) fn from_str(input: &'a str) -> SliceReader<'a> {
+        Self::from_slice(input.as_bytes())
+    }
+
+    pub(crate) fn from_slice(input: &'a [u8]) -> SliceReader<'a> {
         Self {
-            slice: input.as_bytes(),
+            slice: input,
             pos: 0,
+            position: Position::default(),
+            partial: false,
+            cut: false,
         }
     }
+
+    /// Reader over input received so far, resuming at `pos`. When `partial`,
+    /// the end of the slice isn't the end of input, so a fast read that
+    /// reaches it is cut off there.
+    pub(crate) fn resume(
+        slice: &'a [u8],
+        pos: usize,
+        position: Position,
+        partial: bool,
+    ) -> SliceReader<'a> {
+        Self {
+            slice,
+            pos,
+            position,
+            partial,
+            cut: false,
+        }
+    }
+
+    #[inline]
+    pub(crate) fn pos(&self) -> usize {
+        self.pos
+    }
+
+    /// Whether a fast read was cut off by the end of the slice.
+    #[inline]
+    pub(crate) fn is_cut(&self) -> bool {
+        self.cut
+    }
+
+    #[inline]
+    fn advance(&mut self, amount: usize) {
+        let end = (self.pos + amount).min(self.slice.len());
+        self.position.advance(&self.slice[self.pos..end]);
+        self.pos = end;
+    }
 }
 
 impl<'r> Reader<'r> for SliceReader<'r> {
     fn peek_byte(&mut self) -> Xml5Result<Option<u8>> {
-        match self.slice.get(self.pos) {
-            Some(x) => Ok(Some(*x)),
-            _ => Err(UnexpectedEof),
-        }
+        Ok(self.slice.get(self.pos).copied())
     }
 
     fn consume_bytes(&mut self, amount: usize) {
-        self.pos += amount;
+        self.advance(amount);
     }
 
     fn slice_bytes(&self, start: usize, end: usize) -> &'r [u8] {
@@ -151,33 +267,44 @@ impl<'r> Reader<'r> for SliceReader<'r> {
     }
 
     fn try_read_slice(&mut self, needle: &str, case_sensitive: bool) -> bool {
-        if self.slice.len() < needle.len() {
+        let rest = &self.slice[self.pos..];
+        if rest.len() < needle.len() {
             return false;
         }
 
         let read = if case_sensitive {
-            self.slice[self.pos..self.pos + needle.len()].starts_with(needle.as_bytes())
+            rest.starts_with(needle.as_bytes())
         } else {
-            needle.as_bytes().iter().enumerate().all(|(offset, char)| {
-                self.slice[self.pos + offset].to_ascii_lowercase() == char.to_ascii_lowercase()
-            })
+            rest[..needle.len()].eq_ignore_ascii_case(needle.as_bytes())
         };
 
         if read {
-            self.pos += needle.len();
+            self.advance(needle.len());
         }
         read
     }
 
     fn read_fast_until(&mut self, needle: &[u8]) -> FastRead {
-        let (read, n) = match fast_find(needle, &self.slice[self.pos..]) {
-            Some(0) => (FastRead::Char(self.slice[self.pos]), 1),
+        let rest = &self.slice[self.pos..];
+        let (read, n) = match fast_find(needle, rest) {
+            Some(0) => (FastRead::Char(rest[0]), 1),
             Some(size) => (FastRead::InterNeedle(self.pos, self.pos + size), size),
-            None => (FastRead::EOF, 0),
+            None if rest.is_empty() => (FastRead::Eof, 0),
+            None => {
+                self.cut = self.partial;
+                (
+                    FastRead::InterNeedle(self.pos, self.slice.len()),
+                    rest.len(),
+                )
+            }
         };
-        self.pos += n;
+        self.advance(n);
         read
     }
+
+    fn position(&self) -> Position {
+        self.position
+    }
 }
 
 #[inline]
@@ -200,9 +327,10 @@ pub(crate) fn fast_find(needle: &[u8], haystack: &[u8]) -> Option<usize> {
 pub(crate) enum FastRead {
     Char(u8),
     InterNeedle(usize, usize),
-    EOF,
+    Eof,
 }
 
+#[cfg(test)]
 trait TestReader<'a>: Reader<'a> {
     fn test_read_fast(&mut self, needle: &str) -> String {
         match self.read_fast_until(needle.as_bytes()) {
@@ -210,14 +338,15 @@ trait TestReader<'a>: Reader<'a> {
             FastRead::InterNeedle(s, e) => {
                 String::from_utf8(self.slice_bytes(s, e).to_vec()).unwrap()
             }
-            FastRead::EOF => "".to_string(),
+            FastRead::Eof => "".to_string(),
         }
     }
 }
 
+#[cfg(test)]
 impl<'a, T> TestReader<'a> for T where T: Reader<'a> {}
 
-#[allow(unused_macros)]
+#[cfg(test)]
 macro_rules! test_readers {
     (($($e:expr),+ ). $me:ident ($arg:expr) = $eq:expr) => {
         $(
@@ -240,7 +369,7 @@ macro_rules! test_readers {
 pub fn test_read_until() {
     let source = "TestString";
     let mut buf = vec![];
-    let mut buff_reader = BuffReader::from_str(source.as_bytes(), &mut buf);
+    let mut buff_reader = BuffReader::from_reader(source.as_bytes(), &mut buf);
     let mut str_reader = SliceReader::from_str(source);
 
     test_readers!((buff_reader, str_reader).test_read_fast("r") = "TestSt");
@@ -254,20 +383,32 @@ pub fn test_read_until() {
 fn test_read_until2() {
     let source = "xyz_abc";
     let mut buf = vec![];
-    let mut buff_reader = BuffReader::from_str(source.as_bytes(), &mut buf);
+    let mut buff_reader = BuffReader::from_reader(source.as_bytes(), &mut buf);
     let mut str_reader = SliceReader::from_str(source);
 
     test_readers!((buff_reader, str_reader).test_read_fast("x") = "x");
     test_readers!((buff_reader, str_reader).test_read_fast("y") = "y");
     test_readers!((buff_reader, str_reader).test_read_fast("z") = "z");
+    test_readers!((buff_reader, str_reader).test_read_fast("??") = "_abc");
     test_readers!((buff_reader, str_reader).test_read_fast("??") = "");
 }
 
+#[test]
+fn test_read_until_across_chunks() {
+    let source = io::BufReader::with_capacity(2, "abcdef<".as_bytes());
+    let mut buf = vec![];
+    let mut buff_reader = BuffReader::from_reader(source, &mut buf);
+
+    assert_eq!("abcdef", buff_reader.test_read_fast("<"));
+    assert_eq!("<", buff_reader.test_read_fast("<"));
+    assert_eq!("", buff_reader.test_read_fast("<"));
+}
+
 #[test]
 fn test_try_read_slice1() {
     let source = "xyz_abc";
     let mut buf = vec![];
-    let mut buff_reader = BuffReader::from_str(source.as_bytes(), &mut buf);
+    let mut buff_reader = BuffReader::from_reader(source.as_bytes(), &mut buf);
     let mut str_reader = SliceReader::from_str(source);
 
     test_readers!((buff_reader, str_reader).try_read_slice("?A?", true) = false);
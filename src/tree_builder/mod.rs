@@ -0,0 +1,185 @@
+use crate::dom::Document;
+use crate::errors::Xml5Error;
+use crate::events::TagAndAttrText;
+pub use crate::tree_builder::sink::TreeSink;
+use crate::Token;
+
+mod sink;
+
+/// Builds a document out of tokens, following the XML5 tree construction
+/// rules. Rather than giving up on malformed input, it recovers from it the
+/// same way every time and reports an error to the sink.
+///
+/// By default it builds a [`Document`], any other document model can be
+/// built by implementing [`TreeSink`] for it.
+pub struct TreeBuilder<S = Document>
+where
+    S: TreeSink,
+{
+    sink: S,
+    /// elements that haven't been closed yet with their names, innermost last
+    open_elements: Vec<(S::Handle, Vec<u8>)>,
+    phase: Phase,
+}
+
+/// Where the builder is in relation to the root element.
+#[derive(Default, Debug, Clone, Copy, PartialEq)]
+enum Phase {
+    /// Before the root element, only prolog is expected
+    #[default]
+    Start,
+    /// Inside of the root element
+    Main,
+    /// Root element was closed, only comments and PIs are expected
+    End,
+}
+
+impl TreeBuilder<Document> {
+    pub fn new() -> Self {
+        Self::with_sink(Document::new())
+    }
+}
+
+impl Default for TreeBuilder<Document> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<S: TreeSink> TreeBuilder<S> {
+    /// Creates a builder that drives `sink` instead of building a [`Document`].
+    pub fn with_sink(sink: S) -> Self {
+        TreeBuilder {
+            sink,
+            open_elements: vec![],
+            phase: Phase::default(),
+        }
+    }
+
+    /// Consumes all `tokens` and returns the finished document.
+    pub fn build<'a, I>(mut self, tokens: I) -> S::Output
+    where
+        I: IntoIterator<Item = Token<'a>>,
+    {
+        for token in tokens {
+            self.process_token(token);
+        }
+        self.finish()
+    }
+
+    /// Returns the document, implicitly closing any elements left open.
+    pub fn finish(mut self) -> S::Output {
+        while let Some((element, _)) = self.open_elements.pop() {
+            self.sink.pop(&element);
+        }
+        self.sink.finish()
+    }
+
+    pub fn process_token(&mut self, token: Token<'_>) {
+        match token {
+            Token::Error(err, _) => self.sink.parse_error(err),
+            Token::Bom(_) | Token::Declaration(_) | Token::Eof(_) => (),
+            Token::DocType(doctype) => {
+                if self.phase != Phase::Start {
+                    self.sink.parse_error(Xml5Error::UnexpectedDoctype);
+                    return;
+                }
+                let node = self.sink.create_doctype(
+                    doctype.get_name(),
+                    doctype.get_public_id(),
+                    doctype.get_system_id(),
+                );
+                self.append(node);
+            }
+            Token::Text(text) => {
+                if self.phase == Phase::Main {
+                    let parent = self.current_node();
+                    self.sink.append_text(&parent, &text);
+                } else if !text.iter().all(u8::is_ascii_whitespace) {
+                    self.sink.parse_error(Xml5Error::ContentOutsideRoot);
+                }
+            }
+            Token::CData(text) => {
+                if self.phase == Phase::Main {
+                    let node = self.sink.create_cdata(&text);
+                    self.append(node);
+                } else {
+                    self.sink.parse_error(Xml5Error::ContentOutsideRoot);
+                }
+            }
+            Token::Comment(text) => {
+                let node = self.sink.create_comment(&text);
+                self.append(node);
+            }
+            Token::PI(pi) => {
+                let node = self.sink.create_pi(pi.get_target(), pi.get_data());
+                self.append(node);
+            }
+            Token::StartTag(tag) => {
+                let name = tag.name.to_vec();
+                let element = self.append_element(tag);
+                self.open_elements.push((element, name));
+                self.phase = Phase::Main;
+            }
+            Token::EmptyTag(tag) => {
+                let element = self.append_element(tag);
+                self.sink.pop(&element);
+                if self.phase == Phase::Start {
+                    self.phase = Phase::End;
+                }
+            }
+            Token::EndTag(tag) => self.close_element(&tag),
+        }
+    }
+
+    /// Node that new nodes get appended to.
+    #[inline]
+    fn current_node(&self) -> S::Handle {
+        match self.open_elements.last() {
+            Some((element, _)) => element.clone(),
+            None => self.sink.get_document(),
+        }
+    }
+
+    fn append(&mut self, node: S::Handle) {
+        let parent = self.current_node();
+        self.sink.append_child(&parent, &node);
+    }
+
+    fn append_element(&mut self, tag: TagAndAttrText<'_>) -> S::Handle {
+        if self.phase == Phase::End {
+            // A second root, keep it rather than lose the content
+            self.sink.parse_error(Xml5Error::ContentOutsideRoot);
+        }
+        let element = self.sink.create_element(&tag.name);
+        self.sink.add_attrs(&element, tag.attrs);
+        self.append(element.clone());
+        element
+    }
+
+    /// Handles `</name>`, or `</>` which closes the current element.
+    fn close_element(&mut self, name: &[u8]) {
+        let position = if name.is_empty() {
+            self.open_elements.len().checked_sub(1)
+        } else {
+            self.open_elements
+                .iter()
+                .rposition(|(_, element)| element == name)
+        };
+        match position {
+            None => self.sink.parse_error(Xml5Error::UnmatchedEndTag),
+            Some(position) => {
+                if position + 1 != self.open_elements.len() {
+                    // elements opened after the matching one are closed too
+                    self.sink.parse_error(Xml5Error::MisnestedEndTag);
+                }
+                for (element, _) in self.open_elements.drain(position..).rev() {
+                    self.sink.pop(&element);
+                }
+                if self.open_elements.is_empty() {
+                    self.phase = Phase::End;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,42 @@
+use crate::errors::Xml5Error;
+use crate::events::Attrs;
+
+/// Receiver of the tree construction steps taken by a
+/// [`TreeBuilder`](crate::TreeBuilder), so it can build any document model,
+/// much like an `Emitter` receives the steps taken by the tokenizer.
+///
+/// Nodes are referred to by handles, which are expected to be cheap to clone,
+/// e.g. an index into an arena or a reference counted pointer.
+pub trait TreeSink {
+    type Handle: Clone;
+    type Output;
+
+    /// Returns the finished document, once all tokens were processed.
+    fn finish(self) -> Self::Output;
+    fn parse_error(&mut self, err: Xml5Error);
+
+    /// Handle of the document node, the parent of top-level nodes.
+    fn get_document(&self) -> Self::Handle;
+    fn get_parent(&self, node: &Self::Handle) -> Option<Self::Handle>;
+
+    fn create_element(&mut self, name: &[u8]) -> Self::Handle;
+    fn add_attrs(&mut self, element: &Self::Handle, attrs: Attrs<'_>);
+    fn create_comment(&mut self, text: &[u8]) -> Self::Handle;
+    fn create_cdata(&mut self, text: &[u8]) -> Self::Handle;
+    fn create_pi(&mut self, target: &[u8], data: &[u8]) -> Self::Handle;
+    fn create_doctype(
+        &mut self,
+        name: &[u8],
+        public_id: Option<&[u8]>,
+        system_id: Option<&[u8]>,
+    ) -> Self::Handle;
+
+    fn append_child(&mut self, parent: &Self::Handle, child: &Self::Handle);
+    /// Appends text to `parent`. Text may arrive in several pieces, which
+    /// the sink is free to merge into a single node.
+    fn append_text(&mut self, parent: &Self::Handle, text: &[u8]);
+
+    /// Called once `element` is closed and no more children will be
+    /// appended to it.
+    fn pop(&mut self, _element: &Self::Handle) {}
+}
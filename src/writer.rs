@@ -0,0 +1,197 @@
+//! Serialization of [`Token`]s back into XML.
+
+use std::io::Write;
+
+use crate::errors::{Xml5Error, Xml5Result};
+use crate::escape::escape_bytes;
+use crate::events::DocTypeText;
+use crate::Token;
+
+/// Writes tokens as XML to the underlying [`Write`].
+///
+/// Text and attribute values are escaped, so tokens from the tokenizer are
+/// written back in a form that tokenizes into the same tokens. `</>` is
+/// written out with the name of the element it closes. Comments can't hold
+/// `--`, so a space is written between such dashes.
+pub struct Writer<W: Write> {
+    writer: W,
+    /// names of elements that are still open, innermost last
+    open_elements: Vec<Vec<u8>>,
+}
+
+impl<W: Write> Writer<W> {
+    pub fn new(writer: W) -> Self {
+        Writer {
+            writer,
+            open_elements: vec![],
+        }
+    }
+
+    #[inline]
+    pub fn get_ref(&self) -> &W {
+        &self.writer
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+
+    /// Writes a single token. `Eof` and `Error` tokens don't produce any
+    /// output.
+    pub fn write_token(&mut self, token: &Token<'_>) -> Xml5Result<()> {
+        match token {
+            Token::Bom(bom) => self.writer.write_all(&bom.buf)?,
+            Token::Text(text) => self.write_escaped(text, false)?,
+            Token::StartTag(tag) | Token::EmptyTag(tag) => {
+                self.writer.write_all(b"<")?;
+                self.writer.write_all(&tag.name)?;
+                for (name, value) in &tag.attrs {
+                    self.writer.write_all(b" ")?;
+                    self.writer.write_all(name)?;
+                    self.writer.write_all(b"=\"")?;
+                    self.write_escaped(value, true)?;
+                    self.writer.write_all(b"\"")?;
+                }
+                if let Token::EmptyTag(_) = token {
+                    self.writer.write_all(b"/>")?;
+                } else {
+                    self.writer.write_all(b">")?;
+                    self.open_elements.push(tag.name.to_vec());
+                }
+            }
+            Token::EndTag(tag) => {
+                // the element is closed along with the ones opened in it,
+                // like the tree builder does
+                let position = if tag.is_empty() {
+                    self.open_elements.len().checked_sub(1)
+                } else {
+                    self.open_elements.iter().rposition(|open| open == &tag[..])
+                };
+                let name = match position {
+                    Some(position) => self.open_elements.drain(position..).next().unwrap(),
+                    None => tag.to_vec(),
+                };
+                self.writer.write_all(b"</")?;
+                self.writer.write_all(&name)?;
+                self.writer.write_all(b">")?;
+            }
+            Token::Comment(text) => {
+                // `--` can't be in a comment, so dashes are kept apart with a
+                // space, also from the `--` that ends it
+                self.writer.write_all(b"<!--")?;
+                let mut start = 0;
+                for (pos, pair) in text.windows(2).enumerate() {
+                    if pair == b"--" {
+                        self.writer.write_all(&text[start..=pos])?;
+                        self.writer.write_all(b" ")?;
+                        start = pos + 1;
+                    }
+                }
+                self.writer.write_all(&text[start..])?;
+                if text.ends_with(b"-") {
+                    self.writer.write_all(b" ")?;
+                }
+                self.writer.write_all(b"-->")?;
+            }
+            Token::CData(text) => {
+                self.writer.write_all(b"<![CDATA[")?;
+                // `]]>` can't be in CDATA, so it's split between two sections
+                let mut rest = &text[..];
+                while let Some(pos) = rest.windows(3).position(|x| x == b"]]>") {
+                    self.writer.write_all(&rest[..pos + 2])?;
+                    self.writer.write_all(b"]]><![CDATA[")?;
+                    rest = &rest[pos + 2..];
+                }
+                self.writer.write_all(rest)?;
+                self.writer.write_all(b"]]>")?;
+            }
+            Token::Declaration(decl) => {
+                self.writer.write_all(b"<?xml")?;
+                // the version is the one pseudo-attribute that's required
+                let pseudo_attrs = [
+                    (&b"version"[..], decl.get_version().or(Some(b"1.0"))),
+                    (b"encoding", decl.get_encoding()),
+                    (
+                        b"standalone",
+                        decl.get_standalone()
+                            .map(|yes| if yes { &b"yes"[..] } else { b"no" }),
+                    ),
+                ];
+                for (name, value) in pseudo_attrs {
+                    if let Some(value) = value {
+                        self.writer.write_all(b" ")?;
+                        self.writer.write_all(name)?;
+                        self.writer.write_all(b"=\"")?;
+                        self.writer.write_all(value)?;
+                        self.writer.write_all(b"\"")?;
+                    }
+                }
+                self.writer.write_all(b"?>")?;
+            }
+            Token::PI(pi) => {
+                if pi.get_data().windows(2).any(|x| x == b"?>") {
+                    return Err(Xml5Error::InvalidPiData);
+                }
+                self.writer.write_all(b"<?")?;
+                self.writer.write_all(pi.get_target())?;
+                if !pi.get_data().is_empty() {
+                    self.writer.write_all(b" ")?;
+                    self.writer.write_all(pi.get_data())?;
+                }
+                self.writer.write_all(b"?>")?;
+            }
+            Token::DocType(doctype) => self.write_doctype(doctype)?,
+            Token::Eof(_) | Token::Error(..) => (),
+        }
+        Ok(())
+    }
+
+    fn write_doctype(&mut self, doctype: &DocTypeText<'_>) -> Xml5Result<()> {
+        self.writer.write_all(b"<!DOCTYPE ")?;
+        self.writer.write_all(doctype.get_name())?;
+        match (doctype.get_public_id(), doctype.get_system_id()) {
+            (Some(public_id), system_id) => {
+                self.writer.write_all(b" PUBLIC ")?;
+                self.write_quoted(public_id)?;
+                if let Some(system_id) = system_id {
+                    self.writer.write_all(b" ")?;
+                    self.write_quoted(system_id)?;
+                }
+            }
+            (None, Some(system_id)) => {
+                self.writer.write_all(b" SYSTEM ")?;
+                self.write_quoted(system_id)?;
+            }
+            (None, None) => (),
+        }
+        if let Some(subset) = doctype.get_internal_subset() {
+            self.writer.write_all(b" [")?;
+            self.writer.write_all(subset)?;
+            self.writer.write_all(b"]")?;
+        }
+        self.writer.write_all(b">")?;
+        Ok(())
+    }
+
+    /// Writes a doctype identifier, which can't be escaped, so the quote is
+    /// picked to not clash with its content.
+    fn write_quoted(&mut self, id: &[u8]) -> Xml5Result<()> {
+        let quote: &[u8] = if id.contains(&b'"') { b"'" } else { b"\"" };
+        self.writer.write_all(quote)?;
+        self.writer.write_all(id)?;
+        self.writer.write_all(quote)?;
+        Ok(())
+    }
+
+    fn write_escaped(&mut self, text: &[u8], in_attr: bool) -> Xml5Result<()> {
+        let escaped = escape_bytes(text, |chr| match chr {
+            b'<' | b'&' => true,
+            b'>' => !in_attr,
+            b'"' => in_attr,
+            _ => false,
+        });
+        self.writer.write_all(&escaped)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,84 @@
+//! Entity expansion stays within its limits in both time and memory, which is
+//! measured with an allocator that tracks the peak of each thread.
+
+extern crate xml5;
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+use xml5::{Token, Tokenizer, Xml5Error};
+
+struct PeakAlloc;
+
+thread_local! {
+    static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+    static PEAK: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for PeakAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let _ = ALLOCATED.try_with(|allocated| {
+            allocated.set(allocated.get() + layout.size());
+            let _ = PEAK.try_with(|peak| peak.set(peak.get().max(allocated.get())));
+        });
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let _ = ALLOCATED
+            .try_with(|allocated| allocated.set(allocated.get().saturating_sub(layout.size())));
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: PeakAlloc = PeakAlloc;
+
+/// Tokens of `input` along with how long it took to read them, and how much
+/// more memory was in use at most.
+fn tokenize(input: &str) -> (Vec<Token<'static>>, Duration, usize) {
+    let base = ALLOCATED.with(Cell::get);
+    PEAK.with(|peak| peak.set(base));
+    let start = Instant::now();
+    let tokens = Tokenizer::new()
+        .parse_internal_subset(true)
+        .from_str_reader(input)
+        .map(Token::into_owned)
+        .collect();
+    let elapsed = start.elapsed();
+    (tokens, elapsed, PEAK.with(Cell::get) - base)
+}
+
+/// Internal subset where `lol1` expands to about 1 MB, and `lol2` to 200
+/// times that.
+fn laughs() -> String {
+    let mut subset = format!("<!ENTITY lol0 \"{}\">", "x".repeat(1000));
+    subset.push_str(&format!("<!ENTITY lol1 \"{}\">", "&lol0;".repeat(1000)));
+    subset.push_str(&format!("<!ENTITY lol2 \"{}\">", "&lol1;".repeat(200)));
+    subset
+}
+
+#[test]
+fn test_nested_expansion_limit() {
+    let input = format!("<!DOCTYPE a [{}]><a>&lol2;</a>", laughs());
+    let (tokens, elapsed, peak) = tokenize(&input);
+    assert!(tokens.contains(&Token::error(Xml5Error::EntityExpansionLimit)));
+    assert!(elapsed < Duration::from_secs(2), "took {:?}", elapsed);
+    assert!(peak < 16 << 20, "used {} bytes", peak);
+}
+
+#[test]
+fn test_document_expansion_limit() {
+    let input = format!("<!DOCTYPE a [{}]><a>{}</a>", laughs(), "&lol1;".repeat(50));
+    let (tokens, elapsed, peak) = tokenize(&input);
+    let text: usize = tokens
+        .iter()
+        .filter_map(Token::get_text)
+        .map(<[u8]>::len)
+        .sum();
+    assert!(tokens.contains(&Token::error(Xml5Error::EntityExpansionLimit)));
+    assert!(text < 10 << 20, "expanded to {} bytes", text);
+    assert!(elapsed < Duration::from_secs(2), "took {:?}", elapsed);
+    assert!(peak < 32 << 20, "used {} bytes", peak);
+}
@@ -1,8 +1,17 @@
 extern crate xml5;
 
+use std::borrow::Cow;
+use std::io::{self, BufReader, Read};
 use std::str;
 
-use xml5::Tokenizer;
+use xml5::dom::NodeData;
+use xml5::encoding::{Decoder, EncodingSource};
+use xml5::namespace::{NsToken, XML_NAMESPACE};
+use xml5::{
+    Attrs, BufIterator, DefaultEmitter, DoctypeKind, Emitter, IntoBytes, Mix, NsReader, Position,
+    Span, SpanTokens, Token, Tokenizer, TokenizerConfig, TreeBuilder, TreeSink, Writer, Xml5Error,
+    XmlDeclAttr,
+};
 
 #[test]
 fn test_mini_part() {
@@ -45,8 +54,1593 @@ fn test_decl() {
     let tokenizer = Tokenizer::new();
     let mut iter = tokenizer.from_str_reader("<?xml encoding='utf8' ?>");
     let next = iter.next();
+    let decl = next.as_ref().unwrap().get_declaration().unwrap();
+    assert_eq!(Some(&b"utf8"[..]), decl.get_encoding());
+    assert_eq!(None, decl.get_version());
+    assert_eq!(None, decl.get_standalone());
+
+    let tokens: Vec<_> = Tokenizer::new()
+        .from_str_reader("<?xml version=\"1.0\" encoding='UTF-8'\n standalone=\"yes\"?><a/>")
+        .collect();
+    assert_eq!(
+        vec![
+            Token::declaration(
+                Some(Cow::Borrowed(b"1.0")),
+                Some(Cow::Borrowed(b"UTF-8")),
+                Some(true)
+            ),
+            Token::empty_tag(Cow::Borrowed(b"a"), vec![]),
+            Token::eof(),
+        ],
+        tokens
+    );
+
+    // not a declaration
+    let tokens: Vec<_> = Tokenizer::new()
+        .from_str_reader("<?xml-stylesheet href='a.xsl'?>")
+        .collect();
+    assert_eq!(Some(&b"xml-stylesheet"[..]), tokens[0].get_target());
+}
+
+#[test]
+fn test_decl_errors() {
+    let tokens: Vec<_> = Tokenizer::new()
+        .from_str_reader("<?xml version='1.0' foo='bar' standalone='no'?><a/>")
+        .collect();
+    assert_eq!(
+        vec![
+            Token::error(Xml5Error::UnknownXmlDeclarationAttribute),
+            Token::declaration(Some(Cow::Borrowed(b"1.0")), None, None),
+            Token::empty_tag(Cow::Borrowed(b"a"), vec![]),
+            Token::eof(),
+        ],
+        tokens
+    );
+
+    let tokens: Vec<_> = Tokenizer::new()
+        .from_str_reader("<?xml standalone='maybe'?>")
+        .collect();
+    assert_eq!(
+        vec![
+            Token::error(Xml5Error::InvalidXmlDeclaration),
+            Token::declaration(None, None, None),
+            Token::eof(),
+        ],
+        tokens
+    );
+
+    let tokens: Vec<_> = Tokenizer::new()
+        .from_str_reader("<?xml version='1.0")
+        .collect();
+    assert_eq!(
+        vec![
+            Token::error(Xml5Error::EofInXmlDeclaration),
+            Token::declaration(Some(Cow::Borrowed(b"1.0")), None, None),
+            Token::eof(),
+        ],
+        tokens
+    );
+}
+
+#[test]
+fn test_buf_reader() {
+    let source = BufReader::with_capacity(4, "<xml>long text</xml>tail".as_bytes());
+    let mut buf = Vec::new();
+    let mut iter: BufIterator<_, DefaultEmitter> =
+        Tokenizer::new().from_buf_reader(source, &mut buf);
+    assert_eq!(b"xml", iter.next().unwrap().get_name().unwrap());
+    assert_eq!(b"long text", iter.next().unwrap().get_text().unwrap());
+    assert_eq!(b"xml", iter.next().unwrap().get_name().unwrap());
+    assert_eq!(b"tail", iter.next().unwrap().get_text().unwrap());
+    assert_eq!(Some(Token::eof()), iter.next());
+    assert_eq!(None, iter.next());
+}
+
+#[test]
+fn test_buf_reader_split_keywords() {
+    // keywords are read the same wherever the source's buffer ends
+    let inputs = [
+        "\u{FEFF}<?xml version='1.0' encoding='UTF-8' standalone='yes'?><a/>",
+        "<!DOCTYPE a PUBLIC 'p' 'q'><a/>",
+        "<!doctype a system 's'><a/>",
+        "<a><!--c--></a>",
+        "<a>x<![CDATA[y]]>z</a>",
+        "<?xml-stylesheet href='s'?><a/>",
+    ];
+    let configs = [
+        TokenizerConfig::new(),
+        TokenizerConfig::new()
+            .drop_comments(true)
+            .cdata_as_text(true)
+            .coalesce_text(true),
+    ];
+    for input in inputs {
+        for config in &configs {
+            let expected: Vec<_> = Tokenizer::with_config(config.clone())
+                .from_str_reader(input)
+                .map(|token| (token.span(), token.into_owned()))
+                .collect();
+            for capacity in 1..=9 {
+                let source = BufReader::with_capacity(capacity, input.as_bytes());
+                let tokens: Vec<_> = Tokenizer::with_config(config.clone())
+                    .from_buf_reader(source, &mut Vec::new())
+                    .map(|token| (token.span(), token))
+                    .collect();
+                assert_eq!(expected, tokens, "{:?} in chunks of {}", input, capacity);
+            }
+        }
+    }
+}
+
+/// Source that returns `data` in reads that are each interrupted once, and
+/// then fails.
+struct FailingReader {
+    data: &'static [u8],
+    interrupted: bool,
+}
+
+impl Read for FailingReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.interrupted = !self.interrupted;
+        if self.interrupted {
+            return Err(io::ErrorKind::Interrupted.into());
+        } else if self.data.is_empty() {
+            return Err(io::Error::other("disconnected"));
+        }
+        let size = buf.len().min(self.data.len());
+        buf[..size].copy_from_slice(&self.data[..size]);
+        self.data = &self.data[size..];
+        Ok(size)
+    }
+}
+
+#[test]
+fn test_buf_reader_error() {
+    // interruptions are retried, and other errors end the input
+    let source = FailingReader {
+        data: b"<a>some text",
+        interrupted: false,
+    };
+    let tokens: Vec<_> = Tokenizer::new()
+        .from_buf_reader(BufReader::with_capacity(2, source), &mut Vec::new())
+        .collect();
+    assert_eq!(
+        vec![
+            Token::start_tag(Cow::Borrowed(b"a"), vec![]),
+            Token::text(Cow::Borrowed(b"some text")),
+            Token::error(Xml5Error::Io("disconnected".to_string())),
+        ],
+        tokens
+    );
+}
+
+#[test]
+fn test_char_ref_in_text() {
+    let tokens: Vec<_> = Tokenizer::new()
+        .from_str_reader("a &lt;&#98;&#x1F600; &bogus; c")
+        .collect();
+    assert_eq!(
+        vec![
+            Token::text(Cow::Borrowed(b"a ")),
+            Token::text(Cow::Borrowed(b"<")),
+            Token::text(Cow::Borrowed(b"b")),
+            Token::text(Cow::Borrowed("😀".as_bytes())),
+            Token::text(Cow::Borrowed(b" ")),
+            Token::error(Xml5Error::UnknownNamedCharRef),
+            Token::text(Cow::Borrowed(b"&bogus;")),
+            Token::text(Cow::Borrowed(b" c")),
+            Token::eof(),
+        ],
+        tokens
+    );
+}
+
+#[test]
+fn test_attributes() {
+    let tokens: Vec<_> = Tokenizer::new()
+        .from_str_reader(r#"<a x="1 &amp; 2" y='z' w=v><b/></>"#)
+        .collect();
+    assert_eq!(
+        vec![
+            Token::start_tag(
+                Cow::Borrowed(b"a"),
+                vec![
+                    (Cow::Borrowed(b"x"), Cow::Borrowed(b"1 & 2")),
+                    (Cow::Borrowed(b"y"), Cow::Borrowed(b"z")),
+                    (Cow::Borrowed(b"w"), Cow::Borrowed(b"v")),
+                ]
+            ),
+            Token::empty_tag(Cow::Borrowed(b"b"), vec![]),
+            Token::auto_close_tag(),
+            Token::eof(),
+        ],
+        tokens
+    );
+}
+
+#[test]
+fn test_attributes_lookup() {
+    let token = Tokenizer::new()
+        .from_str_reader(r#"<a x="1 &amp;lt; &#233;" y='z' x='dup'/>"#)
+        .next()
+        .unwrap();
+    let attrs = token.get_attributes().unwrap();
+    assert_eq!(3, attrs.len());
+    let x = attrs.get(b"x").unwrap();
+    assert_eq!(b"x", x.key);
+    assert_eq!("1 &lt; \u{E9}".as_bytes(), x.value);
+    assert_eq!("1 &lt; \u{E9}", x.unescaped_value().unwrap());
+    assert_eq!(
+        "z",
+        attrs
+            .get(b"y")
+            .unwrap()
+            .decode_and_unescape_value(Decoder::utf8())
+            .unwrap()
+    );
+    assert_eq!(None, attrs.get(b"w"));
+
+    // duplicates are only reported when asked for
+    let names: Vec<_> = attrs.clone().map(|attr| attr.unwrap().key).collect();
+    assert_eq!(vec![&b"x"[..], b"y", b"x"], names);
+    let checked: Vec<_> = attrs.with_checks(true).collect();
+    assert_eq!(
+        Err(Xml5Error::DuplicatedAttribute(b"x".to_vec())),
+        checked[2]
+    );
+    assert!(checked[..2].iter().all(Result::is_ok));
+
+    assert!(Token::eof().get_attributes().is_none());
+
+    let token = Token::empty_tag(
+        Cow::Borrowed(b"a"),
+        vec![(Cow::Borrowed(b"x"), Cow::Borrowed(b"&bogus;"))],
+    );
+    let x = token.get_attributes().unwrap().get(b"x").unwrap();
+    assert_eq!("&bogus;", x.unescaped_value().unwrap());
+}
+
+#[cfg(feature = "encoding")]
+#[test]
+fn test_attributes_decoded_once() {
+    // the tokenizer transcodes the value and expands its references, so
+    // neither is done again
+    let input: &[u8] = b"<?xml version='1.0' encoding='windows-1252'?><a x='\xE9 &amp;amp;'/>";
+    let mut iter = Tokenizer::new().from_bytes(input);
+    let token = iter.nth(1).unwrap();
+    let x = token.get_attributes().unwrap().get(b"x").unwrap();
+    assert_eq!(
+        "\u{E9} &amp;",
+        x.decode_and_unescape_value(iter.decoder()).unwrap()
+    );
+}
+
+#[test]
+fn test_name_as_str() {
+    let mut buffer = Vec::new();
+    let source: &[u8] = b"<\xC3\xA9/><a\xFF/>";
+    let tags: Vec<_> = Tokenizer::new()
+        .from_buf_reader(source, &mut buffer)
+        .filter_map(|token| match token {
+            Token::EmptyTag(tag) => Some(tag.name_as_str(Decoder::utf8()).map(String::from)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(Ok("\u{E9}".to_string()), tags[0]);
+    assert!(tags[1].is_err());
+}
+
+#[test]
+fn test_comment_and_doctype() {
+    let tokens: Vec<_> = Tokenizer::new()
+        .from_str_reader("<!DOCTYPE root><!-- a -- b --><!bogus>")
+        .collect();
+    assert_eq!(
+        vec![
+            Token::doctype(Cow::Borrowed(b"root")),
+            Token::comment(Cow::Borrowed(b" a -- b ")),
+            Token::error(Xml5Error::IncorrectlyOpenedComment),
+            Token::comment(Cow::Borrowed(b"bogus")),
+            Token::eof(),
+        ],
+        tokens
+    );
+}
+
+#[test]
+fn test_doctype_ids() {
+    let input = r#"<!DOCTYPE svg PUBLIC "-//W3C//DTD SVG 1.1//EN" 'http://www.w3.org/svg11.dtd' [
+  <!ENTITY x "]>">
+]>"#;
+    let mut iter = Tokenizer::new().from_str_reader(input);
+    match iter.next() {
+        Some(Token::DocType(doctype)) => {
+            assert_eq!(b"svg", doctype.get_name());
+            assert_eq!(
+                Some(&b"-//W3C//DTD SVG 1.1//EN"[..]),
+                doctype.get_public_id()
+            );
+            assert_eq!(
+                Some(&b"http://www.w3.org/svg11.dtd"[..]),
+                doctype.get_system_id()
+            );
+            assert_eq!(
+                Some(&b"\n  <!ENTITY x \"]>\">\n"[..]),
+                doctype.get_internal_subset()
+            );
+        }
+        token => panic!("Expected doctype, got {:?}", token),
+    }
+    assert_eq!(Some(Token::eof()), iter.next());
+
+    let mut iter = Tokenizer::new().from_str_reader("<!DOCTYPE html SYSTEM>");
+    assert_eq!(
+        Some(Token::error(Xml5Error::MissingDoctypeIdentifier)),
+        iter.next()
+    );
+    match iter.next() {
+        Some(Token::DocType(doctype)) => {
+            assert_eq!(b"html", doctype.get_name());
+            assert_eq!(None, doctype.get_public_id());
+            assert_eq!(None, doctype.get_system_id());
+        }
+        token => panic!("Expected doctype, got {:?}", token),
+    }
+}
+
+#[test]
+fn test_dtd_entities() {
+    let input =
+        r#"<!DOCTYPE a [<!ENTITY me "J. Doe"><!ENTITY sig "-- &me;">]><a by="&me;">&sig;</a>"#;
+
+    let mut iter = Tokenizer::new()
+        .parse_internal_subset(true)
+        .from_str_reader(input);
+    assert!(matches!(iter.next(), Some(Token::DocType(_))));
+    assert!(iter.dtd().unwrap().get_entity(b"sig").is_some());
+    let tokens: Vec<_> = iter.collect();
+    assert_eq!(
+        vec![
+            Token::start_tag(
+                Cow::Borrowed(b"a"),
+                vec![(Cow::Borrowed(b"by"), Cow::Borrowed(b"J. Doe"))]
+            ),
+            Token::text(Cow::Borrowed(b"-- J. Doe")),
+            Token::end_tag(Cow::Borrowed(b"a")),
+            Token::eof(),
+        ],
+        tokens
+    );
+
+    // without opting in, user entities stay unknown
+    let tokens: Vec<_> = Tokenizer::new().from_str_reader(input).skip(3).collect();
+    assert_eq!(
+        vec![
+            Token::error(Xml5Error::UnknownNamedCharRef),
+            Token::text(Cow::Borrowed(b"&sig;")),
+            Token::end_tag(Cow::Borrowed(b"a")),
+            Token::eof(),
+        ],
+        tokens
+    );
+}
+
+#[test]
+fn test_tree_builder() {
+    let input = "<!DOCTYPE a><!--c--><a x='1'>t<b>u<c/>v</><d>w</a>tail<?pi x?>";
+    let tokens = Tokenizer::new().from_str_reader(input);
+    let document = TreeBuilder::new().build(tokens);
+
+    let doc = &document[document.document()];
+    assert_eq!(4, doc.children.len());
+    assert!(
+        matches!(&document[doc.children[0]].data, NodeData::Doctype { name, .. } if name == b"a")
+    );
+    assert_eq!(
+        NodeData::Comment(b"c".to_vec()),
+        document[doc.children[1]].data
+    );
+    assert_eq!(
+        NodeData::Pi {
+            target: b"pi".to_vec(),
+            data: b"x".to_vec()
+        },
+        document[doc.children[3]].data
+    );
+
+    let root = document.root_element().unwrap();
+    assert_eq!(Some(&b"1"[..]), document[root].get_attr(b"x"));
+    let names: Vec<_> = document[root]
+        .children
+        .iter()
+        .map(|&child| document[child].get_name())
+        .collect();
+    assert_eq!(vec![None, Some(&b"b"[..]), Some(&b"d"[..])], names);
+    assert_eq!(b"tuvw".to_vec(), document.text_content(root));
+
+    // `</a>` implicitly closes `<d>`
+    assert_eq!(
+        &[Xml5Error::MisnestedEndTag, Xml5Error::ContentOutsideRoot],
+        document.errors()
+    );
+}
+
+#[test]
+fn test_tree_builder_unmatched_end_tag() {
+    let tokens = Tokenizer::new().from_str_reader("<a><b></c>x</b></a>");
+    let document = TreeBuilder::new().build(tokens);
+    let root = document.root_element().unwrap();
+    let b = document[root].children[0];
+    assert_eq!(Some(&b"b"[..]), document[b].get_name());
+    assert_eq!(b"x".to_vec(), document.text_content(b));
+    assert_eq!(&[Xml5Error::UnmatchedEndTag], document.errors());
+}
+
+/// Sink that records the nesting of elements as a string, using the element's
+/// index as its handle.
+#[derive(Default)]
+struct OutlineSink {
+    names: Vec<String>,
+    parents: Vec<Option<usize>>,
+    outline: String,
+}
+
+impl TreeSink for OutlineSink {
+    type Handle = usize;
+    type Output = String;
+
+    fn finish(self) -> String {
+        self.outline
+    }
+
+    fn parse_error(&mut self, _err: Xml5Error) {
+        self.outline.push('!');
+    }
+
+    fn get_document(&self) -> usize {
+        usize::MAX
+    }
+
+    fn get_parent(&self, node: &usize) -> Option<usize> {
+        self.parents[*node]
+    }
+
+    fn create_element(&mut self, name: &[u8]) -> usize {
+        self.names.push(String::from_utf8(name.to_vec()).unwrap());
+        self.parents.push(None);
+        self.names.len() - 1
+    }
+
+    fn add_attrs(&mut self, _element: &usize, attrs: Attrs<'_>) {
+        self.outline.push_str(&format!("{}", attrs.len()));
+    }
+
+    fn create_comment(&mut self, _text: &[u8]) -> usize {
+        self.create_element(b"#comment")
+    }
+
+    fn create_cdata(&mut self, _text: &[u8]) -> usize {
+        self.create_element(b"#cdata")
+    }
+
+    fn create_pi(&mut self, _target: &[u8], _data: &[u8]) -> usize {
+        self.create_element(b"#pi")
+    }
+
+    fn create_doctype(&mut self, _: &[u8], _: Option<&[u8]>, _: Option<&[u8]>) -> usize {
+        self.create_element(b"#doctype")
+    }
+
+    fn append_child(&mut self, parent: &usize, child: &usize) {
+        if *parent != usize::MAX {
+            self.parents[*child] = Some(*parent);
+        }
+        self.outline.push_str(&format!("({}", self.names[*child]));
+    }
+
+    fn append_text(&mut self, _parent: &usize, _text: &[u8]) {
+        self.outline.push('~');
+    }
+
+    fn pop(&mut self, element: &usize) {
+        assert!(*element == 0 || self.get_parent(element).is_some());
+        self.outline.push(')');
+    }
+}
+
+#[test]
+fn test_tree_sink() {
+    let tokens = Tokenizer::new().from_str_reader("<a x='1'><b>text<c/></a><!--x-->");
+    let outline = TreeBuilder::with_sink(OutlineSink::default()).build(tokens);
+    assert_eq!("1(a0(b~0(c)!))(#comment", outline);
+}
+
+/// Formats the resolved name in `{namespace}local` notation.
+fn ns_name(token: &NsToken) -> String {
+    let (name, close) = match token {
+        NsToken::StartTag(tag) | NsToken::EmptyTag(tag) => (&tag.name, ""),
+        NsToken::EndTag(name) => (name, "/"),
+        NsToken::Other(Token::Error(err, _)) => return format!("Error({:?})", err),
+        NsToken::Other(Token::Eof(_)) => return "Eof".to_string(),
+        NsToken::Other(token) => return format!("{:?}", token),
+    };
+    let namespace = name.namespace.as_deref().unwrap_or_default();
+    format!(
+        "{}{{{}}}{}",
+        close,
+        str::from_utf8(namespace).unwrap(),
+        str::from_utf8(&name.local_name).unwrap()
+    )
+}
+
+#[test]
+fn test_namespaces() {
+    let input = concat!(
+        r#"<a xmlns="urn:a" xmlns:s="urn:s"><s:b s:x="1" y="2" xml:lang="en"/>"#,
+        r#"<c xmlns="" xmlns:s="urn:t"><s:d/></c><e:f/></a>"#
+    );
+    let tokens: Vec<_> = NsReader::new(Tokenizer::new().from_str_reader(input)).collect();
+
+    let names: Vec<_> = tokens.iter().map(ns_name).collect();
+    assert_eq!(
+        vec![
+            "{urn:a}a",
+            "{urn:s}b",
+            "{}c",
+            "{urn:t}d",
+            "/{}c",
+            "Error(UnboundPrefix([101]))",
+            "{}f",
+            "/{urn:a}a",
+            "Eof",
+        ],
+        names
+    );
+
+    match &tokens[1] {
+        NsToken::EmptyTag(tag) => {
+            let attrs: Vec<_> = tag
+                .attrs
+                .iter()
+                .map(|(name, _)| name.namespace.as_deref())
+                .collect();
+            assert_eq!(vec![Some(&b"urn:s"[..]), None, Some(XML_NAMESPACE)], attrs);
+        }
+        token => panic!("Expected empty tag, got {:?}", token),
+    }
+    // unbound prefixes are reported at the tag using them
+    match &tokens[5] {
+        NsToken::Other(token) => assert_eq!(input.find("<e:f/>"), Some(token.span().start.offset)),
+        token => panic!("Expected error, got {:?}", token),
+    }
+}
+
+#[test]
+fn test_writer_round_trip() {
+    let input = concat!(
+        r#"<?xml version="1.0" standalone="no"?><!DOCTYPE a SYSTEM 'a"b.dtd' [<!ENTITY x "y">]><a x="1 &lt; &quot;2&quot;" y="&amp;">"#,
+        "text &amp; &lt;b&gt;<![CDATA[<raw> ]]]]><![CDATA[>]]><!--c--><?pi data?><e/></a>",
+    );
+    let mut writer = Writer::new(Vec::new());
+    for token in Tokenizer::new().from_str_reader(input) {
+        writer.write_token(&token).unwrap();
+    }
+    let output = String::from_utf8(writer.into_inner()).unwrap();
+    assert_eq!(input, output);
+
+    let mut writer = Writer::new(Vec::new());
+    for token in Tokenizer::new().from_str_reader("<a><b>x</></>") {
+        writer.write_token(&token).unwrap();
+    }
+    assert_eq!(b"<a><b>x</b></a>", &writer.into_inner()[..]);
+
+    // misnested end tags close what they close when read
+    for (input, expected) in [
+        ("<a><b></a></>", "<a><b></a></>"),
+        ("<a><b></c></></>", "<a><b></c></b></a>"),
+        ("<a><b><c></b></>", "<a><b><c></b></a>"),
+    ] {
+        let mut writer = Writer::new(Vec::new());
+        for token in Tokenizer::new().from_str_reader(input) {
+            writer.write_token(&token).unwrap();
+        }
+        assert_eq!(expected.as_bytes(), &writer.into_inner()[..]);
+    }
+
+    // dashes that can't be in comments are kept apart
+    let mut writer = Writer::new(Vec::new());
+    for token in Tokenizer::new().from_str_reader("<!--a--b---c--><!--d--->") {
+        writer.write_token(&token).unwrap();
+    }
+    let output = writer.into_inner();
+    assert_eq!(b"<!--a- -b- - -c--><!--d- -->", &output[..]);
+    let tokens: Vec<_> = Tokenizer::new().from_bytes(&output).collect();
+    assert_eq!(
+        vec![
+            Token::comment(Cow::Borrowed(b"a- -b- - -c")),
+            Token::comment(Cow::Borrowed(b"d- ")),
+            Token::eof(),
+        ],
+        tokens
+    );
+
+    // a declaration always has a version
+    let mut writer = Writer::new(Vec::new());
+    let decl = Token::declaration(None, Some(Cow::Borrowed(b"UTF-8")), None);
+    writer.write_token(&decl).unwrap();
+    let output = writer.into_inner();
+    assert_eq!(br#"<?xml version="1.0" encoding="UTF-8"?>"#, &output[..]);
+    let mut iter = Tokenizer::new().from_bytes(&output);
+    assert_eq!(
+        Some(Token::declaration(
+            Some(Cow::Borrowed(b"1.0")),
+            Some(Cow::Borrowed(b"UTF-8")),
+            None
+        )),
+        iter.next()
+    );
+}
+
+#[test]
+fn test_spans() {
+    let input = "<a>x &lt;\n <!bogus></a>";
+    let spans: Vec<_> = Tokenizer::new()
+        .from_str_reader(input)
+        .map(|token| {
+            let span = token.span();
+            (
+                token,
+                (span.start.offset, span.end.offset),
+                (span.start.line, span.start.column),
+            )
+        })
+        .collect();
+    assert_eq!(
+        vec![
+            (
+                Token::start_tag(Cow::Borrowed(b"a"), vec![]),
+                (0, 3),
+                (1, 1)
+            ),
+            (Token::text(Cow::Borrowed(b"x ")), (3, 5), (1, 4)),
+            (Token::text(Cow::Borrowed(b"<")), (5, 9), (1, 6)),
+            (Token::text(Cow::Borrowed(b"\n ")), (9, 11), (1, 10)),
+            (
+                Token::error(Xml5Error::IncorrectlyOpenedComment),
+                (11, 13),
+                (2, 2)
+            ),
+            (Token::comment(Cow::Borrowed(b"bogus")), (11, 19), (2, 2)),
+            (Token::end_tag(Cow::Borrowed(b"a")), (19, 23), (2, 10)),
+            (Token::eof(), (23, 23), (2, 14)),
+        ],
+        spans
+    );
+
+    // spans don't depend on how the input is read
+    let sliced: Vec<_> = Tokenizer::new()
+        .from_str_reader(input)
+        .map(|token| token.span())
+        .collect();
+    let buffered: Vec<_> = Tokenizer::new()
+        .from_buf_reader(
+            BufReader::with_capacity(3, input.as_bytes()),
+            &mut Vec::new(),
+        )
+        .map(|token| token.span())
+        .collect();
+    assert_eq!(sliced, buffered);
+    let bytes: Vec<_> = Tokenizer::new()
+        .from_byte_reader(BufReader::with_capacity(3, input.as_bytes()))
+        .map(|token| token.span())
+        .collect();
+    assert_eq!(sliced, bytes);
+    let mut push = Tokenizer::new().into_push();
+    let mut pushed = vec![];
+    for chunk in input.as_bytes().chunks(2) {
+        push.feed(chunk);
+        pushed.extend(push.by_ref().map(|token| token.span()));
+    }
+    push.finish();
+    pushed.extend(push.map(|token| token.span()));
+    assert_eq!(sliced, pushed);
+}
+
+#[cfg(feature = "serialize")]
+#[test]
+fn test_deserialize() {
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    enum Kind {
+        Book,
+        Journal,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Author {
+        #[serde(rename = "$text")]
+        name: String,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Item {
+        #[serde(rename = "@id")]
+        id: u32,
+        #[serde(rename = "@tags", default)]
+        tags: Vec<String>,
+        title: String,
+        kind: Kind,
+        #[serde(default)]
+        author: Vec<Author>,
+        price: Option<f64>,
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Catalog {
+        item: Vec<Item>,
+    }
+
+    // `</>` closes the last element and `<price>` is left unclosed
+    let input = r#"<catalog>
+        <item id="1" tags="new sale">
+            <title>XML &amp; you</title><author>Ann</author>
+            <kind>Book</kind><author>Bob</>
+        </item>
+        <!-- comment -->
+        <item id="2"><kind>Journal</kind><title><![CDATA[<X>]]></title><price>2.5</item>
+    </catalog>"#;
+    let catalog: Catalog = xml5::de::from_str(input).unwrap();
+    assert_eq!(
+        catalog,
+        Catalog {
+            item: vec![
+                Item {
+                    id: 1,
+                    tags: vec!["new".to_string(), "sale".to_string()],
+                    title: "XML & you".to_string(),
+                    kind: Kind::Book,
+                    author: vec![
+                        Author {
+                            name: "Ann".to_string()
+                        },
+                        Author {
+                            name: "Bob".to_string()
+                        },
+                    ],
+                    price: None,
+                },
+                Item {
+                    id: 2,
+                    tags: vec![],
+                    title: "<X>".to_string(),
+                    kind: Kind::Journal,
+                    author: vec![],
+                    price: Some(2.5),
+                },
+            ]
+        }
+    );
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    enum Shape {
+        Circle { r: u32 },
+        Point,
+    }
+    let shapes: Vec<Shape> =
+        xml5::de::from_str("<shapes><s><Circle><r>3</r></Circle></s><s>Point</s></shapes>")
+            .unwrap();
+    assert_eq!(shapes, vec![Shape::Circle { r: 3 }, Shape::Point]);
+
+    let map: std::collections::BTreeMap<String, String> =
+        xml5::de::from_str(r#"<m a="1"><b>2</b></m>"#).unwrap();
+    assert_eq!(map["@a"], "1");
+    assert_eq!(map["b"], "2");
+
+    assert!(xml5::de::from_str::<u32>("<n>x</n>").is_err());
+    assert!(xml5::de::from_str::<u32>("").is_err());
+}
+
+#[cfg(feature = "serialize")]
+#[test]
+fn test_deserialize_reader() {
+    use serde::Deserialize;
+    use xml5::de::{DeError, Deserializer};
+    use xml5::{Tokenizer, TreeBuilder};
+
+    let input = "<?xml version='1.0'?><!-- five --><n>5</n>";
+    let reader = BufReader::with_capacity(1, input.as_bytes());
+    assert_eq!(xml5::de::from_reader::<_, u32>(reader), Ok(5));
+
+    let input: &[u8] = b"<n>a\xFFb</n>";
+    let mut buffer = vec![];
+    let tokens = Tokenizer::new().from_buf_reader(input, &mut buffer);
+    let document = TreeBuilder::new().build(tokens);
+    let strict = Deserializer::from_document(&document).unwrap();
+    assert!(matches!(
+        String::deserialize(strict),
+        Err(DeError::Xml(Xml5Error::NonDecodable(_)))
+    ));
+    let lossy = Deserializer::from_document(&document).unwrap().lossy(true);
+    assert_eq!(String::deserialize(lossy).unwrap(), "a\u{FFFD}b");
+
+    #[cfg(not(feature = "encoding"))]
+    assert!(matches!(
+        xml5::de::from_reader::<_, String>(input),
+        Err(DeError::Xml(Xml5Error::NonDecodable(_)))
+    ));
+    #[cfg(feature = "encoding")]
+    {
+        let input: &[u8] = b"<?xml version='1.0' encoding='windows-1252'?><n>caf\xE9</n>";
+        assert_eq!(
+            xml5::de::from_reader::<_, String>(input).unwrap(),
+            "caf\u{E9}"
+        );
+    }
+}
+
+#[cfg(feature = "serialize")]
+#[test]
+fn test_serialize() {
+    use serde::{Deserialize, Serialize};
+    use xml5::ser::Serializer;
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    enum Kind {
+        Book,
+        Journal,
+    }
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct Item {
+        #[serde(rename = "@id")]
+        id: u32,
+        #[serde(rename = "@note", skip_serializing_if = "Option::is_none")]
+        note: Option<String>,
+        title: String,
+        kind: Kind,
+        #[serde(default)]
+        author: Vec<String>,
+    }
+
+    #[derive(Debug, Deserialize, Serialize, PartialEq)]
+    struct Catalog {
+        item: Vec<Item>,
+    }
+
+    let catalog = Catalog {
+        item: vec![
+            Item {
+                id: 1,
+                note: Some("\"quoted\"".to_string()),
+                title: "XML & <you>".to_string(),
+                kind: Kind::Book,
+                author: vec!["Ann".to_string(), "Bob".to_string()],
+            },
+            Item {
+                id: 2,
+                note: None,
+                title: "Other".to_string(),
+                kind: Kind::Journal,
+                author: vec![],
+            },
+        ],
+    };
+    let xml = xml5::ser::to_string(&catalog).unwrap();
+    assert_eq!(
+        xml,
+        "<Catalog><item id=\"1\" note=\"&quot;quoted&quot;\"><title>XML &amp; &lt;you&gt;</title>\
+         <kind>Book</kind><author>Ann</author><author>Bob</author></item>\
+         <item id=\"2\"><title>Other</title><kind>Journal</kind></item></Catalog>"
+    );
+    assert_eq!(xml5::de::from_str::<Catalog>(&xml).unwrap(), catalog);
+
+    #[derive(Serialize)]
+    struct Point {
+        x: i32,
+        y: i32,
+        #[serde(rename = "$text")]
+        label: &'static str,
+    }
+
+    #[derive(Serialize)]
+    struct Config {
+        name: &'static str,
+        point: Vec<Point>,
+    }
+
+    let config = Config {
+        name: "shapes",
+        point: vec![
+            Point {
+                x: 1,
+                y: 2,
+                label: "a",
+            },
+            Point {
+                x: 3,
+                y: 4,
+                label: "",
+            },
+        ],
+    };
+    let mut buffer = vec![];
+    config
+        .serialize(
+            Serializer::new(&mut buffer)
+                .root_name("config")
+                .indent(b' ', 2),
+        )
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(buffer).unwrap(),
+        "<config>\n  <name>shapes</name>\n  \
+         <point>a<x>1</x><y>2</y></point>\n  \
+         <point>\n    <x>3</x>\n    <y>4</y>\n  </point>\n</config>"
+    );
+
+    let mut buffer = vec![];
+    config
+        .serialize(
+            Serializer::new(&mut buffer)
+                .root_name("config")
+                .primitives_as_attributes(true),
+        )
+        .unwrap();
+    assert_eq!(
+        String::from_utf8(buffer).unwrap(),
+        "<config name=\"shapes\"><point x=\"1\" y=\"2\">a</point><point x=\"3\" y=\"4\"/></config>"
+    );
+}
+
+#[cfg(feature = "serialize")]
+#[test]
+fn test_serialize_invalid_names() {
+    use std::collections::BTreeMap;
+
+    use serde::Serialize;
+    use xml5::ser::{SeError, Serializer};
+
+    let to_string = |map: &BTreeMap<&str, &str>, root: &str| {
+        let mut buffer = vec![];
+        map.serialize(Serializer::new(&mut buffer).root_name(root))
+            .map(|_| String::from_utf8(buffer).unwrap())
+    };
+
+    // map keys become names, which can't be written unless they are XML names
+    for key in ["a><b", "", "1a", "a b", "@", "@x=\"1\""] {
+        let map = BTreeMap::from([(key, "x")]);
+        match to_string(&map, "m") {
+            Err(SeError::InvalidName(name)) => assert_eq!(name, key.trim_start_matches('@')),
+            result => panic!("expected an invalid name for {:?}, got {:?}", key, result),
+        }
+    }
+    let map = BTreeMap::from([("\u{E9}t\u{E9}", "x"), ("@a:b-c.d", "y")]);
+    assert_eq!(
+        to_string(&map, "m").unwrap(),
+        "<m a:b-c.d=\"y\"><\u{E9}t\u{E9}>x</\u{E9}t\u{E9}></m>"
+    );
+    assert!(matches!(to_string(&map, "<m>"), Err(SeError::InvalidName(name)) if name == "<m>"));
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_async_reader() {
+    use tokio::io::{AsyncWriteExt, BufReader};
+
+    let input = "<?xml version='1.0'?><!DOCTYPE a [<!ENTITY e 'x'>]><a x='1&amp;2'>\
+                 text &lt; &#65; &e; <![CDATA[c]]d]]><!-- c - d --><?pi data?><b/></a>";
+    let expected: Vec<_> = Tokenizer::new()
+        .parse_internal_subset(true)
+        .from_str_reader(input)
+        .map(|token| (token.span(), token.into_owned()))
+        .collect();
+
+    // the duplex buffer size limits how many bytes arrive at once
+    for max_size in [1, 2, 3, 7, 64] {
+        let (mut writer, reader) = tokio::io::duplex(max_size);
+        let write = tokio::spawn(async move { writer.write_all(input.as_bytes()).await });
+
+        let mut tokens = Tokenizer::new()
+            .parse_internal_subset(true)
+            .from_async_reader(BufReader::new(reader));
+        let mut actual = vec![];
+        while let Some(token) = tokens.next().await {
+            actual.push((token.span(), token));
+        }
+        write.await.unwrap().unwrap();
+        assert_eq!(actual, expected, "chunks of {} bytes", max_size);
+        assert!(tokens.dtd().is_some());
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[tokio::test]
+async fn test_async_reader_large_token() {
+    // a token that arrives a byte at a time is read once, not again from its
+    // start with every byte
+    let size = 4 << 20;
+    let mut input = b"<![CDATA[".to_vec();
+    input.resize(9 + size, b'x');
+    input.extend_from_slice(b"]]>");
+    let reader = tokio::io::BufReader::with_capacity(1, &input[..]);
+    let mut tokens = Tokenizer::new().from_async_reader(reader);
+    let cdata = tokens.next().await.unwrap();
+    assert_eq!(cdata, Token::cdata(Cow::Owned(vec![b'x'; size])));
+    assert_eq!(cdata.span().end.offset, input.len());
+    assert_eq!(tokens.next().await, Some(Token::eof()));
+    assert_eq!(tokens.next().await, None);
+}
+
+#[test]
+fn test_push_tokenizer() {
+    use xml5::PushTokenizer;
+
+    let mut push = PushTokenizer::new();
+    push.feed(b"<root a='x");
+    assert_eq!(push.next(), None);
+    push.feed(b"'>some ");
+    assert_eq!(push.next().unwrap().get_name(), Some(&b"root"[..]));
+    // text may continue in the next chunk
+    assert_eq!(push.next(), None);
+    push.feed(b"text<!-");
+    assert_eq!(push.next(), Some(Token::text(Cow::Borrowed(b"some text"))));
+    assert_eq!(push.next(), None);
+    push.feed(b"- a -->");
+    assert_eq!(push.next(), Some(Token::comment(Cow::Borrowed(b" a "))));
+    assert!(!push.is_done());
+    push.finish();
+    assert_eq!(push.next(), Some(Token::eof()));
+    assert_eq!(push.next(), None);
+    assert!(push.is_done());
+
+    // every split of the input gives the same tokens as reading it whole
+    let input = "<?xml version='1.0' encoding=\"UTF-8\"?><!DOCTYPE a SYSTEM 'a.dtd'>\
+                 <a x=\"1&amp;2\" y=z>text &#x41;&lt;\
+                 <![CDATA[c]]]]><!-- c --><?pi data?><b/></a>";
+    let expected: Vec<_> = Tokenizer::new()
+        .from_str_reader(input)
+        .map(Token::into_owned)
+        .collect();
+    for split in 0..=input.len() {
+        let mut push = PushTokenizer::new();
+        let mut tokens = vec![];
+        push.feed(&input.as_bytes()[..split]);
+        tokens.extend(&mut push);
+        push.feed(&input.as_bytes()[split..]);
+        tokens.extend(&mut push);
+        push.finish();
+        tokens.extend(&mut push);
+        assert_eq!(tokens, expected, "split at {}", split);
+    }
+
+    let mut push = PushTokenizer::new();
+    let mut tokens = vec![];
+    for byte in input.bytes() {
+        push.feed(&[byte]);
+        tokens.extend(&mut push);
+    }
+    push.finish();
+    tokens.extend(&mut push);
+    assert_eq!(tokens, expected);
+}
+
+#[test]
+fn test_push_tokenizer_byte_by_byte() {
+    use xml5::PushTokenizer;
+
+    // a token split across many chunks is read once, not again from its
+    // start with every chunk
+    let size = 4 << 20;
+    let mut input = b"<!--".to_vec();
+    input.resize(4 + size, b'x');
+    input.extend_from_slice(b"-->");
+    let mut push = PushTokenizer::new();
+    let mut tokens = vec![];
+    for byte in &input {
+        push.feed(std::slice::from_ref(byte));
+        tokens.extend(&mut push);
+    }
+    push.finish();
+    tokens.extend(&mut push);
+    assert_eq!(tokens.len(), 2);
+    assert_eq!(tokens[0], Token::comment(Cow::Owned(vec![b'x'; size])));
+    assert_eq!(tokens[0].span().end.offset, input.len());
+    assert_eq!(tokens[1], Token::eof());
+}
+
+#[test]
+fn test_from_bytes() {
+    let input = "<a x='é'>text &amp; ☃</a>";
+    let expected: Vec<_> = Tokenizer::new()
+        .from_str_reader(input)
+        .map(Token::into_owned)
+        .collect();
+    let tokens: Vec<_> = Tokenizer::new().from_bytes(input.as_bytes()).collect();
+    assert_eq!(tokens, expected);
+
+    let source = BufReader::with_capacity(3, input.as_bytes());
+    let tokens: Vec<_> = Tokenizer::new().from_byte_reader(source).collect();
+    assert_eq!(tokens, expected);
+}
+
+#[cfg(not(feature = "encoding"))]
+#[test]
+fn test_from_bytes_invalid_utf8() {
+    // characters split across chunks are fine
+    let source = BufReader::with_capacity(1, "<a>\u{E9}\u{2603}</a>".as_bytes());
+    let tokens: Vec<_> = Tokenizer::new().from_byte_reader(source).collect();
+    assert_eq!(
+        Token::text(Cow::Borrowed("\u{E9}\u{2603}".as_bytes())),
+        tokens[1]
+    );
+
+    let source = BufReader::with_capacity(2, &b"<a>x\xFFy</a>"[..]);
+    let tokens: Vec<_> = Tokenizer::new().from_byte_reader(source).collect();
+    assert_eq!(Token::start_tag(Cow::Borrowed(b"a"), vec![]), tokens[0]);
+    assert_eq!(Token::text(Cow::Borrowed(b"x")), tokens[1]);
+    assert!(matches!(
+        tokens[2],
+        Token::Error(Xml5Error::NonDecodable(_), _)
+    ));
+    assert_eq!(Token::eof(), tokens[3]);
+    assert_eq!(4, tokens.len());
+
+    // and so is a character cut off by the end of input
+    let tokens: Vec<_> = Tokenizer::new().from_bytes(b"<a/>\xC3").collect();
+    assert!(matches!(
+        tokens[1],
+        Token::Error(Xml5Error::NonDecodable(_), _)
+    ));
+}
+
+#[cfg(feature = "encoding")]
+#[test]
+fn test_from_bytes_utf16() {
+    let input = "<a x='é'>text &amp; ☃</a>";
+    // the BOM counts as U+FEFF in spans, as it would in UTF-8
+    let expected: Vec<_> = Tokenizer::new()
+        .from_str_reader(&format!("\u{FEFF}{}", input))
+        .map(Token::into_owned)
+        .collect();
+    let expected_spans: Vec<_> = expected.iter().map(Token::span).collect();
+
+    let mut utf16le = vec![0xFF, 0xFE];
+    let mut utf16be = vec![0xFE, 0xFF];
+    for unit in input.encode_utf16() {
+        utf16le.extend_from_slice(&unit.to_le_bytes());
+        utf16be.extend_from_slice(&unit.to_be_bytes());
+    }
+    for (bytes, capacity) in [(&utf16le, 1), (&utf16le, 3), (&utf16be, 1), (&utf16be, 3)] {
+        // odd chunks split code units, and single bytes split the BOM
+        let source = BufReader::with_capacity(capacity, &bytes[..]);
+        let mut iter = Tokenizer::new().from_byte_reader(source);
+        let token = iter.next().unwrap();
+        assert_eq!(expected_spans[0], token.span());
+        match token {
+            Token::Bom(bom) => {
+                assert_eq!(&bytes[..2], bom.get_bytes());
+                assert_eq!(EncodingSource::Bom, bom.get_decoder().source());
+            }
+            token => panic!("expected BOM, got {:?}", token),
+        }
+        let decoder = iter.decoder();
+        assert_eq!(EncodingSource::Bom, decoder.source());
+        assert!(decoder.encoding().name().starts_with("UTF-16"));
+        let tokens: Vec<_> = iter.collect();
+        assert_eq!(tokens, expected[1..]);
+        let spans: Vec<_> = tokens.iter().map(Token::span).collect();
+        assert_eq!(spans, expected_spans[1..]);
+    }
+}
+
+#[cfg(feature = "encoding")]
+#[test]
+fn test_from_bytes_declared_encoding() {
+    let input: &[u8] = b"<?xml version='1.0' encoding='windows-1252'?><a x='\xE9'>\x80</a>";
+    let source = BufReader::with_capacity(5, input);
+    let mut iter = Tokenizer::new().from_byte_reader(source);
+    let decl = iter.next().unwrap();
+    let decl = decl.get_declaration().unwrap();
+    assert_eq!(Some(&b"windows-1252"[..]), decl.get_encoding());
+    assert_eq!("windows-1252", decl.get_encoder().unwrap().name());
+    assert_eq!(EncodingSource::Declaration, iter.decoder().source());
+    assert_eq!(
+        Token::start_tag(
+            Cow::Borrowed(b"a"),
+            vec![(Cow::Borrowed(b"x"), Cow::Borrowed("\u{E9}".as_bytes()))]
+        ),
+        iter.next().unwrap()
+    );
+    assert_eq!(
+        Token::text(Cow::Borrowed("\u{20AC}".as_bytes())),
+        iter.next().unwrap()
+    );
+
+    let input: &[u8] = b"<?xml version='1.0' encoding='Shift_JIS'?><a>\x82\xA0</a>";
+    let tokens: Vec<_> = Tokenizer::new().from_bytes(input).collect();
+    assert_eq!(Token::text(Cow::Borrowed("\u{3042}".as_bytes())), tokens[2]);
+}
+
+#[cfg(feature = "encoding")]
+#[test]
+fn test_declared_encoding_decoder() {
+    let input: &[u8] = b"<?xml version='1.0' encoding='windows-1252'?><caf\xE9/>";
+    for capacity in 1..9 {
+        let mut buffer = vec![];
+        let source = BufReader::with_capacity(capacity, input);
+        let mut iter = Tokenizer::new().from_buf_reader(source, &mut buffer);
+        assert!(matches!(iter.next(), Some(Token::Declaration(_))));
+        let decoder = iter.decoder();
+        assert_eq!(EncodingSource::Declaration, decoder.source());
+        assert_eq!("windows-1252", decoder.encoding().name());
+        match iter.next() {
+            Some(Token::EmptyTag(tag)) => {
+                assert_eq!("caf\u{E9}", tag.name_as_str(decoder).unwrap())
+            }
+            token => panic!("expected empty tag, got {:?}", token),
+        }
+    }
+
+    let mut push = xml5::PushTokenizer::new();
+    for byte in input {
+        push.feed(&[*byte]);
+    }
+    push.finish();
+    assert!(matches!(push.next(), Some(Token::Declaration(_))));
+    assert_eq!("windows-1252", push.decoder().encoding().name());
+
+    let input = "<?xml version='1.0' encoding='ISO-8859-2'?><a/>";
+    let mut iter = Tokenizer::new().from_str_reader(input);
+    assert!(matches!(iter.next(), Some(Token::Declaration(_))));
+    assert_eq!(EncodingSource::Declaration, iter.decoder().source());
+    assert_eq!("ISO-8859-2", iter.decoder().encoding().name());
+
+    // as when transcoding, encodings that aren't ASCII compatible are not taken
+    let mut iter = Tokenizer::new().from_str_reader("<?xml encoding='UTF-16'?><a/>");
+    iter.next();
+    assert_eq!(EncodingSource::Default, iter.decoder().source());
+    assert_eq!("UTF-8", iter.decoder().encoding().name());
+}
+
+#[test]
+fn test_bom() {
+    let mut iter = Tokenizer::new().from_str_reader("\u{FEFF}<a/>");
+    match iter.next() {
+        Some(Token::Bom(bom)) => assert_eq!(b"\xEF\xBB\xBF", bom.get_bytes()),
+        token => panic!("expected BOM, got {:?}", token),
+    }
+    assert_eq!(EncodingSource::Bom, iter.decoder().source());
+    assert!(matches!(iter.next(), Some(Token::EmptyTag(_))));
+
+    // only at the start of input
+    let tokens: Vec<_> = Tokenizer::new().from_str_reader("<a/>\u{FEFF}").collect();
+    assert!(!tokens.iter().any(|token| matches!(token, Token::Bom(_))));
+
+    let mut iter = Tokenizer::new().from_bytes(b"<a/>");
+    assert!(matches!(iter.next(), Some(Token::EmptyTag(_))));
+    assert_eq!(EncodingSource::Default, iter.decoder().source());
+
+    let mut push = xml5::PushTokenizer::new();
+    push.feed(b"\xEF");
+    assert_eq!(None, push.next());
+    push.feed(b"\xBB\xBF<a/>");
+    push.finish();
+    assert!(matches!(push.next(), Some(Token::Bom(_))));
+    assert_eq!(EncodingSource::Bom, push.decoder().source());
+
+    let tokens: Vec<_> = Tokenizer::new().from_bytes(b"\xEF\xBB\xBF<a/>").collect();
+    assert!(matches!(tokens[0], Token::Bom(_)));
+    assert!(matches!(tokens[1], Token::EmptyTag(_)));
+
+    // a BOM split across reads is still found
+    let source = BufReader::with_capacity(1, &b"\xEF\xBB\xBF<a/>"[..]);
+    let mut iter = Tokenizer::new().from_byte_reader(source);
+    assert!(matches!(iter.next(), Some(Token::Bom(_))));
+    assert_eq!(EncodingSource::Bom, iter.decoder().source());
+    assert!(matches!(iter.next(), Some(Token::EmptyTag(_))));
+}
+
+/// Emitter that only keeps the names of start tags, as ranges of the input,
+/// and the spans of the tags.
+#[derive(Default)]
+struct TagNames {
+    name: Option<(usize, usize)>,
+    in_start_tag: bool,
+    names: Vec<(usize, usize)>,
+    errors: usize,
+    start: Position,
+    tag_start: Option<Position>,
+    spans: Vec<Span>,
+}
+
+impl Emitter for TagNames {
+    type Output = (usize, usize);
+
+    fn pop_token(&mut self) -> Option<(usize, usize)> {
+        self.names.pop()
+    }
+    fn set_token_start(&mut self, position: Position) {
+        self.start = position;
+    }
+    fn set_position(&mut self, position: Position) {
+        if let Some(start) = self.tag_start.take() {
+            self.spans.push(Span {
+                start,
+                end: position,
+            });
+        }
+    }
+    fn create_tag(&mut self) {
+        self.in_start_tag = true;
+    }
+    fn append_tag(&mut self, start: usize, end: usize) {
+        if self.in_start_tag {
+            let name = self.name.get_or_insert((start, end));
+            name.1 = end;
+        }
+    }
+    fn create_end_tag(&mut self) {
+        self.in_start_tag = false;
+    }
+    fn set_empty_tag(&mut self) {}
+    fn create_attr(&mut self) {}
+    fn attr_values(&mut self, _: usize, _: usize) {}
+    fn attr_names(&mut self, _: usize, _: usize) {}
+    fn attr_values_now<T: IntoBytes>(&mut self, _: T) {}
+    fn create_pi_tag(&mut self) {}
+    fn pi_data(&mut self, _: usize, _: usize) {}
+    fn pi_data_now<T: IntoBytes>(&mut self, _: T) {}
+    fn pi_target(&mut self, _: usize, _: usize) {}
+    fn create_doctype(&mut self) {}
+    fn doctype_id(&mut self, _: usize, _: usize) {}
+    fn doctype_name(&mut self, _: usize, _: usize) {}
+    fn clear_doctype_id(&mut self, _: DoctypeKind) {}
+    fn create_doctype_subset(&mut self) {}
+    fn doctype_subset(&mut self, _: usize, _: usize) {}
+    fn create_comment_token(&mut self) {}
+    fn emit_comment(&mut self) {}
+    fn to_comment(&mut self, _: usize, _: usize) {}
+    fn to_comment_now<T: IntoBytes>(&mut self, _: T) {}
+    fn create_cdata(&mut self) {}
+    fn cdata(&mut self, _: usize, _: usize) {}
+    fn cdata_now<T: IntoBytes>(&mut self, _: T) {}
+    fn emit_cdata(&mut self) {}
+    fn emit_bom(&mut self) {}
+    fn emit_eof(&mut self) {}
+    fn emit_pi(&mut self) {}
+    fn emit_error(&mut self, _: Xml5Error) {
+        self.errors += 1;
+    }
+    fn emit_chars(&mut self, _: usize, _: usize) {}
+    fn emit_chars_now<T: IntoBytes>(&mut self, _: T) {}
+    fn emit_end_tag(&mut self) {}
+    fn emit_tag(&mut self) {
+        self.names.extend(self.name.take());
+        self.tag_start = Some(self.start);
+    }
+    fn emit_doctype(&mut self) {}
+    fn set_xml_declaration(&mut self, _: XmlDeclAttr) {}
+    fn emit_decl_value(&mut self, _: usize, _: usize) {}
+    fn emit_xml_decl(&mut self) {}
+}
+
+#[test]
+fn test_with_emitter() {
+    let input = "<?xml version='1.0'?><!-- c --><root a='&amp;'><child/>text</root><x:y>";
+    let mut iter = Tokenizer::new().with_emitter(input, TagNames::default());
+    let names: Vec<_> = iter
+        .by_ref()
+        .map(|(start, end)| &input[start..end])
+        .collect();
+    assert_eq!(vec!["root", "child", "x:y"], names);
+    // unclosed `<x:y>` isn't an error of the tokenizer
+    assert_eq!(0, iter.into_emitter().errors);
+
+    let input = "<a>\n<b/>";
+    let mut iter = Tokenizer::new().with_emitter(input, TagNames::default());
+    iter.by_ref().count();
+    let spans: Vec<_> = iter
+        .emitter()
+        .spans
+        .iter()
+        .map(|span| (span.start.line, span.end.column))
+        .collect();
+    assert_eq!(vec![(1, 4), (2, 5)], spans);
+}
+
+#[test]
+fn test_span_tokens() {
+    let input = b"<a x='1&amp;2'>t</a>";
+    let (tokens, spans): (Vec<_>, Vec<_>) = Tokenizer::new().span_tokens(input).unzip();
+    let offsets: Vec<_> = spans
+        .iter()
+        .map(|span| (span.start.offset, span.end.offset))
+        .collect();
+    assert_eq!(vec![(0, 15), (15, 16), (16, 20), (20, 20)], offsets);
+    match &tokens[..] {
+        [SpanTokens::StartTag {
+            name,
+            attrs,
+            self_close: false,
+        }, SpanTokens::Text(text), SpanTokens::EndTag(Some(end)), SpanTokens::Eof] => {
+            assert_eq!(Some((1, 2)), name.to_range());
+            assert_eq!(Some((15, 16)), text.to_range());
+            assert_eq!(Some((18, 19)), end.to_range());
+            let (key, value) = &attrs[0];
+            assert_eq!(b"x", &*key.bytes(input));
+            // the expanded reference isn't a range of the input
+            assert!(matches!(
+                value.parts(),
+                [Mix::Range(6, 7), Mix::Owned(amp), Mix::Range(12, 13)] if amp == b"&"
+            ));
+            assert_eq!(b"1&2", &*value.bytes(input));
+        }
+        _ => panic!("unexpected tokens {:?}", tokens),
+    }
+}
+
+#[test]
+fn test_tokenizer_config() {
+    let start = |name: &'static str| Token::start_tag(Cow::Borrowed(name.as_bytes()), vec![]);
+    let end = |name: &'static str| Token::end_tag(Cow::Borrowed(name.as_bytes()));
+    let text = |text: &'static str| Token::text(Cow::Borrowed(text.as_bytes()));
+
+    // whitespace around references is part of the text
+    let input = "<a>\n  <b> x </b>\n  <c>&lt; &gt;</c>\n</a>";
+    let expected = vec![
+        start("a"),
+        start("b"),
+        text(" x "),
+        end("b"),
+        start("c"),
+        text("<"),
+        text(" "),
+        text(">"),
+        end("c"),
+        end("a"),
+        Token::eof(),
+    ];
+    let config = TokenizerConfig::new().trim_text(true);
+    let tokens: Vec<_> = Tokenizer::with_config(config.clone())
+        .from_str_reader(input)
+        .collect();
+    assert_eq!(expected, tokens);
+    let mut buffer = vec![];
+    let tokens: Vec<_> = Tokenizer::with_config(config.clone())
+        .from_buf_reader(BufReader::with_capacity(4, input.as_bytes()), &mut buffer)
+        .collect();
+    assert_eq!(expected, tokens);
+    let mut push = Tokenizer::with_config(config).into_push();
+    let mut tokens = vec![];
+    for byte in input.bytes() {
+        push.feed(&[byte]);
+        tokens.extend(&mut push);
+    }
+    push.finish();
+    tokens.extend(&mut push);
+    assert_eq!(expected, tokens);
+
+    // whitespace is dropped with the whole run of text, however it's split,
+    // and errors keep their place in it
+    let input = "<a> &bogus; </a><b> &#32; <!-- c --></b>";
+    let config = TokenizerConfig::new().trim_text(true).drop_comments(true);
+    let tokens: Vec<_> = Tokenizer::with_config(config)
+        .from_str_reader(input)
+        .collect();
+    assert_eq!(
+        vec![
+            start("a"),
+            text(" "),
+            Token::error(Xml5Error::UnknownNamedCharRef),
+            text("&bogus;"),
+            text(" "),
+            end("a"),
+            start("b"),
+            end("b"),
+            Token::eof()
+        ],
+        tokens
+    );
+
+    let input = "<a><!-- c --><?pi x?><b/><![CDATA[d]]></a>";
+    let config = TokenizerConfig::new()
+        .drop_comments(true)
+        .drop_pis(true)
+        .expand_empty_tags(true)
+        .cdata_as_text(true);
+    let tokens: Vec<_> = Tokenizer::with_config(config)
+        .from_str_reader(input)
+        .collect();
+    assert_eq!(
+        vec![
+            start("a"),
+            start("b"),
+            end("b"),
+            text("d"),
+            end("a"),
+            Token::eof()
+        ],
+        tokens
+    );
+
+    let input = "<a>&foo;</a>";
+    let tokens: Vec<_> = Tokenizer::new().from_str_reader(input).collect();
+    assert_eq!(Token::error(Xml5Error::UnknownNamedCharRef), tokens[1]);
+    let config = TokenizerConfig::new().report_errors(false);
+    let tokens: Vec<_> = Tokenizer::with_config(config)
+        .from_str_reader(input)
+        .collect();
+    assert_eq!(
+        vec![start("a"), text("&foo;"), end("a"), Token::eof()],
+        tokens
+    );
+}
+
+#[test]
+fn test_coalesce_text() {
+    let start = |name: &'static str| Token::start_tag(Cow::Borrowed(name.as_bytes()), vec![]);
+    let end = |name: &'static str| Token::end_tag(Cow::Borrowed(name.as_bytes()));
+    let text = |text: &'static str| Token::text(Cow::Borrowed(text.as_bytes()));
+
+    let input = "<a>x &amp; y<![CDATA[<z>]]>w<b/>\n  <![CDATA[ ]]>\n</a>";
+    let expected = vec![
+        start("a"),
+        text("x & y<z>w"),
+        Token::empty_tag(Cow::Borrowed(b"b"), vec![]),
+        end("a"),
+        Token::eof(),
+    ];
+    let config = TokenizerConfig::new().coalesce_text(true).trim_text(true);
+    let tokens: Vec<_> = Tokenizer::with_config(config.clone())
+        .from_str_reader(input)
+        .collect();
+    assert_eq!(expected, tokens);
+    let mut buffer = vec![];
+    let tokens: Vec<_> = Tokenizer::with_config(config.clone())
+        .from_buf_reader(BufReader::new(input.as_bytes()), &mut buffer)
+        .collect();
+    assert_eq!(expected, tokens);
+    let mut push = Tokenizer::with_config(config).into_push();
+    let mut tokens = vec![];
+    for byte in input.bytes() {
+        push.feed(&[byte]);
+        tokens.extend(&mut push);
+    }
+    push.finish();
+    tokens.extend(&mut push);
+    assert_eq!(expected, tokens);
+
+    // recovered text is merged too, and errors found in it come after it
+    let input = "1 < 2 &bogus; 3<a/>";
+    let config = TokenizerConfig::new().coalesce_text(true);
+    let tokens: Vec<_> = Tokenizer::with_config(config.clone())
+        .from_str_reader(input)
+        .collect();
+    assert_eq!(
+        vec![
+            text("1 < 2 &bogus; 3"),
+            Token::error(Xml5Error::UnexpectedSymbolOrEof(Some(b' '))),
+            Token::error(Xml5Error::UnknownNamedCharRef),
+            Token::empty_tag(Cow::Borrowed(b"a"), vec![]),
+            Token::eof()
+        ],
+        tokens
+    );
+    let spans: Vec<_> = tokens.iter().map(|token| token.span().end.offset).collect();
+    assert_eq!(vec![15, 3, 13, 19, 19], spans);
+
+    // text read in one piece is still borrowed from the input
+    let input = "<a>x ]]> y</a>";
+    let tokens: Vec<_> = Tokenizer::with_config(config)
+        .from_str_reader(input)
+        .collect();
     assert_eq!(
-        "xml test",
-        str::from_utf8(next.as_ref().unwrap().get_declaration().unwrap()).unwrap()
+        vec![start("a"), text("x ]]> y"), end("a"), Token::eof()],
+        tokens
     );
+    match &tokens[1] {
+        Token::Text(text) => assert_eq!(input[3..].as_ptr(), text.as_ptr()),
+        token => panic!("expected text, got {:?}", token),
+    }
 }
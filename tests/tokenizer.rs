@@ -0,0 +1,298 @@
+//! Conformance tests of the tokenizer, read from the JSON files in
+//! `tests/fixtures/tokenizer`. They are in the format of the html5lib and
+//! xml5ever tokenizer tests:
+//!
+//! ```json
+//! {"tests": [{
+//!     "description": "unclosed start tag",
+//!     "input": "<a x='1'",
+//!     "output": [["StartTag", "a", {"x": "1"}]],
+//!     "errors": [{"code": "eof-in-tag"}],
+//!     "initialStates": ["Data"]
+//! }]}
+//! ```
+//!
+//! Adjacent `Character` tokens are merged, and `Eof` is left out. Errors are
+//! the names of the `Xml5Error` variants in kebab case, optionally with the
+//! `line` and `col` they were found at. Every test runs once for each of its
+//! initial states, only `Data` if there are none. The states the tokenizer
+//! goes through are tallied, and every state has to be covered by some test.
+
+extern crate xml5;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use serde_json::{json, Map, Value};
+use xml5::{Span, Token, TokenState, Tokenizer};
+
+/// Every state of the tokenizer, without the data they carry.
+const STATES: &[&str] = &[
+    "Data",
+    "CharRefInData",
+    "CharRefInAttrValue",
+    "TagOpen",
+    "EndTagOpen",
+    "EndTagName",
+    "EndTagNameAfter",
+    "Pi",
+    "PiTarget",
+    "PiTargetAfter",
+    "PiData",
+    "PiAfter",
+    "XmlDecl",
+    "XmlDeclAttrName",
+    "XmlDeclAttrNameAfter",
+    "XmlDeclAttrValueBefore",
+    "XmlDeclAttrValue",
+    "XmlDeclAfter",
+    "XmlDeclBogus",
+    "MarkupDecl",
+    "CommentStart",
+    "CommentStartDash",
+    "Comment",
+    "CommentLessThan",
+    "CommentLessThanBang",
+    "CommentLessThanBangDash",
+    "CommentLessThanBangDashDash",
+    "CommentEnd",
+    "CommentEndDash",
+    "CommentEndBang",
+    "Cdata",
+    "CdataBracket",
+    "CdataEnd",
+    "BogusComment",
+    "TagName",
+    "EmptyTag",
+    "TagAttrNameBefore",
+    "TagAttrName",
+    "TagAttrNameAfter",
+    "TagAttrValueBefore",
+    "TagAttrValue",
+    "Doctype",
+    "BeforeDoctypeName",
+    "DoctypeName",
+    "AfterDoctypeName",
+    "AfterDoctypeKeyword",
+    "BeforeDoctypeIdentifier",
+    "DoctypeIdentifier",
+    "AfterDoctypeIdentifier",
+    "BetweenDoctypePublicAndSystem",
+    "DoctypeInternalSubset",
+    "DoctypeInternalSubsetQuoted",
+    "DoctypeInternalSubsetMarkup",
+    "DoctypeInternalSubsetComment",
+    "DoctypeInternalSubsetPi",
+    "AfterDoctypeInternalSubset",
+    "BogusDoctype",
+];
+
+fn initial_state(name: &str) -> TokenState {
+    match name {
+        "Data" => TokenState::Data,
+        "Comment" => TokenState::Comment,
+        "Cdata" => TokenState::Cdata,
+        "PiData" => TokenState::PiData,
+        "BogusComment" => TokenState::BogusComment,
+        _ => panic!("unsupported initial state {}", name),
+    }
+}
+
+fn text(bytes: &[u8]) -> Value {
+    Value::String(String::from_utf8_lossy(bytes).into_owned())
+}
+
+fn opt_text(bytes: Option<&[u8]>) -> Value {
+    bytes.map_or(Value::Null, text)
+}
+
+/// Name of the error's variant in kebab case, e.g. `eof-in-tag`.
+fn error_code(debug: &str) -> String {
+    let name = debug.split('(').next().unwrap_or_default();
+    let mut code = String::new();
+    for chr in name.chars() {
+        if chr.is_ascii_uppercase() && !code.is_empty() {
+            code.push('-');
+        }
+        code.push(chr.to_ascii_lowercase());
+    }
+    code
+}
+
+/// Tokens and errors of `input` in the fixtures' format, and the states gone
+/// through.
+fn tokenize(input: &str, state: TokenState) -> (Vec<Value>, Vec<(String, Span)>, Vec<String>) {
+    let mut iter = Tokenizer::new()
+        .initial_state(state)
+        .trace_states()
+        .from_str_reader(input);
+    let mut output = vec![];
+    let mut errors = vec![];
+    for token in &mut iter {
+        let value = match token {
+            Token::Bom(_) => json!(["BOM"]),
+            Token::Text(chars) => {
+                // characters are reported merged
+                let chars = String::from_utf8_lossy(&chars);
+                if let Some(Value::Array(last)) = output.last_mut() {
+                    if last[0] == "Character" {
+                        last[1] = Value::String(format!("{}{}", last[1].as_str().unwrap(), chars));
+                        continue;
+                    }
+                }
+                json!(["Character", chars])
+            }
+            Token::StartTag(ref tag) | Token::EmptyTag(ref tag) => {
+                let mut attrs = Map::new();
+                for (name, value) in &tag.attrs {
+                    attrs
+                        .entry(String::from_utf8_lossy(name).into_owned())
+                        .or_insert_with(|| text(value));
+                }
+                let kind = match token {
+                    Token::EmptyTag(_) => "EmptyTag",
+                    _ => "StartTag",
+                };
+                json!([kind, text(&tag.name), attrs])
+            }
+            Token::EndTag(name) => json!(["EndTag", text(&name)]),
+            Token::Comment(comment) => json!(["Comment", text(&comment)]),
+            Token::CData(cdata) => json!(["CDATA", text(&cdata)]),
+            Token::Declaration(decl) => json!([
+                "XmlDecl",
+                opt_text(decl.get_version()),
+                opt_text(decl.get_encoding()),
+                decl.get_standalone(),
+            ]),
+            Token::PI(pi) => json!(["PI", text(pi.get_target()), text(pi.get_data())]),
+            Token::DocType(doctype) => {
+                let mut value = vec![
+                    json!("DOCTYPE"),
+                    text(doctype.get_name()),
+                    opt_text(doctype.get_public_id()),
+                    opt_text(doctype.get_system_id()),
+                ];
+                if let Some(subset) = doctype.get_internal_subset() {
+                    value.push(text(subset));
+                }
+                Value::Array(value)
+            }
+            Token::Error(err, span) => {
+                errors.push((error_code(&format!("{:?}", err)), span));
+                continue;
+            }
+            Token::Eof(_) => continue,
+        };
+        output.push(value);
+    }
+    let visited = iter.visited_states().unwrap();
+    let visited = visited.iter().cloned().collect();
+    (output, errors, visited)
+}
+
+/// Differences between the expected and actual errors.
+fn check_errors(expected: &[Value], actual: &[(String, Span)]) -> Option<String> {
+    let matches = expected.len() == actual.len()
+        && expected.iter().zip(actual).all(|(expected, (code, span))| {
+            expected["code"] == code.as_str()
+                && expected
+                    .get("line")
+                    .is_none_or(|line| line == span.end.line)
+                && expected.get("col").is_none_or(|col| col == span.end.column)
+        });
+    if matches {
+        return None;
+    }
+    let actual: Vec<_> = actual
+        .iter()
+        .map(|(code, span)| json!({"code": code, "line": span.end.line, "col": span.end.column}))
+        .collect();
+    Some(format!(
+        "expected errors {}\n  actual errors {}",
+        Value::from(expected.to_vec()),
+        Value::from(actual)
+    ))
+}
+
+#[test]
+fn test_tokenizer_fixtures() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/tokenizer");
+    let mut files: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "test"))
+        .collect();
+    files.sort();
+    assert!(!files.is_empty(), "no fixtures in {}", dir.display());
+
+    let mut coverage: BTreeMap<&str, usize> = STATES.iter().map(|&state| (state, 0)).collect();
+    let mut failures = vec![];
+    let mut runs = 0;
+    for file in &files {
+        let fixtures: Value = serde_json::from_str(&fs::read_to_string(file).unwrap())
+            .unwrap_or_else(|err| panic!("{}: {}", file.display(), err));
+        let file_name = file.file_name().unwrap().to_string_lossy();
+        for test in fixtures["tests"].as_array().unwrap() {
+            let description = test["description"].as_str().unwrap();
+            let input = test["input"].as_str().unwrap();
+            let expected = test["output"].as_array().unwrap();
+            let expected_errors = test
+                .get("errors")
+                .and_then(Value::as_array)
+                .cloned()
+                .unwrap_or_default();
+            let states = match test.get("initialStates").and_then(Value::as_array) {
+                Some(states) => states.iter().map(|state| state.as_str().unwrap()).collect(),
+                None => vec!["Data"],
+            };
+            for state in states {
+                runs += 1;
+                let (output, errors, visited) = tokenize(input, initial_state(state));
+                for name in &visited {
+                    match coverage.get_mut(name.as_str()) {
+                        Some(count) => *count += 1,
+                        None => panic!("state {} is missing from STATES", name),
+                    }
+                }
+                let mut problems = vec![];
+                if &output != expected {
+                    problems.push(format!(
+                        "expected output {}\n  actual output {}",
+                        Value::from(expected.clone()),
+                        Value::from(output)
+                    ));
+                }
+                problems.extend(check_errors(&expected_errors, &errors));
+                if !problems.is_empty() {
+                    failures.push(format!(
+                        "{}: {} (initial state {}), input {:?}\n  {}",
+                        file_name,
+                        description,
+                        state,
+                        input,
+                        problems.join("\n  ")
+                    ));
+                }
+            }
+        }
+    }
+
+    println!("{} runs of {} fixture files", runs, files.len());
+    for (state, count) in &coverage {
+        println!("{:>6} {}", count, state);
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} runs failed:\n{}",
+        failures.len(),
+        runs,
+        failures.join("\n")
+    );
+    let uncovered: Vec<_> = coverage
+        .iter()
+        .filter(|(_, &count)| count == 0)
+        .map(|(state, _)| state)
+        .collect();
+    assert!(uncovered.is_empty(), "states not covered: {:?}", uncovered);
+}